@@ -1,10 +1,21 @@
 extern crate rterm;
 
-fn draw_rect(cb: &mut rterm::Terminal, x: i32, y: i32, sz: i32, c: char, clr: rterm::Attribute) {
-    cb.put_horizontal_line_with_attrs(x - sz, y - sz, sz * 2 + 1, c, clr, rterm::COLOR_BLACK);
-    cb.put_horizontal_line_with_attrs(x - sz, y + sz, sz * 2 + 1, c, clr, rterm::COLOR_BLACK);
-    cb.put_vertical_line_with_attrs(x - sz, y - sz + 1, sz * 2 - 1, c, clr, rterm::COLOR_BLACK);
-    cb.put_vertical_line_with_attrs(x + sz, y - sz + 1, sz * 2 - 1, c, clr, rterm::COLOR_BLACK);
+fn rect(x: i32, y: i32, sz: i32) -> rterm::CellRect {
+    rterm::CellRect::with_size(x - sz, y - sz, sz * 2 + 1, sz * 2 + 1)
+}
+
+fn draw_rect(cb: &mut rterm::Terminal, x: i32, y: i32, sz: i32, clr: rterm::Attribute) {
+    // the canvas keeps its own colors, so the terminal colors do not change
+    let mut cv = cb.canvas();
+    cv.set_foreground(clr);
+    cv.draw_frame(&rect(x, y, sz), rterm::LineStyle::Rounded);
+}
+
+fn erase_rect(cb: &mut rterm::Terminal, x: i32, y: i32, sz: i32) {
+    cb.put_horizontal_line(x - sz, y - sz, sz * 2 + 1, ' ');
+    cb.put_horizontal_line(x - sz, y + sz, sz * 2 + 1, ' ');
+    cb.put_vertical_line(x - sz, y - sz + 1, sz * 2 - 1, ' ');
+    cb.put_vertical_line(x + sz, y - sz + 1, sz * 2 - 1, ' ');
 }
 
 fn main() {
@@ -13,7 +24,6 @@ fn main() {
     let mut x: i32 = 3;
     let mut y: i32 = 4;
     let mut sz: i32 = 1;
-    let c: char = '*';
     let mut color = rterm::COLOR_WHITE;
     let mut dragged = false;
 
@@ -30,7 +40,7 @@ fn main() {
     loop {
         cb.put_string(0, 0, "Try dragging rectangle with mouse. ESC to exit DEMO");
         cb.put_string(0, 1, "Arrows - move, wheel/+/- resize, click - change color");
-        draw_rect(&mut cb, x, y, sz, c, color);
+        draw_rect(&mut cb, x, y, sz, color);
         cb.flush();
 
         let (mut x1, mut y1, mut sz1, mut color1) = (x, y, sz, color);
@@ -118,7 +128,7 @@ fn main() {
         }
 
         if sz1 != sz || x1 != x || y1 != y || color1 != color {
-            draw_rect(&mut cb, x, y, sz, ' ', color);
+            erase_rect(&mut cb, x, y, sz);
             x = x1;
             y = y1;
             sz = sz1;
@@ -0,0 +1,169 @@
+//! Text with ANSI escape sequences, e.g. output of a compiler or git. SGR
+//! sequences (`ESC [ ... m`) set colors and styles: 16 and 256 palette
+//! colors, RGB colors, and all styles that `Style` supports. Other escape
+//! sequences are removed. Control characters are removed as well except
+//! TAB that is replaced with a space.
+//!
+//! Attributes are relative to the current attributes of the place where the
+//! text is printed: SGR 0 and "default color" codes restore them
+use canvas::*;
+use cellbuf::*;
+use color::*;
+use common::*;
+use markup::*;
+
+const ESC: char = '\x1b';
+const BEL: char = '\x07';
+
+// Reads a color given as `5;idx` or `2;r;g;b` after SGR code 38, 48, or 58.
+// Returns `None` for invalid colors, the parameters are consumed anyway
+fn read_color<'a, I: Iterator<Item=&'a [u32]>>(sub: &[u32], params: &mut I) -> Option<Attribute> {
+    // colon separated form keeps everything in one parameter:
+    // `38:5:idx`, `38:2:r:g:b`, or `38:2:colorspace:r:g:b`
+    let args: Vec<u32> = if sub.len() > 1 {
+        sub[1..].to_vec()
+    } else {
+        match params.next() {
+            Some(&[5]) => vec![5, params.next().map_or(256, |p| p[0])],
+            Some(&[2]) => {
+                let mut v = vec![2];
+                for _ in 0..3 {
+                    v.push(params.next().map_or(256, |p| p[0]));
+                }
+                v
+            },
+            _ => return None,
+        }
+    };
+
+    match args.as_slice() {
+        [5, idx] if *idx < 256 => Some(palette(*idx as u8)),
+        [2, r, g, b] | [2, _, r, g, b] if *r < 256 && *g < 256 && *b < 256 =>
+            Some(rgb(*r as u8, *g as u8, *b as u8)),
+        _ => None,
+    }
+}
+
+// Applies parameters of one SGR sequence to the current attributes
+fn apply_sgr(attrs: TextAttrs, params: &str) -> TextAttrs {
+    let list: Vec<Vec<u32>> = params.split(';')
+        .map(|p| p.split(':').map(|n| n.parse::<u32>().unwrap_or(0)).collect())
+        .collect();
+    let mut attrs = attrs;
+    let mut it = list.iter().map(|v| v.as_slice());
+    while let Some(sub) = it.next() {
+        attrs = match sub[0] {
+            0 => TextAttrs::default(),
+            1 => attrs.with_style(STYLE_BOLD),
+            2 => attrs.with_style(STYLE_DIM),
+            3 => attrs.with_style(STYLE_ITALIC),
+            4 => match sub.get(1) {
+                None | Some(1) => attrs.with_style(STYLE_UNDERLINE),
+                Some(0) => attrs.without_style(STYLE_UNDERLINE_MASK),
+                Some(2) => attrs.with_style(STYLE_DOUBLE_UNDERLINE),
+                Some(3) => attrs.with_style(STYLE_CURLY_UNDERLINE),
+                Some(4) => attrs.with_style(STYLE_DOTTED_UNDERLINE),
+                Some(5) => attrs.with_style(STYLE_DASHED_UNDERLINE),
+                _ => attrs,
+            },
+            5 | 6 => attrs.with_style(STYLE_BLINK),
+            7 => attrs.with_style(STYLE_REVERSE),
+            9 => attrs.with_style(STYLE_STRIKETHROUGH),
+            21 => attrs.with_style(STYLE_DOUBLE_UNDERLINE),
+            22 => attrs.without_style(STYLE_BOLD | STYLE_DIM),
+            23 => attrs.without_style(STYLE_ITALIC),
+            24 => attrs.without_style(STYLE_UNDERLINE_MASK),
+            25 => attrs.without_style(STYLE_BLINK),
+            27 => attrs.without_style(STYLE_REVERSE),
+            29 => attrs.without_style(STYLE_STRIKETHROUGH),
+            53 => attrs.with_style(STYLE_OVERLINE),
+            55 => attrs.without_style(STYLE_OVERLINE),
+            n @ 30..=37 => TextAttrs{fg: Some(palette((n - 30) as u8)), ..attrs},
+            n @ 90..=97 => TextAttrs{fg: Some(palette((n - 90 + 8) as u8)), ..attrs},
+            n @ 40..=47 => TextAttrs{bg: Some(palette((n - 40) as u8)), ..attrs},
+            n @ 100..=107 => TextAttrs{bg: Some(palette((n - 100 + 8) as u8)), ..attrs},
+            39 => TextAttrs{fg: None, ..attrs},
+            49 => TextAttrs{bg: None, ..attrs},
+            59 => TextAttrs{underline_color: None, ..attrs},
+            38 => TextAttrs{fg: read_color(sub, &mut it).or(attrs.fg), ..attrs},
+            48 => TextAttrs{bg: read_color(sub, &mut it).or(attrs.bg), ..attrs},
+            58 => TextAttrs{underline_color: read_color(sub, &mut it).or(attrs.underline_color), ..attrs},
+            _ => attrs,
+        };
+    }
+    attrs
+}
+
+impl StyledText {
+    /// Parses text with ANSI escape sequences, see module description
+    pub fn from_ansi(s: &str) -> StyledText {
+        let mut res = StyledText::new();
+        let mut attrs = TextAttrs::default();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                ESC => match chars.next() {
+                    // CSI: parameters and intermediate bytes end with a final
+                    // byte in range '@'..='~'
+                    Some('[') => {
+                        let mut params = String::new();
+                        let mut fin = None;
+                        for c in chars.by_ref() {
+                            if ('@'..='~').contains(&c) {
+                                fin = Some(c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                        if fin == Some('m') && params.chars().all(|c| c.is_ascii_digit() || c == ';' || c == ':') {
+                            res = res.span(&text, attrs);
+                            text.clear();
+                            attrs = apply_sgr(attrs, &params);
+                        }
+                    },
+                    // OSC, DCS, APC, PM, and SOS: strings that end with ST
+                    // or, for OSC, with BEL
+                    Some(']') | Some('P') | Some('_') | Some('^') | Some('X') => {
+                        while let Some(c) = chars.next() {
+                            if c == BEL {
+                                break;
+                            }
+                            if c == ESC && chars.peek() == Some(&'\\') {
+                                chars.next();
+                                break;
+                            }
+                        }
+                    },
+                    // sequences with intermediate bytes, e.g. charset selection
+                    Some(c) if (' '..='/').contains(&c) => {
+                        chars.next();
+                    },
+                    _ => {},
+                },
+                '\t' => text.push(' '),
+                c if c.is_control() => {},
+                c => text.push(c),
+            }
+        }
+        res.span(&text, attrs)
+    }
+}
+
+impl<'a> Canvas<'a> {
+    /// Puts a string with ANSI escape sequences, see module `ansi`. Returns
+    /// the number of columns the text takes including its clipped part
+    pub fn put_ansi_string(&mut self, x: i32, y: i32, s: &str) -> i32 {
+        self.put_styled(x, y, &StyledText::from_ansi(s))
+    }
+}
+
+impl CellBuf {
+    /// Puts a string with ANSI escape sequences using default attributes,
+    /// see module `ansi`. Returns the number of columns the text takes
+    /// including the part outside the buffer
+    pub fn put_ansi_string(&mut self, x: i32, y: i32, s: &str) -> i32 {
+        Canvas::new(self).put_ansi_string(x, y, s)
+    }
+}
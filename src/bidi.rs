@@ -0,0 +1,224 @@
+//! Bidirectional text. Strings keep characters in logical order: the order
+//! they are typed and read. Right-to-left scripts, e.g. Hebrew and Arabic,
+//! are displayed in the opposite order while numbers and left-to-right words
+//! inside them keep their direction. `BidiLine` reorders a line with the
+//! Unicode Bidirectional Algorithm and maps positions between logical and
+//! visual orders, e.g. to put the cursor at a typed character.
+//!
+//! Reordering is opt-in: `put_string` always prints in logical order, and
+//! `Canvas::put_string_bidi` and text blocks with `TextOptions::direction`
+//! print in visual order
+use canvas::*;
+use cellbuf::*;
+use common::*;
+use unicode_bidi::{BidiInfo, Level};
+use unicode_segmentation::UnicodeSegmentation;
+
+// Pairs of characters that are swapped in right-to-left text
+const MIRRORED: [(char, char); 6] = [
+    ('(', ')'),
+    ('[', ']'),
+    ('{', '}'),
+    ('<', '>'),
+    ('«', '»'),
+    ('‹', '›'),
+];
+
+fn mirror(c: char) -> char {
+    for &(l, r) in MIRRORED.iter() {
+        if c == l {
+            return r;
+        }
+        if c == r {
+            return l;
+        }
+    }
+    c
+}
+
+// Returns logical indices of characters from left to right: every run of
+// characters at some level or higher is reversed, from the highest level
+// down to the lowest odd one
+fn reorder(levels: &[u8]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..levels.len()).collect();
+    let highest = levels.iter().cloned().max().unwrap_or(0);
+    let lowest_odd = levels.iter().cloned().filter(|l| l % 2 == 1).min().unwrap_or(highest + 1);
+    let mut level = highest;
+    while level >= lowest_odd {
+        let mut i = 0;
+        while i < order.len() {
+            if levels[order[i]] < level {
+                i += 1;
+                continue;
+            }
+            let start = i;
+            while i < order.len() && levels[order[i]] >= level {
+                i += 1;
+            }
+            order[start..i].reverse();
+        }
+        level -= 1;
+    }
+    order
+}
+
+/// A line of text in logical order with its visual order. Positions are
+/// indices of grapheme clusters
+#[derive(Debug,Clone,PartialEq)]
+pub struct BidiLine {
+    // grapheme clusters in logical order
+    graphemes: Vec<String>,
+    // embedding levels of grapheme clusters, odd levels are right-to-left
+    levels: Vec<u8>,
+    // logical indices of grapheme clusters from left to right
+    visual: Vec<usize>,
+    // visual positions of grapheme clusters in logical order
+    logical: Vec<usize>,
+    rtl: bool,
+}
+
+impl BidiLine {
+    /// Reorders a line of text. `dir` is one of `BIDI_*` values and sets
+    /// the base direction. `BIDI_OFF` keeps logical order
+    pub fn new(text: &str, dir: TextDirection) -> BidiLine {
+        let graphemes: Vec<(usize, &str)> = text.grapheme_indices(true).collect();
+        // byte ranges of paragraphs, they are reordered separately
+        let (levels, paras, rtl) = if dir == BIDI_OFF {
+            (vec![0; graphemes.len()], vec![(0, text.len())], false)
+        } else {
+            let base = match dir {
+                BIDI_LTR => Some(Level::ltr()),
+                BIDI_RTL => Some(Level::rtl()),
+                _ => None,
+            };
+            let info = BidiInfo::new(text, base);
+            let mut byte_levels = Vec::with_capacity(text.len());
+            for para in &info.paragraphs {
+                // the levels are returned for the whole text, but only the
+                // paragraph ones are reordered
+                let levels = info.reordered_levels(para, para.range.clone());
+                byte_levels.extend_from_slice(&levels[para.range.clone()]);
+            }
+            let rtl = match info.paragraphs.first() {
+                Some(para) => para.level.is_rtl(),
+                None => dir == BIDI_RTL,
+            };
+            let paras = info.paragraphs.iter().map(|p| (p.range.start, p.range.end)).collect();
+            (graphemes.iter().map(|&(i, _)| byte_levels[i].number()).collect(), paras, rtl)
+        };
+
+        let mut visual = Vec::with_capacity(levels.len());
+        for (start, end) in paras {
+            let first = graphemes.iter().position(|&(i, _)| i >= start).unwrap_or(graphemes.len());
+            let last = graphemes.iter().position(|&(i, _)| i >= end).unwrap_or(graphemes.len());
+            visual.extend(reorder(&levels[first..last]).into_iter().map(|idx| idx + first));
+        }
+        let mut logical = vec![0; visual.len()];
+        for (pos, &idx) in visual.iter().enumerate() {
+            logical[idx] = pos;
+        }
+        BidiLine{
+            graphemes: graphemes.into_iter().map(|(_, g)| g.to_string()).collect(),
+            levels,
+            visual,
+            logical,
+            rtl,
+        }
+    }
+
+    /// Returns `true` if the base direction is right-to-left
+    pub fn is_rtl(&self) -> bool {
+        self.rtl
+    }
+
+    /// Returns the number of grapheme clusters
+    pub fn len(&self) -> usize {
+        self.graphemes.len()
+    }
+
+    /// Returns `true` if the line is empty
+    pub fn is_empty(&self) -> bool {
+        self.graphemes.is_empty()
+    }
+
+    /// Returns `true` if a grapheme cluster at a logical position is a part
+    /// of right-to-left text
+    pub fn is_rtl_at(&self, logical: usize) -> bool {
+        self.levels.get(logical).map_or(self.rtl, |l| l % 2 == 1)
+    }
+
+    /// Returns the visual position of a grapheme cluster: its index from the
+    /// left. Positions after the end of the line are not changed
+    pub fn logical_to_visual(&self, logical: usize) -> usize {
+        self.logical.get(logical).cloned().unwrap_or(logical)
+    }
+
+    /// Returns the logical position of a grapheme cluster displayed at a
+    /// visual position. Positions after the end of the line are not changed
+    pub fn visual_to_logical(&self, visual: usize) -> usize {
+        self.visual.get(visual).cloned().unwrap_or(visual)
+    }
+
+    /// Returns the line in visual order. Brackets in right-to-left text are
+    /// mirrored
+    pub fn visual_text(&self) -> String {
+        let mut res = String::new();
+        for &idx in &self.visual {
+            let g = &self.graphemes[idx];
+            let mut chars = g.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if self.levels[idx] % 2 == 1 => res.push(mirror(c)),
+                _ => res.push_str(g),
+            }
+        }
+        res
+    }
+
+    /// Returns the column of the cell that displays a grapheme cluster at a
+    /// logical position. The column is relative to the line start and uses
+    /// the width settings of `buf`. The position after the last grapheme
+    /// cluster is to the right of the line if the base direction is
+    /// left-to-right, and to the left of it, column -1, otherwise
+    pub fn cursor_column(&self, buf: &CellBuf, logical: usize) -> i32 {
+        if logical >= self.len() {
+            return if self.rtl { -1 } else { self.width(buf) };
+        }
+        let pos = self.logical[logical];
+        self.visual[..pos].iter().map(|&idx| buf.grapheme_width(&self.graphemes[idx])).sum()
+    }
+
+    /// Returns the logical position of a grapheme cluster displayed at a
+    /// column, e.g. to move the cursor to a mouse click. Columns outside the
+    /// line map to the line start or end depending on the base direction
+    pub fn logical_at_column(&self, buf: &CellBuf, column: i32) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        if column < 0 {
+            return if self.rtl { self.len() } else { self.visual[0] };
+        }
+        let mut col = 0;
+        for &idx in &self.visual {
+            col += buf.grapheme_width(&self.graphemes[idx]);
+            if column < col {
+                return idx;
+            }
+        }
+        if self.rtl { self.visual[self.len() - 1] } else { self.len() }
+    }
+
+    /// Returns the number of columns the line takes
+    pub fn width(&self, buf: &CellBuf) -> i32 {
+        self.graphemes.iter().map(|g| buf.grapheme_width(g)).sum()
+    }
+}
+
+impl<'a> Canvas<'a> {
+    /// Puts a string in visual order using the current attributes. `dir` is
+    /// one of `BIDI_*` values and sets the base direction of the text, see
+    /// module `bidi`.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string_bidi(&mut self, x: i32, y: i32, s: &str, dir: TextDirection) -> bool {
+        self.put_string(x, y, BidiLine::new(s, dir).visual_text())
+    }
+}
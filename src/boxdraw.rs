@@ -0,0 +1,282 @@
+//! Frames and lines drawn with box drawing characters. Every cell of a line
+//! is a set of strokes going up, right, down, and left from the cell center.
+//! When a line crosses or touches box drawing characters that are already
+//! in the buffer, the strokes are merged and the character for the merged
+//! set is put, so lines join into corners, tees, and crosses
+use canvas::*;
+use cellbuf::*;
+
+/// Line style of frames and lines
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum LineStyle {
+    /// Light lines: ─ │ ┌ ┼
+    Single,
+    /// Double lines: ═ ║ ╔ ╬
+    Double,
+    /// Heavy lines: ━ ┃ ┏ ╋
+    Heavy,
+    /// Light lines with rounded corners: ╭ ╮ ╯ ╰
+    Rounded,
+    /// ASCII characters for terminals without box drawing characters: - | +
+    Ascii,
+}
+
+// Stroke weights
+const NONE: u8 = 0;
+const LIGHT: u8 = 1;
+const HEAVY: u8 = 2;
+const DOUBLE: u8 = 3;
+
+// Strokes of a cell: up, right, down, and left
+type Strokes = [u8; 4];
+const UP: usize = 0;
+const RIGHT: usize = 1;
+const DOWN: usize = 2;
+const LEFT: usize = 3;
+
+static BOX_CHARS: &[(char, Strokes)] = &[
+    ('\u{2500}', [0, 1, 0, 1]), ('\u{2501}', [0, 2, 0, 2]),
+    ('\u{2502}', [1, 0, 1, 0]), ('\u{2503}', [2, 0, 2, 0]),
+    ('\u{250C}', [0, 1, 1, 0]), ('\u{250D}', [0, 2, 1, 0]), ('\u{250E}', [0, 1, 2, 0]), ('\u{250F}', [0, 2, 2, 0]),
+    ('\u{2510}', [0, 0, 1, 1]), ('\u{2511}', [0, 0, 1, 2]), ('\u{2512}', [0, 0, 2, 1]), ('\u{2513}', [0, 0, 2, 2]),
+    ('\u{2514}', [1, 1, 0, 0]), ('\u{2515}', [1, 2, 0, 0]), ('\u{2516}', [2, 1, 0, 0]), ('\u{2517}', [2, 2, 0, 0]),
+    ('\u{2518}', [1, 0, 0, 1]), ('\u{2519}', [1, 0, 0, 2]), ('\u{251A}', [2, 0, 0, 1]), ('\u{251B}', [2, 0, 0, 2]),
+    ('\u{251C}', [1, 1, 1, 0]), ('\u{251D}', [1, 2, 1, 0]), ('\u{251E}', [2, 1, 1, 0]), ('\u{251F}', [1, 1, 2, 0]),
+    ('\u{2520}', [2, 1, 2, 0]), ('\u{2521}', [2, 2, 1, 0]), ('\u{2522}', [1, 2, 2, 0]), ('\u{2523}', [2, 2, 2, 0]),
+    ('\u{2524}', [1, 0, 1, 1]), ('\u{2525}', [1, 0, 1, 2]), ('\u{2526}', [2, 0, 1, 1]), ('\u{2527}', [1, 0, 2, 1]),
+    ('\u{2528}', [2, 0, 2, 1]), ('\u{2529}', [2, 0, 1, 2]), ('\u{252A}', [1, 0, 2, 2]), ('\u{252B}', [2, 0, 2, 2]),
+    ('\u{252C}', [0, 1, 1, 1]), ('\u{252D}', [0, 1, 1, 2]), ('\u{252E}', [0, 2, 1, 1]), ('\u{252F}', [0, 2, 1, 2]),
+    ('\u{2530}', [0, 1, 2, 1]), ('\u{2531}', [0, 1, 2, 2]), ('\u{2532}', [0, 2, 2, 1]), ('\u{2533}', [0, 2, 2, 2]),
+    ('\u{2534}', [1, 1, 0, 1]), ('\u{2535}', [1, 1, 0, 2]), ('\u{2536}', [1, 2, 0, 1]), ('\u{2537}', [1, 2, 0, 2]),
+    ('\u{2538}', [2, 1, 0, 1]), ('\u{2539}', [2, 1, 0, 2]), ('\u{253A}', [2, 2, 0, 1]), ('\u{253B}', [2, 2, 0, 2]),
+    ('\u{253C}', [1, 1, 1, 1]), ('\u{253D}', [1, 1, 1, 2]), ('\u{253E}', [1, 2, 1, 1]), ('\u{253F}', [1, 2, 1, 2]),
+    ('\u{2540}', [2, 1, 1, 1]), ('\u{2541}', [1, 1, 2, 1]), ('\u{2542}', [2, 1, 2, 1]), ('\u{2543}', [2, 1, 1, 2]),
+    ('\u{2544}', [2, 2, 1, 1]), ('\u{2545}', [1, 1, 2, 2]), ('\u{2546}', [1, 2, 2, 1]), ('\u{2547}', [2, 2, 1, 2]),
+    ('\u{2548}', [1, 2, 2, 2]), ('\u{2549}', [2, 1, 2, 2]), ('\u{254A}', [2, 2, 2, 1]), ('\u{254B}', [2, 2, 2, 2]),
+    ('\u{2550}', [0, 3, 0, 3]), ('\u{2551}', [3, 0, 3, 0]),
+    ('\u{2552}', [0, 3, 1, 0]), ('\u{2553}', [0, 1, 3, 0]), ('\u{2554}', [0, 3, 3, 0]),
+    ('\u{2555}', [0, 0, 1, 3]), ('\u{2556}', [0, 0, 3, 1]), ('\u{2557}', [0, 0, 3, 3]),
+    ('\u{2558}', [1, 3, 0, 0]), ('\u{2559}', [3, 1, 0, 0]), ('\u{255A}', [3, 3, 0, 0]),
+    ('\u{255B}', [1, 0, 0, 3]), ('\u{255C}', [3, 0, 0, 1]), ('\u{255D}', [3, 0, 0, 3]),
+    ('\u{255E}', [1, 3, 1, 0]), ('\u{255F}', [3, 1, 3, 0]), ('\u{2560}', [3, 3, 3, 0]),
+    ('\u{2561}', [1, 0, 1, 3]), ('\u{2562}', [3, 0, 3, 1]), ('\u{2563}', [3, 0, 3, 3]),
+    ('\u{2564}', [0, 3, 1, 3]), ('\u{2565}', [0, 1, 3, 1]), ('\u{2566}', [0, 3, 3, 3]),
+    ('\u{2567}', [1, 3, 0, 3]), ('\u{2568}', [3, 1, 0, 1]), ('\u{2569}', [3, 3, 0, 3]),
+    ('\u{256A}', [1, 3, 1, 3]), ('\u{256B}', [3, 1, 3, 1]), ('\u{256C}', [3, 3, 3, 3]),
+    ('\u{2574}', [0, 0, 0, 1]), ('\u{2575}', [1, 0, 0, 0]), ('\u{2576}', [0, 1, 0, 0]), ('\u{2577}', [0, 0, 1, 0]),
+    ('\u{2578}', [0, 0, 0, 2]), ('\u{2579}', [2, 0, 0, 0]), ('\u{257A}', [0, 2, 0, 0]), ('\u{257B}', [0, 0, 2, 0]),
+    ('\u{257C}', [0, 2, 0, 1]), ('\u{257D}', [1, 0, 2, 0]), ('\u{257E}', [0, 1, 0, 2]), ('\u{257F}', [2, 0, 1, 0]),
+];
+
+static ROUNDED_CHARS: &[(char, Strokes)] = &[
+    ('\u{256D}', [0, 1, 1, 0]), ('\u{256E}', [0, 0, 1, 1]), ('\u{256F}', [1, 0, 0, 1]), ('\u{2570}', [1, 1, 0, 0]),
+];
+
+impl LineStyle {
+    fn weight(self) -> u8 {
+        match self {
+            LineStyle::Heavy => HEAVY,
+            LineStyle::Double => DOUBLE,
+            _ => LIGHT,
+        }
+    }
+}
+
+// Returns strokes of a box drawing character of the style class: ASCII
+// lines merge only with ASCII characters, and other lines merge with any
+// box drawing character
+fn strokes_of(c: char, style: LineStyle) -> Option<Strokes> {
+    if style == LineStyle::Ascii {
+        return match c {
+            '-' => Some([NONE, LIGHT, NONE, LIGHT]),
+            '|' => Some([LIGHT, NONE, LIGHT, NONE]),
+            '+' => Some([LIGHT; 4]),
+            _ => None,
+        };
+    }
+    BOX_CHARS.iter().chain(ROUNDED_CHARS.iter()).find(|&&(ch, _)| ch == c).map(|&(_, s)| s)
+}
+
+fn find_char(s: Strokes) -> Option<char> {
+    BOX_CHARS.iter().find(|&&(_, st)| st == s).map(|&(ch, _)| ch)
+}
+
+// Returns the character for a set of strokes. Not all combinations of
+// weights have characters, so the closest one is used: double lines are
+// combined only with light ones
+fn char_of(s: Strokes, style: LineStyle) -> char {
+    if s == [NONE; 4] {
+        return ' ';
+    }
+    match style {
+        LineStyle::Ascii => {
+            let vert = s[UP] != NONE || s[DOWN] != NONE;
+            let horiz = s[LEFT] != NONE || s[RIGHT] != NONE;
+            return if vert && horiz {
+                '+'
+            } else if vert {
+                '|'
+            } else {
+                '-'
+            };
+        },
+        LineStyle::Rounded => {
+            if let Some(&(ch, _)) = ROUNDED_CHARS.iter().find(|&&(_, st)| st == s) {
+                return ch;
+            }
+        },
+        _ => {},
+    }
+
+    if let Some(ch) = find_char(s) {
+        return ch;
+    }
+    let mut t = s;
+    for &(a, b) in &[(UP, DOWN), (LEFT, RIGHT)] {
+        let w = if t[a] == DOUBLE || t[b] == DOUBLE { DOUBLE } else { LIGHT };
+        for &d in &[a, b] {
+            if t[d] != NONE {
+                t[d] = w;
+            }
+        }
+    }
+    if let Some(ch) = find_char(t) {
+        return ch;
+    }
+    for w in &mut t {
+        if *w != NONE {
+            *w = LIGHT;
+        }
+    }
+    find_char(t).unwrap_or(' ')
+}
+
+impl<'a> Canvas<'a> {
+    // Puts strokes to a cell. If the cell has a box drawing character, the
+    // strokes are merged with it. Otherwise `alone` strokes are put: a line
+    // end that does not touch anything goes through the whole cell
+    fn put_strokes(&mut self, x: i32, y: i32, s: Strokes, alone: Strokes, style: LineStyle) {
+        let existing = match self.get_cell(x, y) {
+            Some(c) => strokes_of(c.ch, style),
+            None => return,
+        };
+        let merged = match existing {
+            Some(mut e) => {
+                for (w, &n) in e.iter_mut().zip(s.iter()) {
+                    if n != NONE {
+                        *w = n;
+                    }
+                }
+                e
+            },
+            None => alone,
+        };
+        let ch = char_of(merged, style);
+        self.put_char(x, y, ch);
+    }
+
+    /// Draws a horizontal line with box drawing characters using the
+    /// current attributes. The line joins lines and frames it crosses or
+    /// touches.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn draw_horizontal_line(&mut self, x: i32, y: i32, length: i32, style: LineStyle) -> bool {
+        let vis = self.visible_rect();
+        if length <= 0 || y < vis.top || y > vis.bottom || x > vis.right || x + length - 1 < vis.left {
+            return false;
+        }
+
+        let w = style.weight();
+        let full = [NONE, w, NONE, w];
+        for i in 0..length {
+            let mut s = full;
+            if i == 0 && length > 1 {
+                s[LEFT] = NONE;
+            }
+            if i == length - 1 && length > 1 {
+                s[RIGHT] = NONE;
+            }
+            self.put_strokes(x + i, y, s, full, style);
+        }
+        true
+    }
+
+    /// Draws a vertical line with box drawing characters using the current
+    /// attributes. The line joins lines and frames it crosses or touches.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn draw_vertical_line(&mut self, x: i32, y: i32, length: i32, style: LineStyle) -> bool {
+        let vis = self.visible_rect();
+        if length <= 0 || x < vis.left || x > vis.right || y > vis.bottom || y + length - 1 < vis.top {
+            return false;
+        }
+
+        let w = style.weight();
+        let full = [w, NONE, w, NONE];
+        for i in 0..length {
+            let mut s = full;
+            if i == 0 && length > 1 {
+                s[UP] = NONE;
+            }
+            if i == length - 1 && length > 1 {
+                s[DOWN] = NONE;
+            }
+            self.put_strokes(x, y + i, s, full, style);
+        }
+        true
+    }
+
+    /// Draws a frame along the edges of a rectangle using the current
+    /// attributes. The frame joins lines and frames it crosses or touches,
+    /// so adjacent frames share borders.
+    /// Retuns `false` if the entire frame is outside the visible part
+    pub fn draw_frame(&mut self, rect: &CellRect, style: LineStyle) -> bool {
+        if rect.is_empty() {
+            return false;
+        }
+        if rect.left == rect.right {
+            return self.draw_vertical_line(rect.left, rect.top, rect.bottom - rect.top + 1, style);
+        }
+        if rect.top == rect.bottom {
+            return self.draw_horizontal_line(rect.left, rect.top, rect.right - rect.left + 1, style);
+        }
+
+        let vis = self.visible_rect();
+        if rect.left > vis.right || rect.right < vis.left || rect.top > vis.bottom || rect.bottom < vis.top {
+            return false;
+        }
+
+        let w = style.weight();
+        let (horiz, vert) = ([NONE, w, NONE, w], [w, NONE, w, NONE]);
+        for x in rect.left + 1..rect.right {
+            self.put_strokes(x, rect.top, horiz, horiz, style);
+            self.put_strokes(x, rect.bottom, horiz, horiz, style);
+        }
+        for y in rect.top + 1..rect.bottom {
+            self.put_strokes(rect.left, y, vert, vert, style);
+            self.put_strokes(rect.right, y, vert, vert, style);
+        }
+        let corners = [
+            (rect.left, rect.top, [NONE, w, w, NONE]),
+            (rect.right, rect.top, [NONE, NONE, w, w]),
+            (rect.left, rect.bottom, [w, w, NONE, NONE]),
+            (rect.right, rect.bottom, [w, NONE, NONE, w]),
+        ];
+        for &(x, y, s) in &corners {
+            self.put_strokes(x, y, s, s, style);
+        }
+        true
+    }
+
+    /// Draws a frame with a title in its top border. The title is clipped
+    /// if it is longer than the border.
+    /// Retuns `false` if the entire frame is outside the visible part
+    pub fn draw_frame_with_title(&mut self, rect: &CellRect, style: LineStyle, title: &str) -> bool {
+        if !self.draw_frame(rect, style) {
+            return false;
+        }
+        if !title.is_empty() && rect.right - rect.left > 1 {
+            let border = CellRect{left: rect.left + 1, right: rect.right - 1, bottom: rect.top, ..rect.clone()};
+            self.region(&border).put_string(1, 0, format!(" {} ", title));
+        }
+        true
+    }
+}
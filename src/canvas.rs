@@ -0,0 +1,377 @@
+//! Drawing context over a part of a buffer. A widget draws in local
+//! coordinates: (0, 0) is the top left corner of its region, and everything
+//! outside the region is clipped. Regions can be nested, and a nested region
+//! is clipped by all its parents
+use std::cmp;
+
+use cellbuf::*;
+use common::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A region of `CellBuf` with its own origin, clip rectangle, and current
+/// attributes
+#[derive(Debug)]
+pub struct Canvas<'a> {
+    buf: &'a mut CellBuf,
+    // buffer coordinates of the local origin
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    // the visible part of the region in buffer coordinates
+    clip: CellRect,
+    fg: Attribute,
+    bg: Attribute,
+    style: Style,
+    link: LinkId,
+}
+
+impl<'a> Canvas<'a> {
+    /// Creates a drawing context that covers the whole buffer and uses
+    /// default attributes
+    pub fn new(buf: &'a mut CellBuf) -> Canvas<'a> {
+        let (width, height) = (buf.width, buf.height);
+        Canvas{
+            buf,
+            x: 0,
+            y: 0,
+            width,
+            height,
+            clip: CellRect::with_size(0, 0, width, height),
+            fg: COLOR_DEFAULT,
+            bg: COLOR_DEFAULT,
+            style: Style::default(),
+            link: NO_LINK,
+        }
+    }
+
+    /// Returns a drawing context for a part of this region. `rect` is in
+    /// local coordinates and may be partially or completely outside the
+    /// region: only the common part is visible. The new region inherits the
+    /// current attributes
+    pub fn region(&mut self, rect: &CellRect) -> Canvas<'_> {
+        let (x, y) = (self.x + rect.left, self.y + rect.top);
+        let (width, height) = (rect.right - rect.left + 1, rect.bottom - rect.top + 1);
+        let clip = CellRect{
+            left: cmp::max(self.clip.left, x),
+            top: cmp::max(self.clip.top, y),
+            right: cmp::min(self.clip.right, x + width - 1),
+            bottom: cmp::min(self.clip.bottom, y + height - 1),
+        };
+        Canvas{
+            buf: &mut *self.buf,
+            x,
+            y,
+            width,
+            height,
+            clip,
+            fg: self.fg,
+            bg: self.bg,
+            style: self.style,
+            link: self.link,
+        }
+    }
+
+    /// Returns the region size
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the visible part of the region in local coordinates. The
+    /// rectangle is empty if nothing is visible
+    pub fn visible_rect(&self) -> CellRect {
+        CellRect{
+            left: self.clip.left - self.x,
+            top: self.clip.top - self.y,
+            right: self.clip.right - self.x,
+            bottom: self.clip.bottom - self.y,
+        }
+    }
+
+    /// Sets the current text color
+    pub fn set_foreground(&mut self, c: Attribute) {
+        self.fg = c;
+    }
+
+    /// Sets the current background color
+    pub fn set_background(&mut self, c: Attribute) {
+        self.bg = c;
+    }
+
+    /// Sets the current text style
+    pub fn set_style(&mut self, st: Style) {
+        self.style = st;
+    }
+
+    /// Sets the hyperlink of the cells drawn after the call, see
+    /// `CellBuf::add_link`
+    pub fn set_link(&mut self, link: LinkId) {
+        self.link = link;
+    }
+
+    /// Returns the current text color
+    pub fn get_foreground(&self) -> Attribute {
+        self.fg
+    }
+
+    /// Returns the current background color
+    pub fn get_background(&self) -> Attribute {
+        self.bg
+    }
+
+    /// Returns the current text style
+    pub fn get_style(&self) -> Style {
+        self.style
+    }
+
+    /// Returns the number of columns a string takes when it is printed
+    pub fn text_width(&self, s: &str) -> i32 {
+        self.buf.text_width(s)
+    }
+
+    /// Returns the buffer the region draws on, e.g. to measure text with
+    /// its width settings
+    pub fn buffer(&self) -> &CellBuf {
+        self.buf
+    }
+
+    // Returns `true` if a cell in buffer coordinates is visible
+    fn visible(&self, x: i32, y: i32) -> bool {
+        x >= self.clip.left && x <= self.clip.right && y >= self.clip.top && y <= self.clip.bottom
+    }
+
+    // Returns a cell with the current attributes
+    fn cell(&self, ch: char, combining: Combining) -> Cell {
+        Cell{ch, fg: self.fg, bg: self.bg, style: self.style, link: self.link, combining}
+    }
+
+    // Puts a cell at buffer coordinates. A wide character that does not fit
+    // the visible part is replaced with a space. If the cell overwrites a
+    // half of a wide character crossing the clip edge, the other half
+    // outside becomes a space
+    fn put_cell(&mut self, x: i32, y: i32, c: Cell) -> bool {
+        let mut c = c;
+        if x == self.clip.right && self.buf.cell_width(&c) == 2 {
+            c.ch = ' ';
+            c.combining = Combining::None;
+        }
+        self.buf.set_cell(x, y, c)
+    }
+
+    /// Returns a cell in local coordinates or `None` if it is not visible
+    pub fn get_cell(&self, x: i32, y: i32) -> Option<Cell> {
+        let (ax, ay) = (self.x + x, self.y + y);
+        if !self.visible(ax, ay) {
+            return None;
+        }
+        self.buf.get_cell(ax, ay)
+    }
+
+    /// Sets a cell in local coordinates.
+    /// Retuns `false` if the cell is not visible
+    pub fn set_cell(&mut self, x: i32, y: i32, c: Cell) -> bool {
+        let (ax, ay) = (self.x + x, self.y + y);
+        if !self.visible(ax, ay) {
+            return false;
+        }
+        self.put_cell(ax, ay, c)
+    }
+
+    /// Puts a character with the current attributes.
+    /// Retuns `false` if the cell is not visible
+    pub fn put_char(&mut self, x: i32, y: i32, c: char) -> bool {
+        let cell = self.cell(c, Combining::None);
+        self.set_cell(x, y, cell)
+    }
+
+    /// Sets attributes temporarily and puts a character.
+    /// Retuns `false` if the cell is not visible
+    pub fn put_char_with_attrs(&mut self, x: i32, y: i32, c: char, fg: Attribute, bg: Attribute) -> bool {
+        let cell = Cell{fg, bg, ..self.cell(c, Combining::None)};
+        self.set_cell(x, y, cell)
+    }
+
+    /// Puts a string using the current attributes. The string is split into
+    /// grapheme clusters, and wide ones take two columns.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string<S: Into<String> >(&mut self, x: i32, y: i32, s: S) -> bool {
+        let (ax, ay) = (self.x + x, self.y + y);
+        if ay < self.clip.top || ay > self.clip.bottom || ax > self.clip.right {
+            return false
+        }
+
+        let string = s.into();
+        if ax + self.buf.text_width(&string) <= self.clip.left {
+            return false;
+        }
+
+        let mut pos = ax;
+        for g in string.graphemes(true) {
+            if pos > self.clip.right {
+                break;
+            }
+            let w = self.buf.grapheme_width(g);
+            if w == 0 {
+                continue;
+            }
+            if pos >= self.clip.left {
+                let (ch, combining) = self.buf.add_grapheme(g);
+                let cell = self.cell(ch, combining);
+                self.put_cell(pos, ay, cell);
+            } else if pos + w > self.clip.left {
+                // only the second half of a wide character is visible
+                let cell = self.cell(' ', Combining::None);
+                self.put_cell(self.clip.left, ay, cell);
+            }
+            pos += w;
+        }
+
+        true
+    }
+
+    /// Sets new attributes temporarily and puts a string.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string_with_attrs<S: Into<String> >(&mut self, x: i32, y: i32, s: S, fg: Attribute, bg: Attribute) -> bool {
+        let (f_save, b_save) = (self.fg, self.bg);
+        self.fg = fg;
+        self.bg = bg;
+        let res = self.put_string(x, y, s);
+        self.bg = b_save;
+        self.fg = f_save;
+        res
+    }
+
+    /// Puts a string using the current attributes and makes the string a
+    /// hyperlink to `url`.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string_with_link<S: Into<String> >(&mut self, x: i32, y: i32, s: S, url: &str) -> bool {
+        let link_save = self.link;
+        self.link = self.buf.add_link(url);
+        let res = self.put_string(x, y, s);
+        self.link = link_save;
+        res
+    }
+
+    /// Puts a string from top to bottom using the current attributes. Wide
+    /// characters take two columns, so the column to the right of the string
+    /// may be changed as well.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string_vertical<S: Into<String> >(&mut self, x: i32, y: i32, s: S) -> bool {
+        let (ax, ay) = (self.x + x, self.y + y);
+        if ax < self.clip.left || ax > self.clip.right || ay > self.clip.bottom {
+            return false
+        }
+
+        let string = s.into();
+        let height = string.graphemes(true).filter(|g| self.buf.grapheme_width(g) != 0).count() as i32;
+        if ay + height <= self.clip.top {
+            return false;
+        }
+
+        let mut pos = ay;
+        for g in string.graphemes(true) {
+            if pos > self.clip.bottom {
+                break;
+            }
+            if self.buf.grapheme_width(g) == 0 {
+                continue;
+            }
+            if pos >= self.clip.top {
+                let (ch, combining) = self.buf.add_grapheme(g);
+                let cell = self.cell(ch, combining);
+                self.put_cell(ax, pos, cell);
+            }
+            pos += 1;
+        }
+
+        true
+    }
+
+    /// Temporarily changes the current attributes and puts a string from
+    /// top to bottom.
+    /// Retuns `false` if the entire string is outside the visible part
+    pub fn put_string_vertical_with_attrs<S: Into<String> >(&mut self, x: i32, y: i32, s: S, fg: Attribute, bg: Attribute) -> bool {
+        let (f_save, b_save) = (self.fg, self.bg);
+        self.fg = fg;
+        self.bg = bg;
+        let res = self.put_string_vertical(x, y, s);
+        self.bg = b_save;
+        self.fg = f_save;
+        res
+    }
+
+    /// Puts a horizontal line of character `c` using the current attributes.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn put_horizontal_line(&mut self, x: i32, y: i32, length: i32, c: char) -> bool {
+        let (ax, ay) = (self.x + x, self.y + y);
+        let left = cmp::max(ax, self.clip.left);
+        let right = cmp::min(ax + length - 1, self.clip.right);
+        if ay < self.clip.top || ay > self.clip.bottom || left > right {
+            return false;
+        }
+
+        let cell = self.cell(c, Combining::None);
+        let step = cmp::max(self.buf.cell_width(&cell), 1);
+        let mut pos = left;
+        while pos <= right {
+            self.put_cell(pos, ay, cell.clone());
+            pos += step;
+        }
+
+        true
+    }
+
+    /// Puts a horizontal line of character `c`. The attributes are changed
+    /// temporarily and restored after the function finishes.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn put_horizontal_line_with_attrs(&mut self, x: i32, y: i32, length: i32,
+                                c: char, fg: Attribute, bg: Attribute) -> bool {
+        let (f_save, b_save) = (self.fg, self.bg);
+        self.fg = fg;
+        self.bg = bg;
+        let res = self.put_horizontal_line(x, y, length, c);
+        self.bg = b_save;
+        self.fg = f_save;
+        res
+    }
+
+    /// Puts a vertical line of character `c` using the current attributes.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn put_vertical_line(&mut self, x: i32, y: i32, length: i32, c: char) -> bool {
+        let (ax, ay) = (self.x + x, self.y + y);
+        let top = cmp::max(ay, self.clip.top);
+        let bottom = cmp::min(ay + length - 1, self.clip.bottom);
+        if ax < self.clip.left || ax > self.clip.right || top > bottom {
+            return false;
+        }
+
+        let cell = self.cell(c, Combining::None);
+        for pos in top..bottom + 1 {
+            self.put_cell(ax, pos, cell.clone());
+        }
+
+        true
+    }
+
+    /// Puts a vertical line of character `c`. The attributes are changed
+    /// temporarily and restored after the function finishes.
+    /// Retuns `false` if the entire line is outside the visible part
+    pub fn put_vertical_line_with_attrs(&mut self, x: i32, y: i32, length: i32,
+                                c: char, fg: Attribute, bg: Attribute) -> bool {
+        let (f_save, b_save) = (self.fg, self.bg);
+        self.fg = fg;
+        self.bg = bg;
+        let res = self.put_vertical_line(x, y, length, c);
+        self.bg = b_save;
+        self.fg = f_save;
+        res
+    }
+
+    /// Fills the visible part of the region with character `c` using the
+    /// current attributes
+    pub fn fill(&mut self, c: char) {
+        let cell = self.cell(c, Combining::None);
+        let clip = self.clip.clone();
+        self.buf.fill_rect(&clip, cell);
+    }
+}
@@ -1,13 +1,28 @@
 use common::*;
+use image::*;
 use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::mem;
+use unicode_segmentation::UnicodeSegmentation;
 
 const DEFAULT_FG: Attribute = COLOR_DEFAULT;
 const DEFAULT_BG: Attribute = COLOR_DEFAULT;
+// Detected scroll must move at least this number of rows
+const MIN_SCROLL_ROWS: i32 = 3;
+// Changed cells of a row separated by fewer unchanged cells are sent
+// together: moving the cursor over the gap costs about the same
+const DAMAGE_GAP: i32 = 4;
+// The number of changed runs of a row, more runs are joined
+const MAX_ROW_SPANS: usize = 4;
+
+/// Marks the end of truncated text
+pub const ELLIPSIS: &str = "…";
 
 /// Structure `CellRect` is a simple structure to keep information about
-/// arbitrary rectange. Used internally in `CellBuf`
-#[derive(Debug,Clone)]
+/// arbitrary rectange
+#[derive(Debug,Clone,PartialEq)]
 pub struct CellRect {
     pub left: i32,
     pub top: i32,
@@ -23,6 +38,55 @@ impl CellRect {
             bottom: -1,
         }
     }
+
+    /// Creates a rectangle from its position and size
+    pub fn with_size(left: i32, top: i32, width: i32, height: i32) -> Self {
+        CellRect {
+            left,
+            top,
+            right: left + width - 1,
+            bottom: top + height - 1,
+        }
+    }
+
+    /// Returns `true` if the rectangle does not contain any cell
+    pub fn is_empty(&self) -> bool {
+        self.left > self.right || self.top > self.bottom
+    }
+}
+
+/// Changed cells of a buffer row: columns from `left` to `right` inclusive.
+/// `left` is -1 if the row has not changed
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Span {
+    pub y: i32,
+    pub left: i32,
+    pub right: i32,
+}
+
+impl Span {
+    /// Creates an empty span for a row
+    pub fn new(y: i32) -> Self {
+        Span {
+            y,
+            left: -1,
+            right: -1,
+        }
+    }
+
+    /// Returns `true` if the span does not contain any cell
+    pub fn is_empty(&self) -> bool {
+        self.left == -1
+    }
+}
+
+/// Rows from `top` to `bottom` inclusive that moved up by `count` rows or
+/// down if `count` is negative. Rows that appear at the other side are blank
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct Scroll {
+    pub top: i32,
+    pub bottom: i32,
+    pub count: i32,
 }
 
 /// `CellBuf` provides a temporary buffer to generate a picture before
@@ -43,8 +107,32 @@ pub struct CellBuf {
     /// The only exception: `Clear` makes all buffer invalid not depending on
     /// how many cells were changed
     pub dirty: bool,
-    /// The current 'dirty' rectange tat is sent to real terminal after calling `flush`
+    /// The bounding rectangle of changed cells, all -1 if nothing has
+    /// changed. `dirty_spans` tells which cells of the rectangle changed
     pub dirty_rect: CellRect,
+    /// Changed cells of every row that are sent to real terminal after
+    /// calling `flush`: sorted runs that do not overlap
+    damage: Vec<Vec<Span>>,
+    /// Scrolls made since the last flush, in order
+    scrolls: Vec<Scroll>,
+    /// Hyperlink targets by link ID. IDs are never reused, so a cell of
+    /// another buffer with the same ID, e.g. the front buffer, is the same
+    /// link or a link that does not exist anymore
+    links: HashMap<LinkId, String>,
+    link_ids: HashMap<String, LinkId>,
+    next_link: LinkId,
+    /// Grapheme clusters too long to keep in a cell by the ID that
+    /// `Combining::Interned` keeps. IDs are never reused as link IDs are
+    graphemes: HashMap<ClusterId, String>,
+    grapheme_ids: HashMap<String, ClusterId>,
+    next_grapheme: ClusterId,
+    /// `true` if East Asian characters of ambiguous width take two columns
+    ambiguous_wide: bool,
+    /// Images that reserve cells, see module `image`
+    images: Vec<ImagePlacement>,
+    /// Images removed since the last flush
+    removed_images: Vec<ImagePlacement>,
+    next_image_id: ImageId,
 }
 
 impl CellBuf {
@@ -57,13 +145,210 @@ impl CellBuf {
                 Cell{
                     ch: ' ',
                     bg: DEFAULT_BG,
-                    fg: DEFAULT_FG
+                    fg: DEFAULT_FG,
+                    style: Style::default(),
+                    link: NO_LINK,
+                    combining: Combining::None,
                 };
                 (height * width) as usize
             ],
             dirty: false,
             dirty_rect: CellRect::new(),
+            damage: vec![Vec::new(); cmp::max(height, 0) as usize],
+            scrolls: Vec::new(),
+            links: HashMap::new(),
+            link_ids: HashMap::new(),
+            next_link: NO_LINK + 1,
+            graphemes: HashMap::new(),
+            grapheme_ids: HashMap::new(),
+            next_grapheme: 0,
+            ambiguous_wide: false,
+            images: Vec::new(),
+            removed_images: Vec::new(),
+            next_image_id: 1,
+        }
+    }
+
+    /// Registers a hyperlink target and returns its ID to use in `Cell`.
+    /// The same URL gets the same ID while any cell uses it. Control
+    /// characters are removed from the URL because they cannot be sent to a
+    /// terminal. Links that no cell uses are dropped by `clear` and when
+    /// there are more links than cells, see `compact`
+    pub fn add_link(&mut self, url: &str) -> LinkId {
+        let url: String = url.chars().filter(|c| !c.is_control()).collect();
+        if let Some(id) = self.link_ids.get(&url) {
+            return *id;
+        }
+        if self.links.len() >= self.cells.len() {
+            self.compact();
+        }
+        let id = self.next_link;
+        self.next_link += 1;
+        self.links.insert(id, url.clone());
+        self.link_ids.insert(url, id);
+        id
+    }
+
+    /// Returns URL of a hyperlink or `None` if the link is not registered
+    pub fn get_link(&self, id: LinkId) -> Option<&str> {
+        self.links.get(&id).map(|url| url.as_str())
+    }
+
+    /// Drops registered hyperlinks and grapheme clusters that no cell uses.
+    /// IDs of the others do not change
+    pub fn compact(&mut self) {
+        let links: HashSet<LinkId> = self.cells.iter().map(|c| c.link).collect();
+        self.links.retain(|id, _| links.contains(id));
+        self.link_ids.retain(|_, id| links.contains(id));
+
+        let clusters: HashSet<ClusterId> = self.cells.iter().filter_map(|c| match c.combining {
+            Combining::Interned(id, _) => Some(id),
+            _ => None,
+        }).collect();
+        self.graphemes.retain(|id, _| clusters.contains(id));
+        self.grapheme_ids.retain(|_, id| clusters.contains(id));
+    }
+
+    /// Sets whether East Asian characters of ambiguous width take one or two
+    /// columns. Cells that are already in the buffer are not changed
+    pub fn set_ambiguous_wide(&mut self, wide: bool) {
+        self.ambiguous_wide = wide;
+    }
+
+    /// Returns `true` if East Asian characters of ambiguous width take two
+    /// columns
+    pub fn ambiguous_wide(&self) -> bool {
+        self.ambiguous_wide
+    }
+
+    /// Returns the number of columns a cell takes with the current
+    /// ambiguous width setting
+    pub fn cell_width(&self, c: &Cell) -> i32 {
+        if self.ambiguous_wide {
+            c.width_cjk()
+        } else {
+            c.width()
+        }
+    }
+
+    /// Returns the number of columns a grapheme cluster takes with the
+    /// current ambiguous width setting
+    pub fn grapheme_width(&self, g: &str) -> i32 {
+        if self.ambiguous_wide {
+            grapheme_width_cjk(g)
+        } else {
+            grapheme_width(g)
+        }
+    }
+
+    /// Returns the number of columns a string takes when it is printed with
+    /// `put_string`
+    pub fn text_width(&self, s: &str) -> i32 {
+        s.graphemes(true).map(|g| self.grapheme_width(g)).sum()
+    }
+
+    /// Splits a string into the part that takes at most `column` columns and
+    /// the rest. A wide character that crosses the column goes to the rest
+    pub fn split_at_column<'b>(&self, s: &'b str, column: i32) -> (&'b str, &'b str) {
+        let mut w = 0;
+        for (i, g) in s.grapheme_indices(true) {
+            w += self.grapheme_width(g);
+            if w > column {
+                return (&s[..i], &s[i..]);
+            }
+        }
+        (s, "")
+    }
+
+    /// Returns the beginning of a string that takes at most `width` columns.
+    /// When `put_string` prints a string into a region of `width` columns,
+    /// the region shows exactly this part: a wide character that does not
+    /// fit the last column is replaced with a space
+    pub fn truncate<'b>(&self, s: &'b str, width: i32) -> &'b str {
+        self.split_at_column(s, width).0
+    }
+
+    /// Returns a string that takes at most `width` columns. If the string is
+    /// wider, its end is replaced with `…`
+    pub fn truncate_with_ellipsis(&self, s: &str, width: i32) -> String {
+        let (head, tail) = self.split_at_column(s, width);
+        if tail.is_empty() {
+            return head.to_string();
+        }
+        let ew = self.text_width(ELLIPSIS);
+        if ew > width {
+            return self.truncate(s, width).to_string();
+        }
+        let mut res = self.truncate(head, width - ew).to_string();
+        res.push_str(ELLIPSIS);
+        res
+    }
+
+    /// Returns a string that takes exactly `width` columns: a longer string
+    /// is truncated, a shorter one is padded with spaces. `align` is one of
+    /// `ALIGN_LEFT`, `ALIGN_CENTER`, or `ALIGN_RIGHT` and sets on which side
+    /// the text is
+    pub fn pad(&self, s: &str, width: i32, align: Align) -> String {
+        let s = self.truncate(s, width);
+        let space = cmp::max(width, 0) - self.text_width(s);
+        let left = match align {
+            ALIGN_CENTER => space / 2,
+            ALIGN_RIGHT => space,
+            _ => 0,
+        };
+        let mut res: String = (0..left).map(|_| ' ').collect();
+        res.push_str(s);
+        res.extend((0..space - left).map(|_| ' '));
+        res
+    }
+
+    /// Splits a grapheme cluster into the first character and the rest to
+    /// put into `Cell`. Long clusters are stored in the buffer, the same
+    /// cluster is stored only once. Like links, clusters that no cell uses
+    /// are dropped by `clear` and when there are more of them than cells
+    pub fn add_grapheme(&mut self, g: &str) -> (char, Combining) {
+        let mut chars = g.chars();
+        let first = chars.next().unwrap_or(' ');
+        let rest: Vec<char> = chars.collect();
+        let combining = match rest.len() {
+            0 => Combining::None,
+            1 => Combining::Inline([rest[0], '\0']),
+            2 => Combining::Inline([rest[0], rest[1]]),
+            _ => {
+                let id = match self.grapheme_ids.get(g) {
+                    Some(id) => *id,
+                    None => {
+                        if self.graphemes.len() >= self.cells.len() {
+                            self.compact();
+                        }
+                        let id = self.next_grapheme;
+                        self.next_grapheme += 1;
+                        self.graphemes.insert(id, g.to_string());
+                        self.grapheme_ids.insert(g.to_string(), id);
+                        id
+                    },
+                };
+                Combining::Interned(id, self.grapheme_width(g) == 2)
+            },
+        };
+        (first, combining)
+    }
+
+    /// Returns the whole grapheme cluster of a cell
+    pub fn get_grapheme(&self, c: &Cell) -> String {
+        let mut s = String::new();
+        match c.combining {
+            Combining::None => s.push(c.ch),
+            Combining::Inline(chars) => {
+                s.push(c.ch);
+                s.extend(chars.iter().filter(|&&ch| ch != '\0'));
+            },
+            Combining::Interned(id, _) => match self.graphemes.get(&id) {
+                Some(g) => s.push_str(g),
+                None => s.push(c.ch),
+            },
         }
+        s
     }
 
     /// Clears the bufffer by filling it with default colors and space character.
@@ -73,14 +358,13 @@ impl CellBuf {
             c.ch = ' ';
             c.bg = COLOR_DEFAULT;
             c.fg = COLOR_DEFAULT;
+            c.style = Style::default();
+            c.link = NO_LINK;
+            c.combining = Combining::None;
         }
-        self.dirty_rect = CellRect{
-            left: 0,
-            top: 0,
-            right: self.width - 1,
-            bottom: self.height - 1,
-        };
-        self.dirty = true;
+        self.removed_images.append(&mut self.images);
+        self.compact();
+        self.invalidate();
     }
 
     /// Changes buffer dimensions. Used when terminal is resized.
@@ -96,7 +380,10 @@ impl CellBuf {
             Cell{
                 ch: ' ',
                 bg: COLOR_DEFAULT,
-                fg: COLOR_DEFAULT
+                fg: COLOR_DEFAULT,
+                style: Style::default(),
+                link: NO_LINK,
+                combining: Combining::None,
             };
             (height * width) as usize
         ];
@@ -110,18 +397,226 @@ impl CellBuf {
                 let old_idx: usize = (y * self.width + x) as usize;
                 newvec[new_idx] = self.cells[old_idx].clone();
             }
+            // a wide character cut by the new right edge loses its
+            // continuation cell
+            if width < self.width && minw > 0 {
+                let idx = (y * width + minw - 1) as usize;
+                if self.cells[(y * self.width + minw) as usize].is_continuation() {
+                    newvec[idx].ch = ' ';
+                    newvec[idx].combining = Combining::None;
+                }
+            }
         }
 
         mem::swap(&mut self.cells, &mut newvec);
         self.width = width;
         self.height = height;
+        self.invalidate();
+    }
+
+    /// Marks the entire buffer changed, so it is sent to terminal completely
+    /// on the next flush
+    pub fn invalidate(&mut self) {
+        let width = self.width;
+        self.damage = (0..self.height).map(|y| vec![Span{y, left: 0, right: width - 1}]).collect();
+        self.scrolls.clear();
         self.dirty = true;
-        self.dirty_rect = CellRect{
-            left: 0,
-            top: 0,
-            right: width - 1,
-            bottom: height - 1,
+        self.dirty_rect = if self.width > 0 && self.height > 0 {
+            CellRect::with_size(0, 0, self.width, self.height)
+        } else {
+            CellRect::new()
+        };
+    }
+
+    /// Marks rows from `top` to `bottom` inclusive changed completely
+    pub fn invalidate_rows(&mut self, top: i32, bottom: i32) {
+        let top = cmp::max(top, 0);
+        let bottom = cmp::min(bottom, self.height - 1);
+        for y in top..bottom + 1 {
+            self.damage[y as usize].clear();
+            self.add_damage(y, 0, self.width - 1);
+        }
+    }
+
+    /// Moves rows from `top` to `bottom` inclusive up by `count` rows, or
+    /// down if `count` is negative. Rows that appear at the other side of the
+    /// area are filled with spaces and default attributes. A terminal that
+    /// supports scrolling moves the rows on its side on the next flush, so
+    /// only the new rows and the rows changed after the last flush are sent
+    pub fn scroll(&mut self, top: i32, bottom: i32, count: i32) {
+        let top = cmp::max(top, 0);
+        let bottom = cmp::min(bottom, self.height - 1);
+        if count == 0 || top > bottom {
+            return;
+        }
+
+        let w = self.width as usize;
+        let blank = Cell{
+            ch: ' ',
+            bg: DEFAULT_BG,
+            fg: DEFAULT_FG,
+            style: Style::default(),
+            link: NO_LINK,
+            combining: Combining::None,
+        };
+        if count.abs() > bottom - top {
+            for c in &mut self.cells[top as usize * w..(bottom as usize + 1) * w] {
+                *c = blank.clone();
+            }
+            self.invalidate_rows(top, bottom);
+            return;
+        }
+
+        // rows keep their unsent changes when they move
+        let (first, last) = (top as usize * w, (bottom as usize + 1) * w);
+        let n = count.unsigned_abs() as usize;
+        let (t, b) = (top as usize, bottom as usize);
+        if count > 0 {
+            self.cells[first..last].rotate_left(n * w);
+            self.damage[t..b + 1].rotate_left(n);
+        } else {
+            self.cells[first..last].rotate_right(n * w);
+            self.damage[t..b + 1].rotate_right(n);
+        }
+        for (y, spans) in self.damage.iter_mut().enumerate().skip(t).take(b - t + 1) {
+            for span in spans {
+                span.y = y as i32;
+            }
+        }
+        self.update_dirty_rect();
+
+        let (new_top, new_bottom) = if count > 0 {
+            (bottom - count + 1, bottom)
+        } else {
+            (top, top - count - 1)
         };
+        for c in &mut self.cells[new_top as usize * w..(new_bottom as usize + 1) * w] {
+            *c = blank.clone();
+        }
+        self.invalidate_rows(new_top, new_bottom);
+        self.scrolls.push(Scroll{top, bottom, count});
+    }
+
+    /// Returns scrolls made since the last flush and forgets them
+    pub fn take_scrolls(&mut self) -> Vec<Scroll> {
+        self.scrolls.drain(..).collect()
+    }
+
+    /// Looks for rows of `front` - a buffer that keeps what the terminal
+    /// displays - that moved up or down in this buffer, e.g. when a list
+    /// is scrolled. Returns the scroll that moves the most rows, or `None` if
+    /// no scroll is found
+    pub fn detect_scroll(&self, front: &CellBuf) -> Option<Scroll> {
+        if front.width != self.width || front.height != self.height {
+            return None;
+        }
+
+        // rows are compared by hashes: if different rows have the same hash,
+        // the scroll is not optimal, but the rows are redrawn anyway
+        let w = self.width as usize;
+        let hashes = |b: &CellBuf| -> Vec<u64> {
+            b.cells.chunks(cmp::max(w, 1)).map(|row| {
+                let mut h = DefaultHasher::new();
+                row.hash(&mut h);
+                h.finish()
+            }).collect()
+        };
+        let back_rows = hashes(self);
+        let front_rows = hashes(front);
+        let changed: Vec<usize> = (0..back_rows.len()).filter(|&y| back_rows[y] != front_rows[y]).collect();
+        if changed.len() < MIN_SCROLL_ROWS as usize {
+            return None;
+        }
+
+        // only distances to rows of `front` with the same content are tried
+        let mut front_ids: HashMap<u64, Vec<i32>> = HashMap::new();
+        for (y, h) in front_rows.iter().enumerate() {
+            front_ids.entry(*h).or_default().push(y as i32);
+        }
+        let mut counts: Vec<i32> = Vec::new();
+        for &y in &changed {
+            if let Some(ys) = front_ids.get(&back_rows[y]) {
+                counts.extend(ys.iter().map(|fy| fy - y as i32).filter(|c| *c != 0 && c.abs() <= self.height / 2));
+            }
+        }
+        counts.sort_by_key(|c| (c.abs(), *c < 0));
+        counts.dedup();
+
+        let mut best: Option<(i32, Scroll)> = None;
+        for count in counts {
+            let mut run = 0;
+            for y in 0..self.height + 1 {
+                // the row of this buffer is the row of `front` moved by
+                // `count` and it is not the same as before
+                let fy = y + count;
+                let moved = y < self.height && fy >= 0 && fy < self.height
+                    && back_rows[y as usize] == front_rows[fy as usize]
+                    && back_rows[y as usize] != front_rows[y as usize];
+                if moved {
+                    run += 1;
+                    continue;
+                }
+                if run >= MIN_SCROLL_ROWS && best.map(|(r, _)| run > r) != Some(false) {
+                    let (start, end) = (y - run, y - 1);
+                    let sc = if count > 0 {
+                        Scroll{top: start, bottom: end + count, count}
+                    } else {
+                        Scroll{top: start + count, bottom: end, count}
+                    };
+                    best = Some((run, sc));
+                }
+                run = 0;
+            }
+        }
+        best.map(|(_, sc)| sc)
+    }
+
+    /// Returns changed parts of rows in top to bottom order. Rows without
+    /// changes are skipped
+    pub fn dirty_spans(&self) -> Vec<Span> {
+        self.damage.iter().flat_map(|spans| spans.iter().cloned()).collect()
+    }
+
+    /// Compares the buffer with `front` - a buffer that keeps what the
+    /// terminal displays - and returns runs of different cells in top to
+    /// bottom, left to right order. A row may have a few runs. If the buffers
+    /// have different sizes, all rows are returned completely
+    pub fn diff_spans(&self, front: &CellBuf) -> Vec<Span> {
+        if front.width != self.width || front.height != self.height {
+            let width = self.width;
+            return (0..self.height).map(|y| Span{y, left: 0, right: width - 1}).collect();
+        }
+
+        let mut spans = Vec::new();
+        for y in 0..self.height {
+            let mut span = Span::new(y);
+            for x in 0..self.width {
+                let idx = (x + y * self.width) as usize;
+                if self.cells[idx] != front.cells[idx] {
+                    if span.is_empty() {
+                        span.left = x;
+                    }
+                    span.right = x;
+                } else if !span.is_empty() {
+                    spans.push(span);
+                    span = Span::new(y);
+                }
+            }
+            if !span.is_empty() {
+                spans.push(span);
+            }
+        }
+        spans
+    }
+
+    /// Forgets all changes. Called after the buffer is sent to terminal
+    pub fn clear_damage(&mut self) {
+        for spans in &mut self.damage {
+            spans.clear();
+        }
+        self.scrolls.clear();
+        self.dirty = false;
+        self.dirty_rect = CellRect::new();
     }
 
     /// Returns the buffer `Some<Cell>` value: character and its attributes.
@@ -136,38 +631,440 @@ impl CellBuf {
     }
 
     /// Sets the new value for a buffer cell
-    /// Returns false is cell coordinates are outside the current buffer
+    /// Returns false is cell coordinates are outside the current buffer.
+    /// A wide character takes two cells: the cell itself and a continuation
+    /// cell to the right of it. A wide character that does not fit the line
+    /// is replaced with a space. If the new cell overwrites a half of a wide
+    /// character the other half is replaced with a space
     pub fn set_cell(&mut self, x: i32, y: i32, c: Cell) -> bool {
-        if let Some(old) = self.get_cell(x, y) {
-            if old != c {
-                let idx = (x + y * self.width) as usize;
-                self.cells[idx] = c;
-                self.dirty = true;
-
-                if self.dirty_rect.left == -1 {
-                    self.dirty_rect = CellRect {
-                        left: x,
-                        right: x,
-                        top: y,
-                        bottom: y,
-                    };
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return false
+        }
+
+        let mut c = c;
+        if c.is_continuation() || (self.cell_width(&c) == 2 && x == self.width - 1) {
+            c.ch = ' ';
+            c.combining = Combining::None;
+        }
+        let cont = if self.cell_width(&c) == 2 {
+            Some(Cell{ch: CONTINUATION, combining: Combining::None, ..c.clone()})
+        } else {
+            None
+        };
+
+        let next = self.get_cell(x + 1, y);
+        let same = self.get_cell(x, y).as_ref() == Some(&c)
+            && match cont {
+                Some(_) => next == cont,
+                None => next.map(|n| n.is_continuation()) != Some(true),
+            };
+        if same {
+            return true;
+        }
+
+        self.erase_wide(x, y);
+        if let Some(cc) = cont {
+            self.erase_wide(x + 1, y);
+            self.put_cell(x + 1, y, cc);
+        }
+        self.put_cell(x, y, c);
+        true
+    }
+
+    /// Fills a rectangle with copies of a cell. A wide character takes two
+    /// columns, so if the rectangle width is odd the last column gets a space.
+    /// Wide characters cut by the rectangle edges are replaced with spaces.
+    /// Returns `false` if the rectangle is outside the buffer
+    pub fn fill_rect(&mut self, rect: &CellRect, c: Cell) -> bool {
+        let r = match self.clip_rect(rect) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        let mut c = c;
+        if c.is_continuation() {
+            c.ch = ' ';
+            c.combining = Combining::None;
+        }
+        let w = (r.right - r.left + 1) as usize;
+        let row: Vec<Cell> = if self.cell_width(&c) == 2 {
+            let cont = Cell{ch: CONTINUATION, combining: Combining::None, ..c.clone()};
+            let space = Cell{ch: ' ', combining: Combining::None, ..c.clone()};
+            (0..w).map(|i| {
+                if i % 2 == 1 {
+                    cont.clone()
+                } else if i + 1 < w {
+                    c.clone()
                 } else {
-                    if x < self.dirty_rect.left {
-                        self.dirty_rect.left = x;
-                    } else if x > self.dirty_rect.right {
-                        self.dirty_rect.right = x;
-                    }
-                    if y < self.dirty_rect.top {
-                        self.dirty_rect.top = y;
-                    } else if y > self.dirty_rect.bottom {
-                        self.dirty_rect.bottom = y;
-                    }
+                    space.clone()
                 }
+            }).collect()
+        } else {
+            vec![c; w]
+        };
+
+        for y in r.top..r.bottom + 1 {
+            self.write_row(r.left, y, &row);
+        }
+        true
+    }
+
+    /// Fills a rectangle with spaces and default attributes.
+    /// Returns `false` if the rectangle is outside the buffer
+    pub fn clear_rect(&mut self, rect: &CellRect) -> bool {
+        let blank = Cell{
+            ch: ' ',
+            bg: DEFAULT_BG,
+            fg: DEFAULT_FG,
+            style: Style::default(),
+            link: NO_LINK,
+            combining: Combining::None,
+        };
+        self.fill_rect(rect, blank)
+    }
+
+    /// Copies a rectangle so its top left corner is at `x` and `y`. The
+    /// source and destination may overlap. Parts outside the buffer are
+    /// skipped. Halves of wide characters cut by the source edges become
+    /// spaces. Returns `false` if nothing is copied
+    pub fn copy_rect(&mut self, src: &CellRect, x: i32, y: i32) -> bool {
+        let r = match self.clip_source(self.width, self.height, src, x, y) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        let (dx, dy) = (x - src.left, y - src.top);
+        // rows are copied in the order that does not overwrite source rows
+        // before they are copied
+        let rows: Vec<i32> = if dy > 0 {
+            (r.top..r.bottom + 1).rev().collect()
+        } else {
+            (r.top..r.bottom + 1).collect()
+        };
+        for sy in rows {
+            let row = self.row_part(sy, r.left, r.right);
+            self.write_row(r.left + dx, sy + dy, &row);
+        }
+        true
+    }
+
+    /// Copies a rectangle of another buffer, e.g. a widget drawn offscreen or
+    /// a visible part of a large document, so its top left corner is at `x`
+    /// and `y`. Parts outside the buffer are skipped. Halves of wide
+    /// characters cut by the source edges become spaces.
+    /// Returns `false` if nothing is copied
+    pub fn blit(&mut self, src: &CellBuf, src_rect: &CellRect, x: i32, y: i32) -> bool {
+        self.blit_cells(src, src_rect, x, y, None)
+    }
+
+    /// The same as `blit` but the source cells equal to `key` are
+    /// transparent: they do not overwrite the cells of the buffer
+    pub fn blit_transparent(&mut self, src: &CellBuf, src_rect: &CellRect, x: i32, y: i32, key: &Cell) -> bool {
+        self.blit_cells(src, src_rect, x, y, Some(key))
+    }
+
+    fn blit_cells(&mut self, src: &CellBuf, src_rect: &CellRect, x: i32, y: i32, key: Option<&Cell>) -> bool {
+        let r = match self.clip_source(src.width, src.height, src_rect, x, y) {
+            Some(r) => r,
+            None => return false,
+        };
+
+        let (dx, dy) = (x - src_rect.left, y - src_rect.top);
+        for sy in r.top..r.bottom + 1 {
+            let row = src.row_part(sy, r.left, r.right);
+            // a continuation is copied only together with its character
+            let mut opaque = Vec::with_capacity(row.len());
+            for (i, c) in row.iter().enumerate() {
+                opaque.push(match key {
+                    None => true,
+                    Some(_) if c.is_continuation() => i > 0 && opaque[i - 1],
+                    Some(k) => c != k,
+                });
+            }
+
+            let mut start = 0;
+            while start < row.len() {
+                if !opaque[start] {
+                    start += 1;
+                    continue;
+                }
+                let mut end = start;
+                while end < row.len() && opaque[end] {
+                    end += 1;
+                }
+                let run: Vec<Cell> = row[start..end].iter().map(|c| self.import_cell(src, c)).collect();
+                self.write_row(r.left + dx + start as i32, sy + dy, &run);
+                start = end;
             }
-            true
+        }
+        true
+    }
+
+    // Converts a cell of another buffer: registers its hyperlink and long
+    // grapheme cluster in this buffer
+    fn import_cell(&mut self, src: &CellBuf, c: &Cell) -> Cell {
+        let mut c = c.clone();
+        if c.link != NO_LINK {
+            c.link = match src.get_link(c.link) {
+                Some(url) => self.add_link(url),
+                None => NO_LINK,
+            };
+        }
+        if let Combining::Interned(_, wide) = c.combining {
+            let g = src.get_grapheme(&c);
+            let (ch, combining) = self.add_grapheme(&g);
+            c.ch = ch;
+            c.combining = match combining {
+                Combining::Interned(id, _) => Combining::Interned(id, wide),
+                other => other,
+            };
+        }
+        c
+    }
+
+    /// Moves the content of a rectangle up by `dy` rows and left by `dx`
+    /// columns, or down and right if the values are negative. The area that
+    /// appears at the other side is filled with `fill`. Scrolls of whole
+    /// rows use `scroll`, so a terminal can move them on its side.
+    /// Returns `false` if the rectangle is outside the buffer
+    pub fn scroll_rect(&mut self, rect: &CellRect, dy: i32, dx: i32, fill: Cell) -> bool {
+        let r = match self.clip_rect(rect) {
+            Some(r) => r,
+            None => return false,
+        };
+        if dx == 0 && dy == 0 {
+            return true;
+        }
+
+        let (w, h) = (r.right - r.left + 1, r.bottom - r.top + 1);
+        if dx.abs() >= w || dy.abs() >= h {
+            return self.fill_rect(&r, fill);
+        }
+
+        if dx == 0 && w == self.width {
+            self.scroll(r.top, r.bottom, dy);
         } else {
-            false
+            let src = CellRect{
+                left: r.left + cmp::max(dx, 0),
+                top: r.top + cmp::max(dy, 0),
+                right: r.right + cmp::min(dx, 0),
+                bottom: r.bottom + cmp::min(dy, 0),
+            };
+            self.copy_rect(&src, r.left + cmp::max(-dx, 0), r.top + cmp::max(-dy, 0));
         }
+
+        if dy > 0 {
+            self.fill_rect(&CellRect{top: r.bottom - dy + 1, ..r.clone()}, fill.clone());
+        } else if dy < 0 {
+            self.fill_rect(&CellRect{bottom: r.top - dy - 1, ..r.clone()}, fill.clone());
+        }
+        if dx > 0 {
+            self.fill_rect(&CellRect{left: r.right - dx + 1, ..r.clone()}, fill);
+        } else if dx < 0 {
+            self.fill_rect(&CellRect{right: r.left - dx - 1, ..r.clone()}, fill);
+        }
+        true
+    }
+
+    // Returns the part of `rect` of a `width` x `height` source buffer that
+    // gets inside this buffer when the rectangle top left corner is moved to
+    // `x` and `y`
+    fn clip_source(&self, width: i32, height: i32, rect: &CellRect, x: i32, y: i32) -> Option<CellRect> {
+        let (dx, dy) = (x - rect.left, y - rect.top);
+        let r = CellRect{
+            left: cmp::max(cmp::max(rect.left, -dx), 0),
+            top: cmp::max(cmp::max(rect.top, -dy), 0),
+            right: cmp::min(cmp::min(rect.right, self.width - 1 - dx), width - 1),
+            bottom: cmp::min(cmp::min(rect.bottom, self.height - 1 - dy), height - 1),
+        };
+        if r.is_empty() {
+            None
+        } else {
+            Some(r)
+        }
+    }
+
+    // Returns cells of a row from `left` to `right` inclusive. Halves of wide
+    // characters cut by the edges are replaced with spaces
+    fn row_part(&self, y: i32, left: i32, right: i32) -> Vec<Cell> {
+        let start = (y * self.width) as usize;
+        let mut row = self.cells[start + left as usize..start + right as usize + 1].to_vec();
+        if row[0].is_continuation() {
+            row[0].ch = ' ';
+        }
+        if right + 1 < self.width && self.cells[start + right as usize + 1].is_continuation() {
+            let last = row.len() - 1;
+            row[last].ch = ' ';
+            row[last].combining = Combining::None;
+        }
+        row
+    }
+
+    // Returns the part of a rectangle inside the buffer
+    fn clip_rect(&self, rect: &CellRect) -> Option<CellRect> {
+        let r = CellRect{
+            left: cmp::max(rect.left, 0),
+            top: cmp::max(rect.top, 0),
+            right: cmp::min(rect.right, self.width - 1),
+            bottom: cmp::min(rect.bottom, self.height - 1),
+        };
+        if r.is_empty() {
+            None
+        } else {
+            Some(r)
+        }
+    }
+
+    // Stores cells of a row starting from column `x`. The cells must fit the
+    // row. Wide characters cut by the row part edges are replaced with spaces
+    fn write_row(&mut self, x: i32, y: i32, row: &[Cell]) {
+        let right = x + row.len() as i32 - 1;
+        self.erase_wide(x, y);
+        self.erase_wide(right, y);
+
+        let start = (x + y * self.width) as usize;
+        let (mut first, mut last) = (-1, -1);
+        for (i, (dst, src)) in self.cells[start..start + row.len()].iter_mut().zip(row).enumerate() {
+            if dst != src {
+                *dst = src.clone();
+                if first == -1 {
+                    first = i as i32;
+                }
+                last = i as i32;
+            }
+        }
+        if first != -1 {
+            self.add_damage(y, x + first, x + last);
+        }
+    }
+
+    // Replaces the other half of a wide character at given coordinates
+    // with a space
+    fn erase_wide(&mut self, x: i32, y: i32) {
+        let is_cont = |c: &Option<Cell>| c.as_ref().map(|c| c.is_continuation()) == Some(true);
+        let ox = if is_cont(&self.get_cell(x, y)) {
+            x - 1
+        } else if is_cont(&self.get_cell(x + 1, y)) {
+            x + 1
+        } else {
+            return;
+        };
+        if let Some(mut cell) = self.get_cell(ox, y) {
+            cell.ch = ' ';
+            cell.combining = Combining::None;
+            self.put_cell(ox, y, cell);
+        }
+    }
+
+    // Stores a cell as is and updates the dirty area
+    fn put_cell(&mut self, x: i32, y: i32, c: Cell) {
+        let idx = (x + y * self.width) as usize;
+        if self.cells[idx] == c {
+            return;
+        }
+        self.cells[idx] = c;
+        self.add_damage(y, x, x);
+    }
+
+    /// Places an image on a rectangle of cells. The cells are reserved:
+    /// `Terminal::flush` does not write them while the image is there.
+    /// Returns the image ID
+    pub fn place_image(&mut self, rect: &CellRect, image: Image) -> ImageId {
+        let id = self.next_image_id;
+        self.next_image_id += 1;
+        self.images.push(ImagePlacement::new(id, rect, image));
+        self.dirty = true;
+        id
+    }
+
+    /// Removes an image. Its cells are written by the next flush.
+    /// Returns `false` if there is no image with this ID
+    pub fn remove_image(&mut self, id: ImageId) -> bool {
+        let idx = match self.images.iter().position(|img| img.id() == id) {
+            Some(idx) => idx,
+            None => return false,
+        };
+        let img = self.images.remove(idx);
+        if let Some(rect) = self.clip_rect(img.rect()) {
+            for y in rect.top..rect.bottom + 1 {
+                self.add_damage(y, rect.left, rect.right);
+            }
+        }
+        self.removed_images.push(img);
+        self.dirty = true;
+        true
+    }
+
+    /// Returns images placed on the buffer
+    pub fn images(&self) -> &[ImagePlacement] {
+        &self.images
+    }
+
+    /// Returns `true` if a cell is under an image
+    pub fn is_reserved(&self, x: i32, y: i32) -> bool {
+        self.images.iter().any(|img| {
+            let r = img.rect();
+            x >= r.left && x <= r.right && y >= r.top && y <= r.bottom
+        })
     }
-}
 
+    /// Returns images placed since the previous call and marks them as sent
+    /// to terminal, and images removed since the previous call
+    pub fn take_image_updates(&mut self) -> (Vec<ImagePlacement>, Vec<ImagePlacement>) {
+        let mut placed = Vec::new();
+        for img in self.images.iter_mut().filter(|img| !img.is_shown()) {
+            img.set_shown();
+            placed.push(img.clone());
+        }
+        (placed, self.removed_images.drain(..).collect())
+    }
+
+    // Extends the dirty area of a row to include columns from `left` to
+    // `right` inclusive. Runs closer than `DAMAGE_GAP` are joined, and a row
+    // keeps at most `MAX_ROW_SPANS` runs: the closest ones are joined
+    fn add_damage(&mut self, y: i32, left: i32, right: i32) {
+        self.dirty = true;
+        if self.dirty_rect.left == -1 {
+            self.dirty_rect = CellRect{left, top: y, right, bottom: y};
+        } else {
+            let r = &mut self.dirty_rect;
+            r.left = cmp::min(r.left, left);
+            r.right = cmp::max(r.right, right);
+            r.top = cmp::min(r.top, y);
+            r.bottom = cmp::max(r.bottom, y);
+        }
+
+        let spans = &mut self.damage[y as usize];
+        let mut span = Span{y, left, right};
+        // runs that touch the new one are joined with it
+        let first = spans.iter().position(|s| s.right + DAMAGE_GAP >= span.left).unwrap_or(spans.len());
+        let mut last = first;
+        while last < spans.len() && spans[last].left <= span.right + DAMAGE_GAP {
+            span.left = cmp::min(span.left, spans[last].left);
+            span.right = cmp::max(span.right, spans[last].right);
+            last += 1;
+        }
+        spans.splice(first..last, Some(span));
+
+        if spans.len() > MAX_ROW_SPANS {
+            let idx = (1..spans.len()).min_by_key(|&i| spans[i].left - spans[i - 1].right).unwrap_or(1);
+            spans[idx - 1].right = spans[idx].right;
+            spans.remove(idx);
+        }
+    }
+
+    // Recalculates `dirty_rect` from the changed runs
+    fn update_dirty_rect(&mut self) {
+        let mut r = CellRect::new();
+        for span in self.damage.iter().flat_map(|spans| spans.iter()) {
+            if r.left == -1 {
+                r = CellRect{left: span.left, top: span.y, right: span.right, bottom: span.y};
+            } else {
+                r.left = cmp::min(r.left, span.left);
+                r.right = cmp::max(r.right, span.right);
+                r.bottom = span.y;
+            }
+        }
+        self.dirty_rect = r;
+    }
+}
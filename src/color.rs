@@ -0,0 +1,233 @@
+//! Color downsampling. An application may use any color - true color, 256
+//! or 16 color palette - and the output layer converts it to the nearest
+//! color that the real terminal is able to display
+use std::cmp;
+use std::env;
+
+use common::*;
+
+// Standard xterm values of the first 16 palette colors
+const BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+// Channel values of 6x6x6 color cube (palette colors 16..231)
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+const CUBE_START: u8 = 16;
+const GRAY_START: u8 = 232;
+
+/// Makes a true color `Attribute` from its components
+pub fn rgb(r: u8, g: u8, b: u8) -> Attribute {
+    COLOR_RGB | (r as Attribute) << 16 | (g as Attribute) << 8 | b as Attribute
+}
+
+/// Makes an `Attribute` for a color from xterm 256-color palette
+pub fn palette(idx: u8) -> Attribute {
+    idx as Attribute + 1
+}
+
+/// Returns RGB components of a color. Text attributes are ignored.
+/// Returns `None` for `COLOR_DEFAULT` because its real value is unknown
+pub fn color_to_rgb(c: Attribute) -> Option<(u8, u8, u8)> {
+    let c = c & COLOR_MASK;
+    if c & COLOR_RGB != 0 {
+        Some(((c >> 16) as u8, (c >> 8) as u8, c as u8))
+    } else if c == COLOR_DEFAULT || c > 256 {
+        None
+    } else {
+        Some(palette_rgb((c - 1) as u8))
+    }
+}
+
+/// Returns RGB components of a color of xterm 256-color palette
+pub fn palette_rgb(idx: u8) -> (u8, u8, u8) {
+    if idx < CUBE_START {
+        BASE_COLORS[idx as usize]
+    } else if idx < GRAY_START {
+        let i = (idx - CUBE_START) as usize;
+        (CUBE_LEVELS[i / 36], CUBE_LEVELS[(i / 6) % 6], CUBE_LEVELS[i % 6])
+    } else {
+        let v = 8 + (idx - GRAY_START) * 10;
+        (v, v, v)
+    }
+}
+
+// A cheap approximation of perceptual color distance ("redmean")
+fn distance(c1: (u8, u8, u8), c2: (u8, u8, u8)) -> i32 {
+    let rmean = (c1.0 as i32 + c2.0 as i32) / 2;
+    let dr = c1.0 as i32 - c2.0 as i32;
+    let dg = c1.1 as i32 - c2.1 as i32;
+    let db = c1.2 as i32 - c2.2 as i32;
+    (((512 + rmean) * dr * dr) >> 8) + 4 * dg * dg + (((767 - rmean) * db * db) >> 8)
+}
+
+fn nearest_level(v: u8) -> usize {
+    let mut best = 0;
+    for (i, l) in CUBE_LEVELS.iter().enumerate() {
+        if (*l as i32 - v as i32).abs() < (CUBE_LEVELS[best] as i32 - v as i32).abs() {
+            best = i;
+        }
+    }
+    best
+}
+
+/// Returns the index of the nearest color from 6x6x6 color cube
+pub fn rgb_to_216(r: u8, g: u8, b: u8) -> u8 {
+    CUBE_START + (nearest_level(r) * 36 + nearest_level(g) * 6 + nearest_level(b)) as u8
+}
+
+/// Returns the index of the nearest gray color: a color from the grayscale
+/// ramp, or black or white from the color cube
+pub fn rgb_to_gray(r: u8, g: u8, b: u8) -> u8 {
+    // Rec. 601 luma
+    let luma = (299 * r as i32 + 587 * g as i32 + 114 * b as i32) / 1000;
+    if luma < 4 {
+        CUBE_START
+    } else if luma > 246 {
+        GRAY_START - 1
+    } else {
+        GRAY_START + cmp::min((luma - 3) / 10, 23) as u8
+    }
+}
+
+/// Returns the index of the nearest color of xterm 256-color palette.
+/// The first 16 colors are skipped because users often redefine them
+pub fn rgb_to_256(r: u8, g: u8, b: u8) -> u8 {
+    let cube = rgb_to_216(r, g, b);
+    let gray = rgb_to_gray(r, g, b);
+    if distance((r, g, b), palette_rgb(gray)) < distance((r, g, b), palette_rgb(cube)) {
+        gray
+    } else {
+        cube
+    }
+}
+
+/// Returns the index of the nearest color from the first 16 palette colors
+pub fn rgb_to_16(r: u8, g: u8, b: u8) -> u8 {
+    let mut best = 0;
+    let mut best_dist = i32::MAX;
+    for (i, c) in BASE_COLORS.iter().enumerate() {
+        let d = distance((r, g, b), *c);
+        if d < best_dist {
+            best = i;
+            best_dist = d;
+        }
+    }
+    best as u8
+}
+
+/// Converts the color part of `attr` to the nearest color available in
+/// output mode `mode`. Text attributes are preserved. The result for
+/// `OUTPUT_NORMAL` is always `COLOR_DEFAULT` or a palette color 0..15,
+/// for `OUTPUT_256` - `COLOR_DEFAULT` or any palette color
+pub fn quantize(attr: Attribute, mode: OutputMode) -> Attribute {
+    let c = attr & COLOR_MASK;
+    let text_attrs = attr & !COLOR_MASK;
+
+    if mode == OUTPUT_NO_COLOR {
+        return text_attrs;
+    }
+    if mode == OUTPUT_CURRENT || mode == OUTPUT_TRUECOLOR || c == COLOR_DEFAULT {
+        return attr;
+    }
+    let (r, g, b) = match color_to_rgb(c) {
+        Some(v) => v,
+        None => return text_attrs,
+    };
+
+    let idx = match mode {
+        OUTPUT_256 => {
+            if c & COLOR_RGB == 0 {
+                return attr;
+            }
+            rgb_to_256(r, g, b)
+        },
+        OUTPUT_216 => rgb_to_216(r, g, b),
+        OUTPUT_GRAYSCALE => rgb_to_gray(r, g, b),
+        _ => {
+            if c & COLOR_RGB == 0 && c <= 16 {
+                return attr;
+            }
+            rgb_to_16(r, g, b)
+        },
+    };
+
+    text_attrs | palette(idx)
+}
+
+/// Selects output mode by terminal environment variables:
+/// `NO_COLOR`, `COLORTERM`, and `TERM`
+pub fn detect_output_mode() -> OutputMode {
+    let no_color = match env::var_os("NO_COLOR") {
+        Some(v) => !v.is_empty(),
+        None => false,
+    };
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+
+    output_mode_from_env(&colorterm, &term, no_color)
+}
+
+/// Selects output mode by values of terminal environment variables.
+/// `no_color` is `true` if `NO_COLOR` is set to any non-empty value
+pub fn output_mode_from_env(colorterm: &str, term: &str, no_color: bool) -> OutputMode {
+    if no_color || term == "dumb" {
+        return OUTPUT_NO_COLOR;
+    }
+    if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+        return OUTPUT_TRUECOLOR;
+    }
+    if term.contains("256color") {
+        return OUTPUT_256;
+    }
+
+    OUTPUT_NORMAL
+}
+
+/// Parses a color definition. Supported formats:
+/// * `default`
+/// * a name of basic color: `black`, `red`, `green`, `yellow`, `blue`,
+///   `magenta`, `cyan`, `white`. A name with prefix `bright-` for bright
+///   colors, e.g. `bright-red`
+/// * palette color index: a number from 0 to 255
+/// * true color in HTML format `#rrggbb`
+pub fn parse_color(s: &str) -> Option<Attribute> {
+    let s = s.trim().to_lowercase();
+    let names = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+    if s == "default" {
+        return Some(COLOR_DEFAULT);
+    }
+    if let Some(idx) = names.iter().position(|n| *n == s) {
+        return Some(palette(idx as u8));
+    }
+    if let Some(name) = s.strip_prefix("bright-") {
+        return names.iter().position(|n| *n == name).map(|idx| palette(idx as u8 + 8));
+    }
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        return u32::from_str_radix(hex, 16).ok().map(|v| COLOR_RGB | v);
+    }
+    match s.parse::<u8>() {
+        Ok(idx) => Some(palette(idx)),
+        Err(_) => None,
+    }
+}
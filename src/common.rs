@@ -1,9 +1,22 @@
+use unicode_width::UnicodeWidthChar;
+
 pub type InputMode = i32;
 pub type OutputMode = i32;
+pub type AmbiguousWidth = i32;
+pub type SyncOutput = i32;
+pub type Align = i32;
+pub type VAlign = i32;
+pub type TextDirection = i32;
+pub type PixelMode = i32;
+pub type ImageProtocol = i32;
 pub type EventType = u8;
 pub type Modifier = u8;
 pub type Key = u16;
-pub type Attribute = u16;
+pub type Attribute = u32;
+pub type StyleAttr = u16;
+pub type LinkId = u32;
+pub type ClusterId = u32;
+pub type ImageId = u32;
 
 /// Terminal cursor information
 #[derive(Debug)]
@@ -34,12 +47,158 @@ pub enum Event {
     Key(Key, char, Modifier),
 }
 
+/// Text style of a cell
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash,Default)]
+pub struct Style {
+    /// A combination of `STYLE_*` flags and at most one underline kind
+    pub attrs: StyleAttr,
+    /// Color of underline. `COLOR_DEFAULT` means the color of the text
+    pub underline_color: Attribute,
+}
+
+impl Style {
+    /// Creates a style with given attributes and default underline color
+    pub fn new(attrs: StyleAttr) -> Style {
+        Style{
+            attrs,
+            underline_color: COLOR_DEFAULT,
+        }
+    }
+
+    /// Returns underline kind: one of `STYLE_*_UNDERLINE` or 0 if the text
+    /// is not underlined
+    pub fn underline(&self) -> StyleAttr {
+        self.attrs & STYLE_UNDERLINE_MASK
+    }
+}
+
+/// Character of a continuation cell: the second column of a wide
+/// character. The cell to the left of it keeps the wide character itself
+pub const CONTINUATION: char = '\0';
+
+/// Characters of a grapheme cluster that follow its first character
+/// `Cell::ch`: combining marks, variation selectors, emoji joined with zero
+/// width joiner etc
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum Combining {
+    /// The cell contains a single character
+    None,
+    /// Up to two characters kept in the cell. Unused places are `'\0'`
+    Inline([char; 2]),
+    /// A longer cluster kept in `CellBuf`, see `CellBuf::add_grapheme`.
+    /// The flag is `true` if the cluster takes two columns
+    Interned(ClusterId, bool),
+}
+
+// Regional indicators: a pair of them makes a flag emoji
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+// Width of a grapheme cluster. `cjk` selects the width of East Asian
+// characters of ambiguous width: 2 if `true`, 1 otherwise
+fn cluster_width(g: &str, cjk: bool) -> i32 {
+    let mut chars = g.chars();
+    let first = match chars.next() {
+        Some(c) => c,
+        None => return 0,
+    };
+    let w = if cjk { first.width_cjk() } else { first.width() };
+    match w {
+        None | Some(0) => 0,
+        Some(2) => 2,
+        _ => {
+            let mut rest = chars.peekable();
+            if (is_regional_indicator(first) && rest.peek().is_some()) || rest.any(|c| c == '\u{FE0F}') {
+                2
+            } else {
+                1
+            }
+        },
+    }
+}
+
+/// Returns the number of columns a grapheme cluster takes: 1 or 2. The width
+/// is defined by the first character. Emoji presentation selector and flags
+/// make the cluster wide. Returns 0 for clusters that cannot be displayed:
+/// empty ones, control characters, and zero-width characters without a base.
+/// East Asian characters of ambiguous width are narrow
+pub fn grapheme_width(g: &str) -> i32 {
+    cluster_width(g, false)
+}
+
+/// The same as `grapheme_width` but East Asian characters of ambiguous
+/// width are wide as they are in CJK locales
+pub fn grapheme_width_cjk(g: &str) -> i32 {
+    cluster_width(g, true)
+}
+
 /// Internal terminal cell representation
-#[derive(Debug,Clone,PartialEq)]
+#[derive(Debug,Clone,PartialEq,Eq,Hash)]
 pub struct Cell {
     pub ch: char,
     pub fg: Attribute,
-    pub bg: Attribute
+    pub bg: Attribute,
+    pub style: Style,
+    /// Hyperlink of the cell: ID of a link registered in `CellBuf` or
+    /// `NO_LINK`
+    pub link: LinkId,
+    /// The rest of the grapheme cluster that starts with `ch`
+    pub combining: Combining,
+}
+
+impl Cell {
+    /// Returns `true` if the cell is the second column of a wide character
+    pub fn is_continuation(&self) -> bool {
+        self.ch == CONTINUATION
+    }
+
+    /// Returns the number of columns the cell character occupies: 2 for wide
+    /// characters, 0 for continuation cells, and 1 for all others. East Asian
+    /// characters of ambiguous width are narrow
+    pub fn width(&self) -> i32 {
+        self.cell_width(false)
+    }
+
+    /// The same as `width` but East Asian characters of ambiguous width are
+    /// wide
+    pub fn width_cjk(&self) -> i32 {
+        self.cell_width(true)
+    }
+
+    fn cell_width(&self, cjk: bool) -> i32 {
+        if self.is_continuation() {
+            return 0;
+        }
+        let w = if cjk { self.ch.width_cjk() } else { self.ch.width() };
+        match self.combining {
+            Combining::Interned(_, true) => 2,
+            Combining::Inline(chars) if chars.contains(&'\u{FE0F}') || is_regional_indicator(self.ch) => 2,
+            _ => match w {
+                Some(2) => 2,
+                _ => 1,
+            },
+        }
+    }
+
+    /// Returns the cell style combined with old-style attributes
+    /// `ATTR_UNDERLINE` and `ATTR_REVERSE` of its colors, and `ATTR_BOLD` of
+    /// its text color. `ATTR_BOLD` of the background makes it bright and is
+    /// not a text style
+    pub fn full_style(&self) -> Style {
+        let mut st = self.style;
+        let attrs = self.fg | self.bg;
+        if self.fg & ATTR_BOLD != 0 {
+            st.attrs |= STYLE_BOLD;
+        }
+        if attrs & ATTR_REVERSE != 0 {
+            st.attrs |= STYLE_REVERSE;
+        }
+        if attrs & ATTR_UNDERLINE != 0 && st.underline() == 0 {
+            st.attrs |= STYLE_UNDERLINE;
+        }
+        st
+    }
 }
 
 iota! {
@@ -126,6 +285,13 @@ iota! {
         | MOD_MOTION
 }
 
+// Colors occupy the lowest 25 bits of an `Attribute`:
+// * `COLOR_DEFAULT`(0) - the terminal default color
+// * 1..256 - a palette color with index value-1. The first 8 values are
+//   the named `COLOR_*` constants, then bright colors, the 6x6x6 color cube,
+//   and the grayscale ramp of xterm 256-color palette
+// * `COLOR_RGB` bit set - true color, the lowest 24 bits are 0xRRGGBB
+// Use `rgb` and `palette` functions to build a color
 iota! {
     pub const COLOR_DEFAULT: Attribute = iota;
         | COLOR_BLACK
@@ -138,6 +304,10 @@ iota! {
         | COLOR_WHITE
 }
 
+pub const COLOR_RGB: Attribute = 1 << 24;
+/// Mask to extract color from an `Attribute` with text attributes
+pub const COLOR_MASK: Attribute = COLOR_RGB | 0xFF_FFFF;
+
 iota! {
     pub const INPUT_ESC: InputMode = 1 << iota;
         | INPUT_ALT
@@ -151,6 +321,67 @@ iota! {
         | OUTPUT_256
         | OUTPUT_216
         | OUTPUT_GRAYSCALE
+        | OUTPUT_TRUECOLOR
+        | OUTPUT_NO_COLOR
+}
+
+// How to display East Asian characters of ambiguous width, e.g. box
+// drawing characters or Greek letters: narrow, wide, or detected by printing
+// a character and checking how far the cursor has moved
+iota! {
+    pub const AMBIGUOUS_NARROW: AmbiguousWidth = iota;
+        | AMBIGUOUS_WIDE
+        | AMBIGUOUS_AUTO
+}
+
+// Whether to wrap output into synchronized update sequences: never, always,
+// or if the terminal reports that it supports them
+iota! {
+    pub const SYNC_OFF: SyncOutput = iota;
+        | SYNC_ON
+        | SYNC_AUTO
+}
+
+// Horizontal alignment of text lines. Justified lines are stretched to the
+// full width except the last line of every paragraph
+iota! {
+    pub const ALIGN_LEFT: Align = iota;
+        | ALIGN_CENTER
+        | ALIGN_RIGHT
+        | ALIGN_JUSTIFY
+}
+
+// Vertical alignment of a text block that is shorter than its rectangle
+iota! {
+    pub const VALIGN_TOP: VAlign = iota;
+        | VALIGN_MIDDLE
+        | VALIGN_BOTTOM
+}
+
+// Base direction of bidirectional text: do not reorder text, detect the
+// direction by the first strong character, left-to-right, or right-to-left
+iota! {
+    pub const BIDI_OFF: TextDirection = iota;
+        | BIDI_AUTO
+        | BIDI_LTR
+        | BIDI_RTL
+}
+
+// How `PixelCanvas` maps pixels to cells: 2x4 Braille dots, 2x2 quadrant
+// blocks, or 1x2 half blocks with their own text and background colors
+iota! {
+    pub const PIXEL_BRAILLE: PixelMode = iota;
+        | PIXEL_QUADRANT
+        | PIXEL_HALF_BLOCK
+}
+
+// How images are displayed: with half block characters, Sixel graphics,
+// kitty graphics protocol, or with the best protocol the terminal supports
+iota! {
+    pub const IMAGE_BLOCKS: ImageProtocol = iota;
+        | IMAGE_SIXEL
+        | IMAGE_KITTY
+        | IMAGE_AUTO
 }
 
 iota! {
@@ -164,10 +395,32 @@ iota! {
 }
 
 iota! {
-    pub const ATTR_BOLD: Attribute = 1 << (iota + 9);
+    pub const ATTR_BOLD: Attribute = 1 << (iota + 25);
         | ATTR_UNDERLINE
         | ATTR_REVERSE
 }
 
+iota! {
+    pub const STYLE_BOLD: StyleAttr = 1 << iota;
+        | STYLE_DIM
+        | STYLE_ITALIC
+        | STYLE_BLINK
+        | STYLE_REVERSE
+        | STYLE_STRIKETHROUGH
+        | STYLE_OVERLINE
+}
+
+// Underline kinds. Only one kind can be used at a time
+iota! {
+    pub const STYLE_UNDERLINE: StyleAttr = (iota + 1) << 8;
+        | STYLE_DOUBLE_UNDERLINE
+        | STYLE_CURLY_UNDERLINE
+        | STYLE_DOTTED_UNDERLINE
+        | STYLE_DASHED_UNDERLINE
+}
+pub const STYLE_UNDERLINE_MASK: StyleAttr = 0x7 << 8;
+
 
 pub const CURSOR_HIDDEN: i32 = -1;
+
+pub const NO_LINK: LinkId = 0;
@@ -0,0 +1,564 @@
+//! Output to terminals that understand ANSI escape sequences: xterm and
+//! compatible terminal emulators, Windows 10 console in VT mode etc
+use std::cell::RefCell;
+use std::io::Write;
+
+use cellbuf::*;
+use color::*;
+use common::*;
+use intf::*;
+
+// Appends SGR parameters for a color. `base` is 30 for text color, 40 for
+// background, and 50 for underline color. Underline color does not have
+// short codes for the first 16 palette colors
+fn push_color(s: &mut String, c: Attribute, base: u32) {
+    let c = c & COLOR_MASK;
+    if c == COLOR_DEFAULT {
+        s.push_str(&format!(";{}", base + 9));
+    } else if c & COLOR_RGB != 0 {
+        s.push_str(&format!(";{};2;{};{};{}", base + 8, (c >> 16) & 0xFF, (c >> 8) & 0xFF, c & 0xFF));
+    } else {
+        let idx = c - 1;
+        if idx < 8 && base != 50 {
+            s.push_str(&format!(";{}", base + idx));
+        } else if idx < 16 && base != 50 {
+            s.push_str(&format!(";{}", base + 60 + idx - 8));
+        } else {
+            s.push_str(&format!(";{};5;{}", base + 8, idx));
+        }
+    }
+}
+
+// Appends SGR parameter for underline kind `ul`. Nothing is appended if
+// the text is not underlined
+fn push_underline(s: &mut String, ul: StyleAttr) {
+    match ul {
+        0 => {},
+        STYLE_DOUBLE_UNDERLINE => s.push_str(";4:2"),
+        STYLE_CURLY_UNDERLINE => s.push_str(";4:3"),
+        STYLE_DOTTED_UNDERLINE => s.push_str(";4:4"),
+        STYLE_DASHED_UNDERLINE => s.push_str(";4:5"),
+        _ => s.push_str(";4"),
+    }
+}
+
+/// Returns SGR escape sequence that resets all attributes and then sets
+/// colors and text style of a cell. Colors are converted to the nearest ones
+/// available in output mode `mode`
+pub fn sgr_sequence(c: &Cell, mode: OutputMode) -> String {
+    let mut s = String::from("\x1b[0");
+    let st = c.full_style();
+
+    if st.attrs & STYLE_BOLD != 0 {
+        s.push_str(";1");
+    }
+    if st.attrs & STYLE_DIM != 0 {
+        s.push_str(";2");
+    }
+    if st.attrs & STYLE_ITALIC != 0 {
+        s.push_str(";3");
+    }
+    push_underline(&mut s, st.underline());
+    if st.attrs & STYLE_BLINK != 0 {
+        s.push_str(";5");
+    }
+    if st.attrs & STYLE_REVERSE != 0 {
+        s.push_str(";7");
+    }
+    if st.attrs & STYLE_STRIKETHROUGH != 0 {
+        s.push_str(";9");
+    }
+    if st.attrs & STYLE_OVERLINE != 0 {
+        s.push_str(";53");
+    }
+
+    let fg = quantize(c.fg, mode) & COLOR_MASK;
+    let bg = background(c, mode);
+    if fg != COLOR_DEFAULT {
+        push_color(&mut s, fg, 30);
+    }
+    if bg != COLOR_DEFAULT {
+        push_color(&mut s, bg, 40);
+    }
+    if st.underline() != 0 {
+        let ul = quantize(st.underline_color, mode) & COLOR_MASK;
+        if ul != COLOR_DEFAULT {
+            push_color(&mut s, ul, 50);
+        }
+    }
+
+    s.push('m');
+    s
+}
+
+// Returns the background color of a cell converted for output mode `mode`.
+// `ATTR_BOLD` makes the first 8 palette colors bright, as the Windows
+// console does
+fn background(c: &Cell, mode: OutputMode) -> Attribute {
+    let bg = quantize(c.bg, mode) & COLOR_MASK;
+    if c.bg & ATTR_BOLD != 0 && bg != COLOR_DEFAULT && bg & COLOR_RGB == 0 && bg <= COLOR_WHITE {
+        bg + 8
+    } else {
+        bg
+    }
+}
+
+/// Returns OSC 8 escape sequence that starts a hyperlink. All characters
+/// printed after it belong to the link until a sequence for another link or
+/// for `NO_LINK` is printed. The link ID is sent as well, so a terminal
+/// knows that parts of the link drawn separately are the same link
+pub fn hyperlink_sequence(buf: &CellBuf, id: LinkId) -> String {
+    match buf.get_link(id) {
+        Some(url) => format!("\x1b]8;id={};{}\x1b\\", id, url),
+        None => String::from("\x1b]8;;\x1b\\"),
+    }
+}
+
+// Trailing blanks shorter than this are printed instead of erasing
+const MIN_ERASE: i32 = 4;
+
+// Colors and style of text as a terminal displays them: colors are already
+// converted for the output mode
+#[derive(Debug,Clone,Copy,PartialEq)]
+struct Pen {
+    fg: Attribute,
+    bg: Attribute,
+    style: Style,
+}
+
+impl Pen {
+    fn new(c: &Cell, mode: OutputMode) -> Pen {
+        let mut style = c.full_style();
+        style.underline_color = quantize(style.underline_color, mode) & COLOR_MASK;
+        Pen{
+            fg: quantize(c.fg, mode) & COLOR_MASK,
+            bg: background(c, mode),
+            style,
+        }
+    }
+}
+
+// Returns CSI sequence with a single parameter that is omitted if it is 1
+fn csi_n(n: i32, cmd: char) -> String {
+    if n == 1 {
+        format!("\x1b[{}", cmd)
+    } else {
+        format!("\x1b[{}{}", n, cmd)
+    }
+}
+
+// Returns the shortest sequence that moves the cursor horizontally in the
+// same line
+fn horizontal_move(from: i32, to: i32) -> String {
+    if to > from {
+        csi_n(to - from, 'C')
+    } else if to < from {
+        csi_n(from - to, 'D')
+    } else {
+        String::new()
+    }
+}
+
+/// Output encoder for terminals that understand ANSI escape sequences. It
+/// remembers the terminal cursor position, text attributes, and the current
+/// hyperlink, and emits only the sequences needed to change them: no cursor
+/// moves between adjacent cells, short relative moves or CR/LF when they are
+/// shorter than absolute ones, and only changed SGR parameters. All output is
+/// collected in a string that is sent to the terminal at once
+#[derive(Debug)]
+pub struct Encoder {
+    out: String,
+    cursor: Option<(i32, i32)>,
+    pen: Option<Pen>,
+    link: LinkId,
+}
+
+impl Default for Encoder {
+    fn default() -> Encoder {
+        Encoder{
+            out: String::new(),
+            cursor: None,
+            pen: None,
+            link: NO_LINK,
+        }
+    }
+}
+
+impl Encoder {
+    /// Creates an encoder that does not know the terminal state
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    /// Forgets the terminal state, e.g. after something else has written to
+    /// the terminal. The next output sets the cursor position and text
+    /// attributes completely
+    pub fn reset(&mut self) {
+        self.cursor = None;
+        self.pen = None;
+        self.link = NO_LINK;
+    }
+
+    /// Returns collected output and clears it
+    pub fn take(&mut self) -> String {
+        ::std::mem::take(&mut self.out)
+    }
+
+    /// Moves the cursor to the given position
+    pub fn move_to(&mut self, x: i32, y: i32) {
+        let abs = if x == 0 && y == 0 {
+            String::from("\x1b[H")
+        } else if x == 0 {
+            format!("\x1b[{}H", y + 1)
+        } else {
+            format!("\x1b[{};{}H", y + 1, x + 1)
+        };
+        let (cx, cy) = match self.cursor {
+            Some(pos) if pos == (x, y) => return,
+            Some(pos) => pos,
+            None => {
+                self.out.push_str(&abs);
+                self.cursor = Some((x, y));
+                return;
+            },
+        };
+
+        let mut moves = vec![abs];
+        if y == cy {
+            moves.push(horizontal_move(cx, x));
+            moves.push(format!("\r{}", horizontal_move(0, x)));
+        } else if y > cy {
+            moves.push(format!("\r{}{}", "\n".repeat((y - cy) as usize), horizontal_move(0, x)));
+            moves.push(format!("{}{}", csi_n(y - cy, 'B'), horizontal_move(cx, x)));
+        } else {
+            moves.push(format!("{}{}", csi_n(cy - y, 'A'), horizontal_move(cx, x)));
+        }
+        let best = moves.into_iter().min_by_key(|m| m.len()).unwrap_or_default();
+        self.out.push_str(&best);
+        self.cursor = Some((x, y));
+    }
+
+    /// Sets colors and style of the following text to the ones of cell `c`.
+    /// Colors are converted to the nearest ones available in output mode
+    /// `mode`
+    pub fn set_style(&mut self, c: &Cell, mode: OutputMode) {
+        let mut pen = Pen::new(c, mode);
+        let old = match self.pen {
+            Some(old) => old,
+            None => {
+                self.set_full_style(c, mode, pen);
+                return;
+            },
+        };
+        // underline color does not matter for text without underline, so
+        // the terminal keeps the old one
+        if pen.style.underline() == 0 {
+            pen.style.underline_color = old.style.underline_color;
+        }
+        if pen == old {
+            return;
+        }
+
+        let mut p = String::new();
+        let (o, n) = (old.style.attrs, pen.style.attrs);
+        // the same code turns off both bold and dim
+        let bd = STYLE_BOLD | STYLE_DIM;
+        if o & bd & !n != 0 {
+            p.push_str(";22");
+            if n & STYLE_BOLD != 0 {
+                p.push_str(";1");
+            }
+            if n & STYLE_DIM != 0 {
+                p.push_str(";2");
+            }
+        } else {
+            if n & !o & STYLE_BOLD != 0 {
+                p.push_str(";1");
+            }
+            if n & !o & STYLE_DIM != 0 {
+                p.push_str(";2");
+            }
+        }
+        for &(flag, on, off) in &[(STYLE_ITALIC, ";3", ";23"), (STYLE_BLINK, ";5", ";25"),
+                                  (STYLE_REVERSE, ";7", ";27"), (STYLE_STRIKETHROUGH, ";9", ";29"),
+                                  (STYLE_OVERLINE, ";53", ";55")] {
+            if o & flag != n & flag {
+                p.push_str(if n & flag != 0 { on } else { off });
+            }
+        }
+        if old.style.underline() != pen.style.underline() {
+            match pen.style.underline() {
+                0 => p.push_str(";24"),
+                ul => push_underline(&mut p, ul),
+            }
+        }
+        if old.fg != pen.fg {
+            push_color(&mut p, pen.fg, 30);
+        }
+        if old.bg != pen.bg {
+            push_color(&mut p, pen.bg, 40);
+        }
+        if old.style.underline_color != pen.style.underline_color {
+            push_color(&mut p, pen.style.underline_color, 50);
+        }
+
+        let diff = format!("\x1b[{}m", &p[1..]);
+        if diff.len() < sgr_sequence(c, mode).len() {
+            self.out.push_str(&diff);
+            self.pen = Some(pen);
+        } else {
+            self.set_full_style(c, mode, pen);
+        }
+    }
+
+    /// Resets colors and text style to the default ones, so they do not
+    /// leak into output that does not go through the encoder
+    pub fn reset_style(&mut self) {
+        let default = Pen{fg: COLOR_DEFAULT, bg: COLOR_DEFAULT, style: Style::default()};
+        if self.pen != Some(default) {
+            self.out.push_str("\x1b[0m");
+            self.pen = Some(default);
+        }
+    }
+
+    // Resets all attributes and sets new ones
+    fn set_full_style(&mut self, c: &Cell, mode: OutputMode, pen: Pen) {
+        let mut pen = pen;
+        // the reset turns underline color to default, and it is set only
+        // for underlined text
+        if pen.style.underline() == 0 {
+            pen.style.underline_color = COLOR_DEFAULT;
+        }
+        self.out.push_str(&sgr_sequence(c, mode));
+        self.pen = Some(pen);
+    }
+
+    /// Starts a hyperlink or ends the current one if `id` is `NO_LINK`
+    pub fn set_link(&mut self, buf: &CellBuf, id: LinkId) {
+        if id != self.link {
+            self.out.push_str(&hyperlink_sequence(buf, id));
+            self.link = id;
+        }
+    }
+
+    /// Prints text that takes `width` columns at the cursor position. The
+    /// cursor position becomes unknown when the text reaches the end of
+    /// the line `line_width` characters long, because terminals differ in
+    /// how they wrap lines
+    pub fn put(&mut self, s: &str, width: i32, line_width: i32) {
+        self.out.push_str(s);
+        self.cursor = match self.cursor {
+            Some((x, y)) if x + width < line_width => Some((x + width, y)),
+            _ => None,
+        };
+    }
+
+    /// Erases the line from the cursor to the end using the current
+    /// background color
+    pub fn erase_line(&mut self) {
+        self.out.push_str("\x1b[K");
+    }
+
+    /// Moves lines from `top` to `bottom` inclusive up by `count` lines, or
+    /// down if `count` is negative, using a scroll region. New lines are
+    /// filled with the current background color, so text attributes must be
+    /// reset before the call
+    pub fn scroll(&mut self, top: i32, bottom: i32, count: i32) {
+        self.out.push_str(&format!("\x1b[{};{}r", top + 1, bottom + 1));
+        if count > 0 {
+            self.out.push_str(&csi_n(count, 'S'));
+        } else {
+            self.out.push_str(&csi_n(-count, 'T'));
+        }
+        // resetting the scroll region moves the cursor home
+        self.out.push_str("\x1b[r");
+        self.cursor = None;
+    }
+}
+
+// Returns `true` if a cell can be erased instead of printing it
+fn is_blank(c: &Cell, mode: OutputMode) -> bool {
+    let pen = Pen::new(c, mode);
+    let visible = STYLE_REVERSE | STYLE_UNDERLINE_MASK | STYLE_STRIKETHROUGH | STYLE_OVERLINE;
+    c.ch == ' ' && c.combining == Combining::None && c.link == NO_LINK
+        && pen.bg == COLOR_DEFAULT && pen.style.attrs & visible == 0
+}
+
+/// Terminal that draws with ANSI escape sequences. The output goes to any
+/// `Write` implementation, e.g. `std::io::stdout()`. The terminal size is
+/// not detected automatically and must be set in `new` and `resize`.
+/// The terminal does not read input, so `get_cursor_pos` always fails.
+/// Every `write` sends all output with a single `write_all` call. Note that
+/// `std::io::Stdout` is line buffered and splits output at line feeds, so
+/// use a block buffered or unbuffered output to get one system call
+pub struct EscTerminal<W: Write> {
+    out: RefCell<W>,
+    width: i32,
+    height: i32,
+    output_mode: OutputMode,
+    cursor: RefCell<(i16, i16)>,
+    encoder: RefCell<Encoder>,
+}
+
+impl<W: Write> EscTerminal<W> {
+    /// Creates a new terminal that writes to `out`. Output mode is
+    /// detected from environment variables
+    pub fn new(out: W, width: i32, height: i32) -> EscTerminal<W> {
+        EscTerminal{
+            out: RefCell::new(out),
+            width,
+            height,
+            output_mode: detect_output_mode(),
+            cursor: RefCell::new((0, 0)),
+            encoder: RefCell::new(Encoder::new()),
+        }
+    }
+
+    /// Changes the terminal size. Used when the real terminal is resized
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.encoder.borrow_mut().reset();
+    }
+
+    /// Destroys the terminal and returns its output
+    pub fn into_inner(self) -> W {
+        self.out.into_inner()
+    }
+
+    fn send(&self, s: &str) -> Result<(), String> {
+        let mut out = self.out.borrow_mut();
+        match out.write_all(s.as_bytes()).and_then(|_| out.flush()) {
+            Err(e) => Err(format!("Failed to output: {}", e)),
+            _ => Ok(()),
+        }
+    }
+
+    // Encodes a changed part of a row
+    fn write_span(&self, enc: &mut Encoder, buf: &CellBuf, span: &Span) {
+        let y = span.y;
+        // a wide character is redrawn from its first column
+        let left = match buf.get_cell(span.left, y) {
+            Some(ref c) if c.is_continuation() => span.left - 1,
+            _ => span.left,
+        };
+
+        // blanks at the end of the line are erased
+        let mut end = span.right + 1;
+        if span.right == buf.width - 1 {
+            let mut blank = end;
+            while blank > left && buf.get_cell(blank - 1, y).map(|c| is_blank(&c, self.output_mode)) == Some(true) {
+                blank -= 1;
+            }
+            if end - blank >= MIN_ERASE {
+                end = blank;
+            }
+        }
+
+        for x in left..end {
+            let c = match buf.get_cell(x, y) {
+                Some(c) => c,
+                None => continue,
+            };
+            // the terminal has already moved the cursor past it
+            if c.is_continuation() {
+                continue;
+            }
+            enc.move_to(x, y);
+            enc.set_link(buf, c.link);
+            enc.set_style(&c, self.output_mode);
+            let w = buf.cell_width(&c);
+            match c.combining {
+                Combining::None => enc.put(c.ch.encode_utf8(&mut [0; 4]), w, buf.width),
+                _ => enc.put(&buf.get_grapheme(&c), w, buf.width),
+            }
+        }
+        if end <= span.right {
+            if let Some(c) = buf.get_cell(end, y) {
+                enc.move_to(end, y);
+                enc.set_link(buf, NO_LINK);
+                enc.set_style(&c, self.output_mode);
+                enc.erase_line();
+            }
+        }
+        // the link must not cover cells that are not redrawn
+        enc.set_link(buf, NO_LINK);
+    }
+}
+
+impl<W: Write> TerminalManager for EscTerminal<W> {
+    fn write(&self, buf: &CellBuf, spans: &[Span]) -> Result<(), String> {
+        if spans.is_empty() {
+            return Ok(());
+        }
+
+        let mut enc = self.encoder.borrow_mut();
+        for span in spans {
+            if !span.is_empty() {
+                self.write_span(&mut enc, buf, span);
+            }
+        }
+
+        enc.reset_style();
+        // output moves the cursor, so it is returned back
+        let (cx, cy) = *self.cursor.borrow();
+        enc.move_to(cx as i32, cy as i32);
+        let s = enc.take();
+        self.send(&s)
+    }
+
+    fn set_output_mode(&mut self, mode: OutputMode) -> OutputMode {
+        if mode != OUTPUT_CURRENT {
+            self.output_mode = mode;
+        }
+        self.output_mode
+    }
+
+    fn size(&self) -> Result<Point, String> {
+        Ok(Point{
+            x: self.width,
+            y: self.height,
+        })
+    }
+
+    fn set_cursor_pos(&self, x: i16, y: i16) -> Result<(), String> {
+        *self.cursor.borrow_mut() = (x, y);
+        let mut enc = self.encoder.borrow_mut();
+        enc.move_to(x as i32, y as i32);
+        let s = enc.take();
+        self.send(&s)
+    }
+
+    fn get_cursor_pos(&self) -> Result<CursorInfo, String> {
+        // the reply to cursor position request comes to the input that the
+        // terminal does not read, and the position set by `set_cursor_pos`
+        // may differ from the real one, e.g. after `write_raw`
+        Err(String::from("Cursor position cannot be read from output-only terminal"))
+    }
+
+    fn scroll(&self, top: i32, bottom: i32, count: i32) -> Result<(), String> {
+        let mut enc = self.encoder.borrow_mut();
+        let blank = Cell{
+            ch: ' ',
+            fg: COLOR_DEFAULT,
+            bg: COLOR_DEFAULT,
+            style: Style::default(),
+            link: NO_LINK,
+            combining: Combining::None,
+        };
+        enc.set_style(&blank, self.output_mode);
+        enc.scroll(top, bottom, count);
+        let s = enc.take();
+        self.send(&s)
+    }
+
+    fn write_raw(&self, s: &str) -> Result<(), String> {
+        // the string may change anything
+        self.encoder.borrow_mut().reset();
+        self.send(s)
+    }
+
+    fn write_control(&self, s: &str) -> Result<(), String> {
+        self.send(s)
+    }
+}
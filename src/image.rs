@@ -0,0 +1,302 @@
+//! Images in terminal window. A terminal that supports Sixel graphics or
+//! kitty graphics protocol displays real pixels. The image is placed on a
+//! rectangle of cells, and `CellBuf` reserves them: `Terminal::flush` does
+//! not write the cells under the image, so text output does not erase it.
+//! On other terminals an image is drawn with half block characters, two
+//! pixels per cell.
+//!
+//! Images are not moved by scrolls. Sixel images cannot be deleted, so the
+//! cells under a removed Sixel image are redrawn
+use std::cmp;
+
+use cellbuf::*;
+use color::*;
+use common::*;
+use pixel::*;
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+// Kitty protocol limits the payload of one sequence
+const KITTY_CHUNK: usize = 4096;
+// Pixels with lower alpha are transparent
+const ALPHA_THRESHOLD: u8 = 128;
+
+/// ID of the image that `Terminal` sends to find out whether the terminal
+/// supports kitty graphics protocol
+pub const KITTY_QUERY_ID: u32 = 31;
+/// Kitty graphics query: a terminal that supports the protocol replies with
+/// `ESC _ G i=31;OK ESC \`
+pub const KITTY_QUERY: &str = "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\";
+
+/// RGBA pixels
+#[derive(Debug,Clone,PartialEq)]
+pub struct Image {
+    width: i32,
+    height: i32,
+    // 4 bytes per pixel, row by row
+    data: Vec<u8>,
+}
+
+/// An image placed on a rectangle of cells of `CellBuf`
+#[derive(Debug,Clone)]
+pub struct ImagePlacement {
+    id: ImageId,
+    rect: CellRect,
+    image: Image,
+    // `true` after the image is sent to terminal
+    shown: bool,
+}
+
+impl ImagePlacement {
+    /// Creates a placement that has not been sent to terminal yet
+    pub fn new(id: ImageId, rect: &CellRect, image: Image) -> ImagePlacement {
+        ImagePlacement{id, rect: rect.clone(), image, shown: false}
+    }
+
+    /// Returns the image ID
+    pub fn id(&self) -> ImageId {
+        self.id
+    }
+
+    /// Returns the rectangle of cells the image covers
+    pub fn rect(&self) -> &CellRect {
+        &self.rect
+    }
+
+    /// Returns the image
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// Returns `true` if the image has been sent to terminal
+    pub fn is_shown(&self) -> bool {
+        self.shown
+    }
+
+    /// Marks the image as sent to terminal
+    pub fn set_shown(&mut self) {
+        self.shown = true;
+    }
+}
+
+impl Image {
+    /// Creates an image from RGBA data: 4 bytes per pixel, row by row.
+    /// Returns an error if the data size does not match the image size
+    pub fn from_rgba(width: i32, height: i32, data: Vec<u8>) -> Result<Image, String> {
+        if width < 0 || height < 0 || data.len() != (width * height * 4) as usize {
+            return Err(format!("Invalid RGBA data size {} for image {}x{}", data.len(), width, height));
+        }
+        Ok(Image{width, height, data})
+    }
+
+    /// Creates an opaque image from RGB data: 3 bytes per pixel, row by row.
+    /// Returns an error if the data size does not match the image size
+    pub fn from_rgb(width: i32, height: i32, data: &[u8]) -> Result<Image, String> {
+        if width < 0 || height < 0 || data.len() != (width * height * 3) as usize {
+            return Err(format!("Invalid RGB data size {} for image {}x{}", data.len(), width, height));
+        }
+        let mut rgba = Vec::with_capacity(data.len() / 3 * 4);
+        for px in data.chunks(3) {
+            rgba.extend_from_slice(px);
+            rgba.push(255);
+        }
+        Ok(Image{width, height, data: rgba})
+    }
+
+    /// Returns the image size in pixels
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Returns RGBA components of a pixel
+    pub fn pixel(&self, x: i32, y: i32) -> Option<(u8, u8, u8, u8)> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        let i = ((y * self.width + x) * 4) as usize;
+        Some((self.data[i], self.data[i + 1], self.data[i + 2], self.data[i + 3]))
+    }
+
+    /// Returns the image scaled to a new size. Every new pixel is the
+    /// average of the pixels it covers
+    pub fn scale(&self, width: i32, height: i32) -> Image {
+        let (width, height) = (cmp::max(width, 0), cmp::max(height, 0));
+        let mut data = Vec::with_capacity((width * height * 4) as usize);
+        if self.width == 0 || self.height == 0 {
+            data.resize((width * height * 4) as usize, 0);
+            return Image{width, height, data};
+        }
+        for y in 0..height {
+            let top = y * self.height / height;
+            let bottom = cmp::max((y + 1) * self.height / height, top + 1);
+            for x in 0..width {
+                let left = x * self.width / width;
+                let right = cmp::max((x + 1) * self.width / width, left + 1);
+                let mut sum = [0u32; 4];
+                for sy in top..bottom {
+                    for sx in left..right {
+                        let i = ((sy * self.width + sx) * 4) as usize;
+                        for (s, v) in sum.iter_mut().zip(&self.data[i..i + 4]) {
+                            *s += *v as u32;
+                        }
+                    }
+                }
+                let n = ((bottom - top) * (right - left)) as u32;
+                data.extend(sum.iter().map(|s| (s / n) as u8));
+            }
+        }
+        Image{width, height, data}
+    }
+
+    /// Returns the image as half block characters that take `cols` x `rows`
+    /// cells. Transparent pixels are clear
+    pub fn to_pixel_canvas(&self, cols: i32, rows: i32) -> PixelCanvas {
+        let mut pc = PixelCanvas::new(PIXEL_HALF_BLOCK, cols, rows);
+        let (w, h) = pc.size();
+        let scaled = self.scale(w, h);
+        for y in 0..h {
+            for x in 0..w {
+                if let Some((r, g, b, a)) = scaled.pixel(x, y) {
+                    if a >= ALPHA_THRESHOLD {
+                        pc.set_color(rgb(r, g, b));
+                        pc.set_pixel(x, y);
+                    }
+                }
+            }
+        }
+        pc
+    }
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut res = String::with_capacity(data.len() / 3 * 4 + 4);
+    for chunk in data.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - 6 * i)) & 0x3F] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+// Appends a run of the same sixel character
+fn push_sixel_run(s: &mut String, ch: char, count: usize) {
+    if count > 3 {
+        s.push_str(&format!("!{}{}", count, ch));
+    } else {
+        s.extend((0..count).map(|_| ch));
+    }
+}
+
+/// Returns Sixel sequence that draws an image at the cursor position. Colors
+/// are reduced to 6x6x6 color cube, transparent pixels are not drawn
+pub fn sixel_sequence(img: &Image) -> String {
+    // index of color cube or `None` for transparent pixels
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let colors: Vec<Option<usize>> = img.data.chunks(4).map(|p| {
+        if p[3] < ALPHA_THRESHOLD {
+            None
+        } else {
+            Some(level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        }
+    }).collect();
+
+    let mut s = format!("\x1bP0;1;0q\"1;1;{};{}", img.width, img.height);
+    let mut used: Vec<usize> = colors.iter().filter_map(|&c| c).collect();
+    used.sort();
+    used.dedup();
+    for &c in &used {
+        s.push_str(&format!("#{};2;{};{};{}", c, c / 36 * 20, c / 6 % 6 * 20, c % 6 * 20));
+    }
+
+    let (w, h) = (img.width as usize, img.height as usize);
+    for top in (0..h).step_by(6) {
+        let rows = top..cmp::min(top + 6, h);
+        let mut band_colors: Vec<usize> = rows.clone()
+            .flat_map(|y| colors[y * w..(y + 1) * w].iter().filter_map(|&c| c))
+            .collect();
+        band_colors.sort();
+        band_colors.dedup();
+        for (k, &c) in band_colors.iter().enumerate() {
+            if k > 0 {
+                s.push('$');
+            }
+            s.push_str(&format!("#{}", c));
+            let sixels: Vec<char> = (0..w).map(|x| {
+                let bits = rows.clone().fold(0, |acc, y| {
+                    if colors[y * w + x] == Some(c) { acc | 1 << (y - top) } else { acc }
+                });
+                (63 + bits) as u8 as char
+            }).collect();
+            // trailing empty sixels are not needed
+            let len = sixels.iter().rposition(|&ch| ch != '?').map_or(0, |i| i + 1);
+            let mut i = 0;
+            while i < len {
+                let run = sixels[i..len].iter().take_while(|&&ch| ch == sixels[i]).count();
+                push_sixel_run(&mut s, sixels[i], run);
+                i += run;
+            }
+        }
+        if top + 6 < h {
+            s.push('-');
+        }
+    }
+    s.push_str("\x1b\\");
+    s
+}
+
+/// Returns kitty graphics protocol sequences that transmit an image and
+/// display it at the cursor position scaled to `cols` x `rows` cells. The
+/// cursor does not move, and the terminal does not reply
+pub fn kitty_sequence(img: &Image, id: ImageId, cols: i32, rows: i32) -> String {
+    let payload = base64(&img.data);
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut s = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = if i + 1 < chunks.len() { 1 } else { 0 };
+        let data = String::from_utf8_lossy(chunk);
+        if i == 0 {
+            s.push_str(&format!("\x1b_Ga=T,f=32,s={},v={},i={},c={},r={},C=1,q=2,m={};{}\x1b\\",
+                img.width, img.height, id, cols, rows, more, data));
+        } else {
+            s.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, data));
+        }
+    }
+    if chunks.is_empty() {
+        s.push_str(&format!("\x1b_Ga=T,f=32,s={},v={},i={},c={},r={},C=1,q=2;\x1b\\",
+            img.width, img.height, id, cols, rows));
+    }
+    s
+}
+
+/// Returns kitty graphics protocol sequence that deletes an image
+pub fn kitty_delete_sequence(id: ImageId) -> String {
+    format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", id)
+}
+
+/// Removes the cells covered by rectangles from spans. A span that crosses a
+/// rectangle is split in two
+pub fn spans_outside(spans: &[Span], rects: &[CellRect]) -> Vec<Span> {
+    let mut res: Vec<Span> = spans.to_vec();
+    for rect in rects {
+        let mut next = Vec::with_capacity(res.len());
+        for span in res {
+            if span.y < rect.top || span.y > rect.bottom || span.right < rect.left || span.left > rect.right {
+                next.push(span);
+                continue;
+            }
+            if span.left < rect.left {
+                next.push(Span{y: span.y, left: span.left, right: rect.left - 1});
+            }
+            if span.right > rect.right {
+                next.push(Span{y: span.y, left: rect.right + 1, right: span.right});
+            }
+        }
+        res = next;
+    }
+    res
+}
@@ -4,10 +4,27 @@ use cellbuf::*;
 /// Every type of virtual terminal must be able to write a buffer to real
 /// terminal, return terminal size, set and get terminal cursor position.
 /// All function returns `Result`, the second argument of `Result' is the
-/// string - a error message or empty string if everything is OK
+/// string - a error message or empty string if everything is OK.
+/// A terminal converts colors to the ones it can display depending on the
+/// output mode set by `set_output_mode`.
+/// `write` outputs only the changed parts of buffer rows listed in `spans`.
+/// `scroll` moves full-width rows on the terminal side: rows from `top` to
+/// `bottom` move up by `count` rows(down if `count` is negative), and the
+/// new rows are blank. A terminal that cannot scroll returns an error.
+/// `write_raw` sends a string to the terminal as is, e.g. an escape sequence
+/// that requests some terminal property.
+/// `write_control` sends a sequence that neither moves the cursor nor
+/// changes text attributes, e.g. the beginning of synchronized update, so a
+/// terminal that tracks its output state keeps it
 pub trait TerminalManager {
-    fn write(&self, buf: &CellBuf) -> Result<(), String>;
+    fn write(&self, buf: &CellBuf, spans: &[Span]) -> Result<(), String>;
+    fn set_output_mode(&mut self, mode: OutputMode) -> OutputMode;
     fn size(&self) -> Result<Point, String>;
     fn set_cursor_pos(&self, x: i16, y: i16) -> Result<(), String>;
     fn get_cursor_pos(&self) -> Result<CursorInfo, String>;
+    fn write_raw(&self, s: &str) -> Result<(), String>;
+    fn scroll(&self, top: i32, bottom: i32, count: i32) -> Result<(), String>;
+    fn write_control(&self, s: &str) -> Result<(), String> {
+        self.write_raw(s)
+    }
 }
@@ -0,0 +1,219 @@
+//! Layers are buffers drawn above the main terminal buffer: popups,
+//! dropdowns, tooltips, and dialogs. A layer can be moved, hidden, or
+//! removed, and what is underneath reappears without redrawing it.
+//! `LayerStack` composes the main buffer and visible layers into a buffer
+//! that keeps the picture between flushes, so only the cells of the picture
+//! that have changed are sent to the terminal
+use cellbuf::*;
+use common::*;
+
+/// A named buffer drawn above the main buffer at some offset
+#[derive(Debug)]
+pub struct Layer {
+    name: String,
+    z: i32,
+    /// Layer content
+    pub buffer: CellBuf,
+    /// Column of the layer left edge on the screen
+    pub x: i32,
+    /// Row of the layer top edge on the screen
+    pub y: i32,
+    /// Hidden layers are not drawn
+    pub visible: bool,
+    /// Cells equal to this one are transparent: the cells underneath are
+    /// displayed instead of them
+    pub transparent: Option<Cell>,
+}
+
+impl Layer {
+    /// Creates a visible opaque layer in the top left corner of the screen.
+    /// Layers with greater `z` are drawn above the ones with lower `z`, and
+    /// layers with the same `z` are drawn in order of addition
+    pub fn new(name: &str, width: i32, height: i32, z: i32) -> Layer {
+        Layer{
+            name: name.to_string(),
+            z,
+            buffer: CellBuf::new(width, height),
+            x: 0,
+            y: 0,
+            visible: true,
+            transparent: None,
+        }
+    }
+
+    /// Returns the layer name
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the layer z-order
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+}
+
+// Where and how a layer is drawn, used to detect moved and hidden layers
+#[derive(Debug,Clone,PartialEq)]
+struct Placement {
+    x: i32,
+    y: i32,
+    visible: bool,
+    transparent: Option<Cell>,
+}
+
+impl Placement {
+    fn of(layer: &Layer) -> Placement {
+        Placement{x: layer.x, y: layer.y, visible: layer.visible, transparent: layer.transparent.clone()}
+    }
+}
+
+/// Layers sorted by z-order and the composed picture
+#[derive(Debug,Default)]
+pub struct LayerStack {
+    // from bottom to top
+    layers: Vec<Layer>,
+    composite: Option<CellBuf>,
+    // the next picture is drawn here first, so the cells covered by layers
+    // do not look changed in the composed picture
+    scratch: Option<CellBuf>,
+    // a layer has been added or removed since the last flush
+    changed: bool,
+    // placements of the layers at the last flush
+    placements: Vec<Placement>,
+}
+
+impl LayerStack {
+    pub fn new() -> LayerStack {
+        LayerStack::default()
+    }
+
+    /// Adds a layer. A layer with the same name is replaced
+    pub fn add(&mut self, layer: Layer) {
+        self.layers.retain(|l| l.name != layer.name);
+        self.layers.push(layer);
+        self.layers.sort_by_key(|l| l.z);
+        self.changed = true;
+    }
+
+    /// Removes a layer and returns it, or `None` if there is no layer with
+    /// this name
+    pub fn remove(&mut self, name: &str) -> Option<Layer> {
+        let idx = self.layers.iter().position(|l| l.name == name)?;
+        self.changed = true;
+        Some(self.layers.remove(idx))
+    }
+
+    /// Returns a layer by its name
+    pub fn get(&self, name: &str) -> Option<&Layer> {
+        self.layers.iter().find(|l| l.name == name)
+    }
+
+    /// Returns a layer by its name to draw on it, move, or hide it
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|l| l.name == name)
+    }
+
+    /// Changes z-order of a layer. The layer is drawn above other layers
+    /// with the same `z`. Returns `false` if there is no layer with this name
+    pub fn set_z(&mut self, name: &str, z: i32) -> bool {
+        match self.remove(name) {
+            Some(mut layer) => {
+                layer.z = z;
+                self.add(layer);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns layer names from the bottom to the top
+    pub fn names(&self) -> Vec<&str> {
+        self.layers.iter().map(|l| l.name()).collect()
+    }
+
+    /// Returns `true` if the stack does not have any layer
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Returns `true` if the stack keeps the composed picture. It is created
+    /// when the first layer is added and dropped after the first
+    /// `clear_damage` without layers
+    pub fn is_composing(&self) -> bool {
+        self.composite.is_some()
+    }
+
+    /// Draws `base` and visible layers above it into the composed picture.
+    /// Scrolls of `base` are moved to the picture. Changed cells of the
+    /// picture are marked dirty. The first picture is dirty completely.
+    /// Nothing is drawn if neither `base` nor layers have changed
+    pub fn compose(&mut self, base: &mut CellBuf) {
+        if self.composite.is_none() {
+            if self.layers.is_empty() {
+                return;
+            }
+            let mut c = CellBuf::new(base.width, base.height);
+            c.invalidate();
+            self.composite = Some(c);
+        } else if !base.dirty && !self.dirty() {
+            return;
+        }
+
+        let scratch = self.scratch.get_or_insert_with(|| CellBuf::new(0, 0));
+        scratch.resize(base.width, base.height);
+        scratch.set_ambiguous_wide(base.ambiguous_wide());
+        let full = CellRect::with_size(0, 0, base.width, base.height);
+        scratch.blit(base, &full, 0, 0);
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            let rect = CellRect::with_size(0, 0, layer.buffer.width, layer.buffer.height);
+            match layer.transparent {
+                Some(ref key) => scratch.blit_transparent(&layer.buffer, &rect, layer.x, layer.y, key),
+                None => scratch.blit(&layer.buffer, &rect, layer.x, layer.y),
+            };
+        }
+        scratch.clear_damage();
+
+        if let Some(ref mut c) = self.composite {
+            c.resize(base.width, base.height);
+            c.set_ambiguous_wide(base.ambiguous_wide());
+            for sc in base.take_scrolls() {
+                c.scroll(sc.top, sc.bottom, sc.count);
+            }
+            c.blit(scratch, &full, 0, 0);
+        }
+    }
+
+    /// Returns the composed picture
+    pub fn composite(&self) -> Option<&CellBuf> {
+        self.composite.as_ref()
+    }
+
+    /// Returns the composed picture to send it to a terminal
+    pub fn composite_mut(&mut self) -> Option<&mut CellBuf> {
+        self.composite.as_mut()
+    }
+
+    /// Forgets changes of the layers and the composed picture. Called after
+    /// the picture is sent to terminal
+    pub fn clear_damage(&mut self) {
+        for layer in &mut self.layers {
+            layer.buffer.clear_damage();
+        }
+        self.changed = false;
+        self.placements = self.layers.iter().map(Placement::of).collect();
+        if self.layers.is_empty() {
+            self.composite = None;
+            self.scratch = None;
+        } else if let Some(ref mut c) = self.composite {
+            c.clear_damage();
+        }
+    }
+
+    /// Returns `true` if any layer has changed, moved, been hidden, shown,
+    /// added, or removed since the last flush
+    pub fn dirty(&self) -> bool {
+        self.changed
+            || self.layers.len() != self.placements.len()
+            || self.layers.iter().zip(&self.placements).any(|(l, p)| l.buffer.dirty || Placement::of(l) != *p)
+    }
+}
@@ -13,9 +13,52 @@
 //! * print a character
 //! * print a string horintally or vertically
 //! * print a horizontal or vertical line of the same character
+//! * print a string as a hyperlink(for terminals that support OSC 8)
+//! * draw frames and lines with box drawing characters that join where they
+//!   cross or touch
+//! * print text with several colors and styles given as markup, e.g.
+//!   `"[fg=red,bold]Error:[/] file [u]x.rs[/]"`, see module `markup`
+//! * print output of other tools that contains ANSI color sequences, see
+//!   module `ansi`
+//! * put paragraphs into a rectangle with word wrapping and alignment, see
+//!   module `textblock`
+//! * print Hebrew or Arabic text mixed with left-to-right text in visual
+//!   order, see module `bidi`
+//! * draw charts and small graphics with Braille dots or block elements, see
+//!   module `pixel`
+//! * show images with Sixel graphics or kitty graphics protocol, or with half
+//!   block characters on other terminals, see module `image`
 //! All functoion above come in two flavors: printing with current colors or
 //! using temporary one
 //!
+//! Besides colors, text may have a style: bold, dim, italic, blinking,
+//! reversed, strikethrough, overlined, and underlined with single, double,
+//! curly, dotted, or dashed line of its own color. A terminal that cannot
+//! display some styles just ignores them
+//!
+//! Colors can be set as 16 or 256 palette colors or true color RGB values.
+//! Before output the colors are converted to the nearest ones that the
+//! terminal supports. The terminal capability is detected automatically and
+//! can be overridden with `Terminal::set_output_mode`
+//!
+//! Instead of colors a theme style name can be used: every put function has
+//! a `themed` variant that takes a style name("border", "selection") or a
+//! style handle. Themes can be loaded from text files, see module `theme`
+//!
+//! The terminal can be asked for its default text and background colors and
+//! for palette colors, e.g. to find out whether the background is light or
+//! dark, see `Terminal::query_colors`
+//!
+//! Widgets can draw in their own regions with local coordinates, and
+//! everything outside a region is clipped, see `Terminal::canvas`
+//!
+//! Popups and dialogs can be drawn on layers above the main buffer, so they
+//! can be moved or hidden without redrawing what is underneath, see
+//! `Terminal::layers_mut`
+//!
+//! On terminals that support synchronized updates every flush can be
+//! displayed at once without tearing, see `Terminal::set_sync_output`
+//!
 //! Reading functions:
 //! * read value of one cell of the terminal (character with its attributes)
 //! * read the entire terminal content
@@ -65,16 +108,43 @@
 extern crate iota;
 #[macro_use]
 extern crate lazy_static;
+extern crate unicode_bidi;
+extern crate unicode_segmentation;
 extern crate unicode_width;
 
+pub mod ansi;
+pub mod bidi;
+pub mod boxdraw;
 pub mod common;
+pub mod canvas;
 pub mod cellbuf;
+pub mod color;
+pub mod escape;
+pub mod image;
+pub mod layer;
+pub mod markup;
+pub mod pixel;
+pub mod query;
 pub mod terminal;
+pub mod textblock;
+pub mod theme;
 pub mod intf;
 
 #[cfg(windows)] mod term_windows;
 
+pub use bidi::*;
+pub use boxdraw::*;
 pub use common::*;
+pub use canvas::*;
 pub use cellbuf::*;
+pub use color::*;
+pub use escape::*;
+pub use image::*;
+pub use layer::*;
+pub use markup::*;
+pub use pixel::*;
+pub use query::*;
 pub use terminal::*;
+pub use textblock::*;
+pub use theme::*;
 pub use intf::*;
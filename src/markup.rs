@@ -0,0 +1,256 @@
+//! Text with several colors and styles printed with one call. Styled text
+//! is made of spans: parts of text with their attributes. It can be built
+//! span by span with `StyledText` methods or parsed from markup:
+//! ```text
+//! [fg=red,bold]Error:[/] file [u]x.rs[/] not found
+//! ```
+//! A tag `[...]` contains a comma separated list of attributes that are
+//! applied to the text up to the matching `[/]`. Tags can be nested.
+//! Attributes:
+//! * `fg=color`, `bg=color`, `ul=color` - text, background, and underline
+//!   colors in the format of `parse_color`
+//! * a style name as in theme files, e.g. `bold` or `curly-underline`, or
+//!   a short one: `b` - bold, `d` - dim, `i` - italic, `u` - underline,
+//!   `r` - reverse, `s` - strikethrough
+//! * a style name with `-` in front of it removes the style
+//!
+//! `[[` is a literal `[`. A tag with unknown attributes is printed as text,
+//! and `[/]` without an open tag is ignored
+use color::*;
+use common::*;
+use canvas::*;
+use theme::*;
+
+/// Attributes of a span relative to the current attributes of the place
+/// where the text is printed. `None` colors are not changed
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct TextAttrs {
+    pub fg: Option<Attribute>,
+    pub bg: Option<Attribute>,
+    pub underline_color: Option<Attribute>,
+    /// Style flags that are added
+    pub add: StyleAttr,
+    /// Style flags that are removed
+    pub remove: StyleAttr,
+}
+
+impl TextAttrs {
+    /// Returns the attributes with a new text color
+    pub fn with_fg(self, c: Attribute) -> TextAttrs {
+        TextAttrs{fg: Some(c), ..self}
+    }
+
+    /// Returns the attributes with a new background color
+    pub fn with_bg(self, c: Attribute) -> TextAttrs {
+        TextAttrs{bg: Some(c), ..self}
+    }
+
+    /// Returns the attributes that also add style flags
+    pub fn with_style(self, attrs: StyleAttr) -> TextAttrs {
+        let mut res = self;
+        if attrs & STYLE_UNDERLINE_MASK != 0 {
+            res.add &= !STYLE_UNDERLINE_MASK;
+        }
+        res.add |= attrs;
+        res.remove &= !attrs;
+        res
+    }
+
+    /// Returns the attributes that also remove style flags
+    pub fn without_style(self, attrs: StyleAttr) -> TextAttrs {
+        let mut res = self;
+        if attrs & STYLE_UNDERLINE_MASK != 0 {
+            res.add &= !STYLE_UNDERLINE_MASK;
+            res.remove |= STYLE_UNDERLINE_MASK;
+        }
+        res.add &= !attrs;
+        res.remove |= attrs & !STYLE_UNDERLINE_MASK;
+        res
+    }
+
+    /// Applies the attributes of a nested span
+    pub fn merge(self, inner: TextAttrs) -> TextAttrs {
+        let mut res = self.without_style(inner.remove).with_style(inner.add);
+        res.fg = inner.fg.or(self.fg);
+        res.bg = inner.bg.or(self.bg);
+        res.underline_color = inner.underline_color.or(self.underline_color);
+        res
+    }
+
+    /// Returns the colors and style of a span printed with given current
+    /// colors and style
+    pub fn apply(&self, fg: Attribute, bg: Attribute, style: Style) -> (Attribute, Attribute, Style) {
+        let mut st = style;
+        if self.add & STYLE_UNDERLINE_MASK != 0 {
+            st.attrs &= !STYLE_UNDERLINE_MASK;
+        }
+        st.attrs = (st.attrs & !self.remove) | self.add;
+        if let Some(c) = self.underline_color {
+            st.underline_color = c;
+        }
+        (self.fg.unwrap_or(fg), self.bg.unwrap_or(bg), st)
+    }
+}
+
+/// Text made of spans with their own attributes
+#[derive(Debug,Clone,PartialEq,Default)]
+pub struct StyledText {
+    spans: Vec<(String, TextAttrs)>,
+}
+
+// Parses a tag without brackets, returns `None` if it is not a valid tag
+fn parse_tag(tag: &str) -> Option<TextAttrs> {
+    let mut attrs = TextAttrs::default();
+    for word in tag.split(',').map(|w| w.trim()) {
+        if let Some(eq) = word.find('=') {
+            let clr = parse_color(&word[eq + 1..])?;
+            match word[..eq].trim() {
+                "fg" => attrs.fg = Some(clr),
+                "bg" => attrs.bg = Some(clr),
+                "ul" => attrs.underline_color = Some(clr),
+                _ => return None,
+            }
+        } else if let Some(name) = word.strip_prefix('-') {
+            attrs = attrs.without_style(parse_markup_style(name)?);
+        } else {
+            attrs = attrs.with_style(parse_markup_style(word)?);
+        }
+    }
+    Some(attrs)
+}
+
+fn parse_markup_style(s: &str) -> Option<StyleAttr> {
+    match s {
+        "b" => Some(STYLE_BOLD),
+        "d" => Some(STYLE_DIM),
+        "i" => Some(STYLE_ITALIC),
+        "u" => Some(STYLE_UNDERLINE),
+        "r" => Some(STYLE_REVERSE),
+        "s" => Some(STYLE_STRIKETHROUGH),
+        _ => parse_style_attr(s),
+    }
+}
+
+/// Escapes text so it is printed as is when it is a part of markup
+pub fn escape_markup(s: &str) -> String {
+    s.replace('[', "[[")
+}
+
+impl StyledText {
+    /// Creates empty text
+    pub fn new() -> StyledText {
+        StyledText::default()
+    }
+
+    /// Parses markup, see module description for its format
+    pub fn from_markup(markup: &str) -> StyledText {
+        let mut res = StyledText::new();
+        // attributes of all open tags, every item includes all the previous
+        let mut stack = vec![TextAttrs::default()];
+        let mut text = String::new();
+        let mut rest = markup;
+
+        while let Some(pos) = rest.find('[') {
+            text.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            if let Some(r) = rest.strip_prefix('[') {
+                text.push('[');
+                rest = r;
+                continue;
+            }
+            let end = match rest.find(']') {
+                Some(end) => end,
+                None => {
+                    text.push('[');
+                    break;
+                },
+            };
+            let tag = &rest[..end];
+            let top = stack[stack.len() - 1];
+            if tag.trim() == "/" {
+                if stack.len() > 1 {
+                    res.push(&text, top);
+                    text.clear();
+                    stack.pop();
+                }
+            } else if let Some(attrs) = parse_tag(tag) {
+                res.push(&text, top);
+                text.clear();
+                stack.push(top.merge(attrs));
+            } else {
+                text.push('[');
+                text.push_str(tag);
+                text.push(']');
+            }
+            rest = &rest[end + 1..];
+        }
+        text.push_str(rest);
+        res.push(&text, stack[stack.len() - 1]);
+        res
+    }
+
+    // Appends a span, joins it with the last one if they have the same
+    // attributes
+    fn push(&mut self, s: &str, attrs: TextAttrs) {
+        if s.is_empty() {
+            return;
+        }
+        if let Some(last) = self.spans.last_mut() {
+            if last.1 == attrs {
+                last.0.push_str(s);
+                return;
+            }
+        }
+        self.spans.push((s.to_string(), attrs));
+    }
+
+    /// Appends a span printed with the current attributes
+    pub fn text(mut self, s: &str) -> StyledText {
+        self.push(s, TextAttrs::default());
+        self
+    }
+
+    /// Appends a span with its own attributes
+    pub fn span(mut self, s: &str, attrs: TextAttrs) -> StyledText {
+        self.push(s, attrs);
+        self
+    }
+
+    /// Returns all spans
+    pub fn spans(&self) -> &[(String, TextAttrs)] {
+        &self.spans
+    }
+
+    /// Returns the text without attributes
+    pub fn plain_text(&self) -> String {
+        self.spans.iter().map(|s| s.0.as_str()).collect()
+    }
+}
+
+impl<'a> Canvas<'a> {
+    /// Puts styled text. Attributes of spans are applied to the current
+    /// attributes. Returns the number of columns the text takes including
+    /// its clipped part, so the next text can be put right after it
+    pub fn put_styled(&mut self, x: i32, y: i32, text: &StyledText) -> i32 {
+        let (fg, bg, style) = (self.get_foreground(), self.get_background(), self.get_style());
+        let mut pos = x;
+        for (s, attrs) in text.spans() {
+            let (f, b, st) = attrs.apply(fg, bg, style);
+            self.set_foreground(f);
+            self.set_background(b);
+            self.set_style(st);
+            self.put_string(pos, y, s.as_str());
+            pos += self.text_width(s);
+        }
+        self.set_foreground(fg);
+        self.set_background(bg);
+        self.set_style(style);
+        pos - x
+    }
+
+    /// Parses markup and puts the styled text. Returns the number of columns
+    /// the text takes including its clipped part
+    pub fn put_markup(&mut self, x: i32, y: i32, markup: &str) -> i32 {
+        self.put_styled(x, y, &StyledText::from_markup(markup))
+    }
+}
@@ -0,0 +1,311 @@
+//! Small graphics with characters: every cell displays a few pixels as
+//! Braille dots or block elements. A pixel is either clear or has a color.
+//! Braille cells have only one color, so all their dots take the color of
+//! most pixels. Block cells have two colors: the text color for one part
+//! and the background color for the other one
+use std::char;
+use std::cmp;
+
+use cellbuf::*;
+use common::*;
+
+// Bits of Braille dots by pixel position in a cell, row by row
+const BRAILLE_DOTS: [u32; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+const BRAILLE_BASE: u32 = 0x2800;
+// Block elements by set pixels: bit 0 - top left, 1 - top right,
+// 2 - bottom left, 3 - bottom right
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+// The same for a cell with one column of pixels: bit 0 - top, 1 - bottom
+const HALF_BLOCKS: [char; 4] = [' ', '▀', '▄', '█'];
+
+/// A grid of pixels drawn into a buffer as characters
+#[derive(Debug,Clone,PartialEq)]
+pub struct PixelCanvas {
+    mode: PixelMode,
+    width: i32,
+    height: i32,
+    pixels: Vec<Option<Attribute>>,
+    color: Attribute,
+    background: Attribute,
+}
+
+// Returns the color of most pixels, the first one of equally frequent
+fn most_frequent(colors: &[Attribute]) -> Option<Attribute> {
+    let mut best = None;
+    let mut best_count = 0;
+    for (i, c) in colors.iter().enumerate() {
+        if colors[..i].contains(c) {
+            continue;
+        }
+        let count = colors.iter().filter(|&x| x == c).count();
+        if count > best_count {
+            best = Some(*c);
+            best_count = count;
+        }
+    }
+    best
+}
+
+impl PixelCanvas {
+    /// Creates a clear canvas that takes `cols` x `rows` cells. `mode` is
+    /// one of `PIXEL_*` values. Pixels are drawn with the default color
+    pub fn new(mode: PixelMode, cols: i32, rows: i32) -> PixelCanvas {
+        let (cw, ch) = PixelCanvas::cell_pixels(mode);
+        let (width, height) = (cmp::max(cols, 0) * cw, cmp::max(rows, 0) * ch);
+        PixelCanvas{
+            mode,
+            width,
+            height,
+            pixels: vec![None; (width * height) as usize],
+            color: COLOR_DEFAULT,
+            background: COLOR_DEFAULT,
+        }
+    }
+
+    /// Returns the number of pixels in a cell horizontally and vertically
+    pub fn cell_pixels(mode: PixelMode) -> (i32, i32) {
+        match mode {
+            PIXEL_BRAILLE => (2, 4),
+            PIXEL_QUADRANT => (2, 2),
+            _ => (1, 2),
+        }
+    }
+
+    /// Returns the canvas size in pixels
+    pub fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    /// Returns the canvas size in cells
+    pub fn cell_size(&self) -> (i32, i32) {
+        let (cw, ch) = PixelCanvas::cell_pixels(self.mode);
+        (self.width / cw, self.height / ch)
+    }
+
+    /// Sets the color of pixels drawn after the call
+    pub fn set_color(&mut self, c: Attribute) {
+        self.color = c;
+    }
+
+    /// Sets the background color of cells
+    pub fn set_background(&mut self, c: Attribute) {
+        self.background = c;
+    }
+
+    /// Clears all pixels
+    pub fn clear(&mut self) {
+        for p in &mut self.pixels {
+            *p = None;
+        }
+    }
+
+    // Returns the index of a pixel or `None` if it is outside the canvas
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            None
+        } else {
+            Some((y * self.width + x) as usize)
+        }
+    }
+
+    /// Sets a pixel with the current color. Pixels outside the canvas are
+    /// ignored by all drawing functions
+    pub fn set_pixel(&mut self, x: i32, y: i32) {
+        let c = self.color;
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = Some(c);
+        }
+    }
+
+    /// Clears a pixel
+    pub fn clear_pixel(&mut self, x: i32, y: i32) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = None;
+        }
+    }
+
+    /// Returns the color of a pixel or `None` if the pixel is clear or
+    /// outside the canvas
+    pub fn get_pixel(&self, x: i32, y: i32) -> Option<Attribute> {
+        self.index(x, y).and_then(|i| self.pixels[i])
+    }
+
+    // Sets a horizontal run of pixels
+    fn span(&mut self, left: i32, right: i32, y: i32) {
+        for x in cmp::max(left, 0)..cmp::min(right + 1, self.width) {
+            self.set_pixel(x, y);
+        }
+    }
+
+    /// Draws a line between two pixels including both of them
+    pub fn draw_line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32) {
+        let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+        let (sx, sy) = (if x0 < x1 { 1 } else { -1 }, if y0 < y1 { 1 } else { -1 });
+        let (mut x, mut y) = (x0, y0);
+        let mut err = dx + dy;
+        loop {
+            self.set_pixel(x, y);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    /// Draws the outline of a rectangle given in pixels
+    pub fn draw_rect(&mut self, rect: &CellRect) {
+        if rect.is_empty() {
+            return;
+        }
+        self.draw_line(rect.left, rect.top, rect.right, rect.top);
+        self.draw_line(rect.left, rect.bottom, rect.right, rect.bottom);
+        self.draw_line(rect.left, rect.top, rect.left, rect.bottom);
+        self.draw_line(rect.right, rect.top, rect.right, rect.bottom);
+    }
+
+    /// Sets all pixels of a rectangle given in pixels
+    pub fn fill_rect(&mut self, rect: &CellRect) {
+        for y in cmp::max(rect.top, 0)..cmp::min(rect.bottom + 1, self.height) {
+            self.span(rect.left, rect.right, y);
+        }
+    }
+
+    /// Draws a circle with the center at pixel (`cx`, `cy`)
+    pub fn draw_circle(&mut self, cx: i32, cy: i32, radius: i32) {
+        if radius < 0 {
+            return;
+        }
+        let (mut x, mut y) = (radius, 0);
+        let mut err = 1 - radius;
+        while x >= y {
+            for &(px, py) in &[(x, y), (y, x), (-y, x), (-x, y), (-x, -y), (-y, -x), (y, -x), (x, -y)] {
+                self.set_pixel(cx + px, cy + py);
+            }
+            y += 1;
+            if err < 0 {
+                err += 2 * y + 1;
+            } else {
+                x -= 1;
+                err += 2 * (y - x) + 1;
+            }
+        }
+    }
+
+    /// Draws a filled circle with the center at pixel (`cx`, `cy`)
+    pub fn fill_circle(&mut self, cx: i32, cy: i32, radius: i32) {
+        if radius < 0 {
+            return;
+        }
+        let r2 = radius * radius + radius;
+        for dy in -radius..radius + 1 {
+            let mut dx = 0;
+            while (dx + 1) * (dx + 1) + dy * dy <= r2 {
+                dx += 1;
+            }
+            self.span(cx - dx, cx + dx, cy + dy);
+        }
+    }
+
+    /// Draws lines between consecutive points and between the last and the
+    /// first point
+    pub fn draw_polygon(&mut self, points: &[(i32, i32)]) {
+        for (i, &(x0, y0)) in points.iter().enumerate() {
+            let (x1, y1) = points[(i + 1) % points.len()];
+            self.draw_line(x0, y0, x1, y1);
+        }
+    }
+
+    /// Sets pixels inside a polygon. Pixel centers are tested with even-odd
+    /// rule, so a self-intersecting polygon may have holes
+    pub fn fill_polygon(&mut self, points: &[(i32, i32)]) {
+        if points.len() < 3 {
+            self.draw_polygon(points);
+            return;
+        }
+        let top = cmp::max(points.iter().map(|p| p.1).min().unwrap_or(0), 0);
+        let bottom = cmp::min(points.iter().map(|p| p.1).max().unwrap_or(0), self.height - 1);
+        for y in top..bottom + 1 {
+            let sy = y as f64 + 0.5;
+            let mut xs: Vec<f64> = Vec::new();
+            for (i, &(x0, y0)) in points.iter().enumerate() {
+                let (x1, y1) = points[(i + 1) % points.len()];
+                let (fy0, fy1) = (y0 as f64 + 0.5, y1 as f64 + 0.5);
+                if (fy0 <= sy && sy < fy1) || (fy1 <= sy && sy < fy0) {
+                    let t = (sy - fy0) / (fy1 - fy0);
+                    xs.push(x0 as f64 + t * (x1 - x0) as f64);
+                }
+            }
+            xs.sort_by(|a, b| a.partial_cmp(b).unwrap_or(cmp::Ordering::Equal));
+            for pair in xs.chunks(2) {
+                if pair.len() == 2 {
+                    self.span(pair[0].ceil() as i32, pair[1].floor() as i32, y);
+                }
+            }
+        }
+    }
+
+    // Returns the cell that displays pixels of a cell at column `col` and
+    // row `row` of the canvas
+    fn cell(&self, col: i32, row: i32) -> Cell {
+        let (cw, ch) = PixelCanvas::cell_pixels(self.mode);
+        let mut pixels = Vec::with_capacity((cw * ch) as usize);
+        for dy in 0..ch {
+            for dx in 0..cw {
+                pixels.push(self.get_pixel(col * cw + dx, row * ch + dy));
+            }
+        }
+        let colors: Vec<Attribute> = pixels.iter().filter_map(|&p| p).collect();
+        let blank = Cell{ch: ' ', fg: COLOR_DEFAULT, bg: self.background, style: Style::default(),
+            link: NO_LINK, combining: Combining::None};
+        let fg = match most_frequent(&colors) {
+            Some(c) => c,
+            None => return blank,
+        };
+
+        if self.mode == PIXEL_BRAILLE {
+            let bits = pixels.iter().zip(BRAILLE_DOTS.iter())
+                .filter(|&(p, _)| p.is_some())
+                .fold(0, |acc, (_, bit)| acc | bit);
+            let ch = char::from_u32(BRAILLE_BASE + bits).unwrap_or(' ');
+            return Cell{ch, fg, ..blank};
+        }
+
+        // pixels of other colors and clear ones are displayed with background
+        let rest: Vec<Attribute> = pixels.iter()
+            .filter(|&&p| p != Some(fg))
+            .map(|&p| p.unwrap_or(self.background))
+            .collect();
+        let bg = most_frequent(&rest).unwrap_or(self.background);
+        let bits = pixels.iter().enumerate()
+            .filter(|&(_, &p)| p == Some(fg))
+            .fold(0, |acc, (i, _)| acc | 1 << i);
+        let ch = if self.mode == PIXEL_QUADRANT { QUADRANTS[bits] } else { HALF_BLOCKS[bits] };
+        Cell{ch, fg, bg, ..blank}
+    }
+
+    /// Draws the canvas into a rectangle of a buffer. The top left pixel is
+    /// drawn in the top left cell of the rectangle, and the parts of the
+    /// canvas and the rectangle that do not overlap are not changed.
+    /// Retuns `false` if nothing is drawn
+    pub fn render(&self, buf: &mut CellBuf, rect: &CellRect) -> bool {
+        let (cols, rows) = self.cell_size();
+        let mut res = false;
+        for row in 0..cmp::min(rows, rect.bottom - rect.top + 1) {
+            for col in 0..cmp::min(cols, rect.right - rect.left + 1) {
+                let c = self.cell(col, row);
+                res = buf.set_cell(rect.left + col, rect.top + row, c) || res;
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,350 @@
+//! Queries of terminal colors: default text and background colors(OSC 10
+//! and OSC 11) and palette colors(OSC 4), and queries of private modes
+//! (DECRQM), device attributes, cell size, and kitty graphics protocol
+//! support. A
+//! terminal sends replies to the input stream, so `ReplyParser`
+//! picks them out of incoming events and returns all other events untouched
+use color::*;
+use common::*;
+
+// Replies longer than this are not color replies
+const MAX_REPLY_LEN: usize = 64;
+
+/// A color that can be requested from a terminal
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ColorQuery {
+    /// Default text color
+    Foreground,
+    /// Default background color
+    Background,
+    /// A color of 256-color palette
+    Palette(u8),
+}
+
+impl ColorQuery {
+    /// Returns escape sequence that requests the color from a terminal
+    pub fn sequence(&self) -> String {
+        match *self {
+            ColorQuery::Foreground => String::from("\x1b]10;?\x1b\\"),
+            ColorQuery::Background => String::from("\x1b]11;?\x1b\\"),
+            ColorQuery::Palette(idx) => format!("\x1b]4;{};?\x1b\\", idx),
+        }
+    }
+}
+
+/// Color reported by a terminal
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct ColorInfo {
+    /// True color value, see `rgb`
+    pub color: Attribute,
+    /// `true` if the color is light: it is a light background or a text color
+    /// for dark backgrounds
+    pub light: bool,
+}
+
+impl ColorInfo {
+    /// Creates color information from RGB components
+    pub fn new(r: u8, g: u8, b: u8) -> ColorInfo {
+        let luma = (299 * r as u32 + 587 * g as u32 + 114 * b as u32) / 1000;
+        ColorInfo{
+            color: rgb(r, g, b),
+            light: luma >= 128,
+        }
+    }
+}
+
+/// Primary device attributes request. Almost every terminal replies to it,
+/// so sending it after other queries shows when all replies have arrived
+pub const DEVICE_ATTRIBUTES_QUERY: &str = "\x1b[c";
+
+/// Cell size request(XTWINOPS 16). A terminal replies with the size of a
+/// character cell in pixels
+pub const CELL_SIZE_QUERY: &str = "\x1b[16t";
+
+/// Private mode that makes a terminal hold the screen update until the
+/// application finishes drawing the frame
+pub const MODE_SYNC_OUTPUT: u16 = 2026;
+
+/// Returns escape sequence that requests the state of a private mode(DECRQM)
+pub fn mode_query_sequence(mode: u16) -> String {
+    format!("\x1b[?{}$p", mode)
+}
+
+/// State of a private mode reported by a terminal
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub enum ModeState {
+    /// The terminal does not know the mode
+    NotRecognized,
+    Set,
+    Reset,
+    PermanentlySet,
+    PermanentlyReset,
+}
+
+impl ModeState {
+    fn from_code(code: u8) -> Option<ModeState> {
+        match code {
+            0 => Some(ModeState::NotRecognized),
+            1 => Some(ModeState::Set),
+            2 => Some(ModeState::Reset),
+            3 => Some(ModeState::PermanentlySet),
+            4 => Some(ModeState::PermanentlyReset),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the mode can be turned on and off
+    pub fn is_supported(&self) -> bool {
+        *self == ModeState::Set || *self == ModeState::Reset
+    }
+}
+
+// Converts a color component of 1-4 hex digits to 8-bit value
+fn parse_component(s: &str) -> Option<u8> {
+    if s.is_empty() || s.len() > 4 || !s.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let v = u32::from_str_radix(s, 16).ok()?;
+    let max = (1u32 << (4 * s.len())) - 1;
+    Some(((v * 255 + max / 2) / max) as u8)
+}
+
+/// Parses X11 color specification that terminals use in replies:
+/// `rgb:R/G/B` with 1-4 hex digits per component, or `#RRGGBB`
+pub fn parse_color_spec(s: &str) -> Option<(u8, u8, u8)> {
+    if let Some(spec) = s.strip_prefix("rgb:") {
+        let parts: Vec<&str> = spec.split('/').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        return Some((parse_component(parts[0])?, parse_component(parts[1])?, parse_component(parts[2])?));
+    }
+    if let Some(hex) = s.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        return Some((parse_component(&hex[..2])?, parse_component(&hex[2..4])?, parse_component(&hex[4..])?));
+    }
+    None
+}
+
+// Parses the body of OSC reply(without leading ESC ] and terminator)
+fn parse_reply(body: &str) -> Option<(ColorQuery, ColorInfo)> {
+    let parts: Vec<&str> = body.split(';').collect();
+    let (q, spec) = match parts.as_slice() {
+        ["10", spec] => (ColorQuery::Foreground, *spec),
+        ["11", spec] => (ColorQuery::Background, *spec),
+        ["4", idx, spec] => (ColorQuery::Palette(idx.parse::<u8>().ok()?), *spec),
+        _ => return None,
+    };
+    let (r, g, b) = parse_color_spec(spec)?;
+    Some((q, ColorInfo::new(r, g, b)))
+}
+
+// Parses the body of kitty graphics reply: `Gi=31;OK`
+fn parse_graphics_reply(body: &str) -> Option<(u32, String)> {
+    if !body.starts_with('G') {
+        return None;
+    }
+    let sep = body.find(';')?;
+    let id = body[1..sep].split(',')
+        .find(|kv| kv.starts_with("i="))
+        .and_then(|kv| kv[2..].parse::<u32>().ok())?;
+    Some((id, body[sep + 1..].to_string()))
+}
+
+// Parses mode state reply(without leading ESC [ ? and trailing $ y)
+fn parse_mode_reply(body: &str) -> Option<(u16, ModeState)> {
+    let mut parts = body.split(';');
+    let mode = parts.next()?.parse::<u16>().ok()?;
+    let state = ModeState::from_code(parts.next()?.parse::<u8>().ok()?)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((mode, state))
+}
+
+/// Picks color, mode, device attributes, and cell size replies out of
+/// terminal input events. Every event should be passed to `feed`. Events
+/// that start an escape sequence are held until it is clear whether the
+/// sequence is a reply. Complete OSC sequences are always swallowed, so they
+/// never appear as key presses
+#[derive(Debug,Default)]
+pub struct ReplyParser {
+    events: Vec<Event>,
+    text: String,
+    replies: Vec<(ColorQuery, ColorInfo)>,
+    modes: Vec<(u16, ModeState)>,
+    // number of device attributes replies that are not taken yet
+    device_attributes: usize,
+    device_attribute_params: Vec<u32>,
+    graphics: Vec<(u32, String)>,
+    cell_size: Option<(i32, i32)>,
+}
+
+impl ReplyParser {
+    pub fn new() -> ReplyParser {
+        ReplyParser::default()
+    }
+
+    /// Processes an input event. Returns the events that are not a part of
+    /// any reply and must be processed as usual
+    pub fn feed(&mut self, ev: Event) -> Vec<Event> {
+        let ch = match ev {
+            Event::Key(KEY_ESC, _, _) => '\x1b',
+            Event::Key(_, c, _) if c != '\0' => c,
+            _ => {
+                let mut out = self.flush();
+                out.push(ev);
+                return out;
+            },
+        };
+
+        if self.text.is_empty() {
+            if ch != '\x1b' {
+                return vec![ev];
+            }
+        } else if self.text.len() == 1 && ch != ']' && ch != '[' && ch != '_' {
+            // neither OSC, CSI, nor APC: return held ESC and start again
+            // from this event
+            let mut out = self.flush();
+            out.extend(self.feed(ev));
+            return out;
+        }
+
+        if self.text.starts_with("\x1b[") {
+            return self.feed_csi(ch, ev);
+        }
+
+        self.text.push(ch);
+        self.events.push(ev);
+
+        let body_len = if self.text.ends_with('\x07') {
+            Some(self.text.len() - 1)
+        } else if self.text.len() > 3 && self.text.ends_with("\x1b\\") {
+            Some(self.text.len() - 2)
+        } else {
+            None
+        };
+        if let Some(len) = body_len {
+            if self.text.starts_with("\x1b_") {
+                if let Some(reply) = parse_graphics_reply(&self.text[2..len]) {
+                    self.graphics.push(reply);
+                }
+            } else if let Some(reply) = parse_reply(&self.text[2..len]) {
+                self.replies.push(reply);
+            }
+            self.text.clear();
+            self.events.clear();
+        } else if self.text.len() > MAX_REPLY_LEN {
+            return self.flush();
+        }
+
+        Vec::new()
+    }
+
+    // Processes the next character of CSI reply: `ESC [ ? Ps ; Ps $ y` for
+    // a mode state, `ESC [ ? Ps ; ... c` for device attributes, or
+    // `ESC [ 6 ; height ; width t` for cell size
+    fn feed_csi(&mut self, ch: char, ev: Event) -> Vec<Event> {
+        let len = self.text.len();
+        let private = self.text.starts_with("\x1b[?");
+        let after_dollar = self.text.ends_with('$');
+        let valid = match ch {
+            '?' => len == 2,
+            '0'..='9' | ';' => !after_dollar,
+            'c' => private && len > 2 && !after_dollar,
+            '$' => private && len > 3 && !after_dollar,
+            'y' => after_dollar,
+            't' => !private && len > 2,
+            _ => false,
+        };
+        if !valid {
+            let mut out = self.flush();
+            out.extend(self.feed(ev));
+            return out;
+        }
+
+        self.text.push(ch);
+        self.events.push(ev);
+        match ch {
+            'y' => {
+                if let Some(reply) = parse_mode_reply(&self.text[3..len - 1]) {
+                    self.modes.push(reply);
+                }
+            },
+            'c' => {
+                self.device_attributes += 1;
+                self.device_attribute_params = self.text[3..len].split(';')
+                    .filter_map(|p| p.parse::<u32>().ok())
+                    .collect();
+            },
+            't' => {
+                let parts: Vec<Option<i32>> = self.text[2..len].split(';').map(|p| p.parse::<i32>().ok()).collect();
+                // other window reports are swallowed as well
+                if let [Some(6), Some(h), Some(w)] = parts.as_slice() {
+                    self.cell_size = Some((*w, *h));
+                }
+            },
+            _ => {
+                if self.text.len() > MAX_REPLY_LEN {
+                    return self.flush();
+                }
+                return Vec::new();
+            },
+        }
+        self.text.clear();
+        self.events.clear();
+        Vec::new()
+    }
+
+    /// Returns held events of an incomplete sequence, e.g. a single ESC
+    /// key press, and resets the parser
+    pub fn flush(&mut self) -> Vec<Event> {
+        self.text.clear();
+        self.events.drain(..).collect()
+    }
+
+    /// Returns `true` if the parser holds events of an incomplete sequence
+    pub fn is_holding(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    /// Returns all replies parsed since the previous call
+    pub fn take_replies(&mut self) -> Vec<(ColorQuery, ColorInfo)> {
+        self.replies.drain(..).collect()
+    }
+
+    /// Returns all mode state replies parsed since the previous call
+    pub fn take_mode_replies(&mut self) -> Vec<(u16, ModeState)> {
+        self.modes.drain(..).collect()
+    }
+
+    /// Returns `true` if a device attributes reply has been parsed and not
+    /// taken yet. Every call takes one reply
+    pub fn take_device_attributes(&mut self) -> bool {
+        if self.device_attributes == 0 {
+            return false;
+        }
+        self.device_attributes -= 1;
+        true
+    }
+
+    /// Returns parameters of the last device attributes reply: the terminal
+    /// class and supported features, e.g. 4 means Sixel graphics
+    pub fn device_attribute_params(&self) -> &[u32] {
+        &self.device_attribute_params
+    }
+
+    /// Returns the cell size in pixels(width and height) from the last cell
+    /// size reply parsed since the previous call
+    pub fn take_cell_size(&mut self) -> Option<(i32, i32)> {
+        self.cell_size.take()
+    }
+
+    /// Returns all kitty graphics protocol replies parsed since the previous
+    /// call: image ID and message, "OK" or an error
+    pub fn take_graphics_replies(&mut self) -> Vec<(u32, String)> {
+        self.graphics.drain(..).collect()
+    }
+}
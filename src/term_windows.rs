@@ -10,14 +10,19 @@ use self::winapi::{COORD, SMALL_RECT, CHAR_INFO, CONSOLE_SCREEN_BUFFER_INFO};
 use self::winapi::{FOREGROUND_RED, FOREGROUND_GREEN, FOREGROUND_BLUE};
 use self::winapi::{BACKGROUND_RED, BACKGROUND_GREEN, BACKGROUND_BLUE};
 use self::winapi::{FOREGROUND_INTENSITY, BACKGROUND_INTENSITY};
+use self::winapi::{COMMON_LVB_UNDERSCORE, COMMON_LVB_GRID_HORIZONTAL};
+use self::winapi::{COMMON_LVB_LEADING_BYTE, COMMON_LVB_TRAILING_BYTE};
 
 use common::*;
 use cellbuf::*;
+use color::*;
 use intf::*;
 
 const MOUSE_LMB: DWORD = 0x1;
 const MOUSE_RMB: DWORD = 0x2;
 const MOUSE_MMB: DWORD = 0x4 | 0x8 | 0x10;
+// console mode flag that enables escape sequences(Windows 10 and newer)
+const ENABLE_VIRTUAL_TERMINAL_PROCESSING: DWORD = 0x4;
 
 /// Implemetation of Windows terminal
 #[derive(Debug)]
@@ -26,6 +31,7 @@ pub struct WinTerminal {
     pub tx: Sender<i32>,
     stdin_worker: Option<thread::JoinHandle<()>>,
     input_mode: InputMode,
+    output_mode: OutputMode,
     event_chan: SyncSender<Event>,
 }
 
@@ -53,6 +59,14 @@ lazy_static! {
             BACKGROUND_RED | BACKGROUND_BLUE,
             BACKGROUND_BLUE | BACKGROUND_GREEN,
             BACKGROUND_BLUE | BACKGROUND_GREEN | BACKGROUND_RED,
+            BACKGROUND_INTENSITY,
+            BACKGROUND_INTENSITY | BACKGROUND_RED,
+            BACKGROUND_INTENSITY | BACKGROUND_GREEN,
+            BACKGROUND_INTENSITY | BACKGROUND_RED | BACKGROUND_GREEN,
+            BACKGROUND_INTENSITY | BACKGROUND_BLUE,
+            BACKGROUND_INTENSITY | BACKGROUND_RED | BACKGROUND_BLUE,
+            BACKGROUND_INTENSITY | BACKGROUND_BLUE | BACKGROUND_GREEN,
+            BACKGROUND_INTENSITY | BACKGROUND_BLUE | BACKGROUND_GREEN | BACKGROUND_RED,
         ]
     };
     static ref COLOR_TABLE_FG: Vec<DWORD> = {
@@ -66,30 +80,63 @@ lazy_static! {
             FOREGROUND_RED | FOREGROUND_BLUE,
             FOREGROUND_BLUE | FOREGROUND_GREEN,
             FOREGROUND_BLUE | FOREGROUND_GREEN | FOREGROUND_RED,
+            FOREGROUND_INTENSITY,
+            FOREGROUND_INTENSITY | FOREGROUND_RED,
+            FOREGROUND_INTENSITY | FOREGROUND_GREEN,
+            FOREGROUND_INTENSITY | FOREGROUND_RED | FOREGROUND_GREEN,
+            FOREGROUND_INTENSITY | FOREGROUND_BLUE,
+            FOREGROUND_INTENSITY | FOREGROUND_RED | FOREGROUND_BLUE,
+            FOREGROUND_INTENSITY | FOREGROUND_BLUE | FOREGROUND_GREEN,
+            FOREGROUND_INTENSITY | FOREGROUND_BLUE | FOREGROUND_GREEN | FOREGROUND_RED,
         ]
     };
 }
 
-fn get_ct(table: &Vec<DWORD>, idx: u16) -> u16 {
-    let mut idx: usize = (idx as usize) & 0x0F;
+fn get_ct(table: &Vec<DWORD>, idx: Attribute) -> u16 {
+    let mut idx: usize = (idx & COLOR_MASK) as usize;
     if idx >= table.len() {
         idx = table.len() - 1
     }
     return table[idx] as u16
 }
 
-fn cell_to_char_info(c: &Cell) -> (u16, Vec<WCHAR>) {
-    let mut attr = get_ct(&*COLOR_TABLE_FG, c.fg) | get_ct(&*COLOR_TABLE_BG, c.bg);
+// Windows console supports only 16 colors, so every color is converted
+// to one of them after applying the selected output mode
+fn console_color(c: Attribute, mode: OutputMode) -> Attribute {
+    match mode {
+        OUTPUT_NO_COLOR | OUTPUT_NORMAL => quantize(c, mode),
+        _ => quantize(quantize(c, mode), OUTPUT_NORMAL),
+    }
+}
 
-    if c.fg & ATTR_REVERSE | c.bg & ATTR_REVERSE != 0 {
+fn cell_to_char_info(c: &Cell, mode: OutputMode) -> (u16, Vec<WCHAR>) {
+    let fg = console_color(c.fg, mode);
+    let bg = console_color(c.bg, mode);
+    let mut attr = get_ct(&*COLOR_TABLE_FG, fg) | get_ct(&*COLOR_TABLE_BG, bg);
+
+    // Console cannot display italic, blinking or strikethrough text. All
+    // kinds of underline are displayed as a single one. A console cell holds
+    // one character, so only the first character of a grapheme cluster is
+    // displayed
+    let st = c.full_style();
+    if st.attrs & STYLE_DIM != 0 {
+        attr &= !(FOREGROUND_INTENSITY as u16)
+    }
+    if st.attrs & STYLE_REVERSE != 0 {
         attr = (attr&0xF0)>>4 | (attr & 0x0F)<<4;
     }
-    if c.fg & ATTR_BOLD != 0 {
+    if st.attrs & STYLE_BOLD != 0 {
         attr |= FOREGROUND_INTENSITY as u16
     }
     if c.bg & ATTR_BOLD != 0 {
         attr |= BACKGROUND_INTENSITY as u16
     }
+    if st.underline() != 0 {
+        attr |= COMMON_LVB_UNDERSCORE as u16
+    }
+    if st.attrs & STYLE_OVERLINE != 0 {
+        attr |= COMMON_LVB_GRID_HORIZONTAL as u16
+    }
 
     let mut v: Vec<WCHAR> = vec![];
     let c = c.ch;
@@ -128,6 +175,68 @@ impl WinTerminal {
         self.stdin_worker.take().unwrap().join().expect("Could not join thread");
     }
 
+    // Writes a rectangular area of the buffer to console
+    fn write_rect(&self, buf: &CellBuf, rect: &CellRect) -> Result<(), String> {
+        let height = rect.bottom - rect.top + 1;
+        let width = rect.right - rect.left + 1;
+
+        let mut v: Vec<CHAR_INFO> = vec![];
+
+        for y in 0..height {
+            for x in 0..width {
+                if let Some(cl) = buf.get_cell(rect.left + x, rect.top + y) {
+                    // both columns of a wide character keep the character,
+                    // and the console needs to know which half is which
+                    if cl.is_continuation() {
+                        let lead = buf.get_cell(rect.left + x - 1, rect.top + y);
+                        let lead = lead.unwrap_or(Cell{ch: ' ', ..cl});
+                        let (attr, vec) = cell_to_char_info(&lead, self.output_mode);
+                        v.push(CHAR_INFO{Attributes: attr | COMMON_LVB_TRAILING_BYTE as u16, UnicodeChar: vec[0]});
+                        continue;
+                    }
+                    let (mut attr, vec) = cell_to_char_info(&cl, self.output_mode);
+                    let next = buf.get_cell(rect.left + x + 1, rect.top + y);
+                    if next.map(|c| c.is_continuation()) == Some(true) {
+                        attr |= COMMON_LVB_LEADING_BYTE as u16;
+                    }
+                    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: vec[0]});
+                    //if v.len() > 1 {
+                    //    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: v[0]});
+                    //}
+                } else {
+                    let c = Cell{ch: ' ', bg: COLOR_BLACK, fg: COLOR_WHITE, style: Style::default(), link: NO_LINK, combining: Combining::None};
+                    let (attr, vec) = cell_to_char_info(&c, self.output_mode);
+                    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: vec[0]});
+                }
+            }
+        }
+
+        let res: i32;
+        let size: COORD = COORD{
+            X: width as i16,
+            Y: height as i16,
+        };
+        let coord: COORD = COORD{X: 0, Y: 0};
+        let errcode: DWORD;
+        let mut region: SMALL_RECT =
+            SMALL_RECT{
+                Left: rect.left as i16,
+                Top: rect.top as i16,
+                Right: rect.right as i16,
+                Bottom: rect.bottom as i16,
+            };
+        unsafe {
+            let h = WinTerminal::stdout_handle();
+            res = kernel32::WriteConsoleOutputW(h, v.as_ptr(), size, coord, &mut region);
+            errcode = kernel32::GetLastError();
+        }
+
+        match res {
+            0 => Err(format!("Failed to output: {}", errcode)),
+            _ => Ok(()),
+        }
+    }
+
     fn input_record_to_event(irec: winapi::INPUT_RECORD, state: &mut ThreadState) -> Event {
         state.repeat_count = 1;
         match irec.EventType {
@@ -333,6 +442,7 @@ impl WinTerminal {
             tx: t,
             stdin_worker: None,
             input_mode: mode,
+            output_mode: detect_output_mode(),
             event_chan: sender,
         };
 
@@ -409,57 +519,29 @@ impl WinTerminal {
 }
 
 impl TerminalManager for WinTerminal {
-    fn write(self: &Self, buf: &CellBuf) -> Result<(), String> {
-        let rect = &buf.dirty_rect;
-        if rect.left == -1 {
-            return Ok(());
-        }
-
-        let height = rect.bottom - rect.top + 1;
-        let width = rect.right - rect.left + 1;
-
-        let mut v: Vec<CHAR_INFO> = vec![];
-
-        for y in 0..height {
-            for x in 0..width {
-                if let Some(cl) = buf.get_cell(rect.left + x, rect.top + y) {
-                    let (attr, vec) = cell_to_char_info(&cl);
-                    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: vec[0]});
-                    //if v.len() > 1 {
-                    //    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: v[0]});
-                    //}
-                } else {
-                    let c = Cell{ch: ' ', bg: COLOR_BLACK, fg: COLOR_WHITE};
-                    let (attr, vec) = cell_to_char_info(&c);
-                    v.push(CHAR_INFO{Attributes: attr, UnicodeChar: vec[0]});
-                }
+    fn write(self: &Self, buf: &CellBuf, spans: &[Span]) -> Result<(), String> {
+        for span in spans {
+            if span.is_empty() {
+                continue;
             }
-        }
-
-        let res: i32;
-        let size: COORD = COORD{
-            X: width as i16,
-            Y: height as i16,
-        };
-        let coord: COORD = COORD{X: 0, Y: 0};
-        let errcode: DWORD;
-        let mut region: SMALL_RECT =
-            SMALL_RECT{
-                Left: rect.left as i16,
-                Top: rect.top as i16,
-                Right: rect.right as i16,
-                Bottom: rect.bottom as i16,
+            // both halves of a wide character must be written together
+            let is_cont = |x: i32| buf.get_cell(x, span.y).map(|c| c.is_continuation()) == Some(true);
+            let rect = CellRect{
+                left: if is_cont(span.left) { span.left - 1 } else { span.left },
+                top: span.y,
+                right: if is_cont(span.right + 1) { span.right + 1 } else { span.right },
+                bottom: span.y,
             };
-        unsafe {
-            let h = WinTerminal::stdout_handle();
-            res = kernel32::WriteConsoleOutputW(h, v.as_ptr(), size, coord, &mut region);
-            errcode = kernel32::GetLastError();
+            self.write_rect(buf, &rect)?;
         }
+        Ok(())
+    }
 
-        match res {
-            0 => Err(format!("Failed to output: {}", errcode)),
-            _ => Ok(()),
+    fn set_output_mode(&mut self, mode: OutputMode) -> OutputMode {
+        if mode != OUTPUT_CURRENT {
+            self.output_mode = mode;
         }
+        self.output_mode
     }
 
     fn set_cursor_pos(&self, x: i16, y: i16) -> Result<(), String> {
@@ -514,6 +596,63 @@ impl TerminalManager for WinTerminal {
         }
     }
 
+    fn scroll(&self, top: i32, bottom: i32, count: i32) -> Result<(), String> {
+        let width = self.size()?.x;
+        let clip = SMALL_RECT{
+            Left: 0,
+            Top: top as i16,
+            Right: (width - 1) as i16,
+            Bottom: bottom as i16,
+        };
+        // the moved rows and their new position
+        let (src, dest) = if count > 0 {
+            (SMALL_RECT{Top: (top + count) as i16, ..clip}, COORD{X: 0, Y: top as i16})
+        } else {
+            (SMALL_RECT{Bottom: (bottom + count) as i16, ..clip}, COORD{X: 0, Y: (top - count) as i16})
+        };
+        let c = Cell{ch: ' ', bg: COLOR_DEFAULT, fg: COLOR_DEFAULT, style: Style::default(), link: NO_LINK, combining: Combining::None};
+        let (attr, vec) = cell_to_char_info(&c, self.output_mode);
+        let fill = CHAR_INFO{Attributes: attr, UnicodeChar: vec[0]};
+        let res: BOOL;
+        let errcode: DWORD;
+
+        unsafe {
+            let h = WinTerminal::stdout_handle();
+            res = kernel32::ScrollConsoleScreenBufferW(h, &src, &clip, dest, &fill);
+            errcode = kernel32::GetLastError();
+        }
+        match res {
+            FALSE => Err(format!("Failed to scroll: {}", errcode)),
+            _ => Ok(()),
+        }
+    }
+
+    fn write_raw(&self, s: &str) -> Result<(), String> {
+        let v: Vec<WCHAR> = s.encode_utf16().collect();
+        let mut mode: DWORD = 0;
+        let mut written: DWORD = 0;
+        let res: BOOL;
+        let errcode: DWORD;
+
+        unsafe {
+            let h = WinTerminal::stdout_handle();
+            // old consoles do not support escape sequences and print them
+            // as text, so nothing is written if VT mode cannot be enabled
+            if kernel32::GetConsoleMode(h, &mut mode) == FALSE
+                || kernel32::SetConsoleMode(h, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == FALSE {
+                return Err(String::from("Failed to output: console does not support escape sequences"));
+            }
+            res = kernel32::WriteConsoleW(h, v.as_ptr() as *const winapi::VOID, v.len() as DWORD,
+                                          &mut written, ::std::ptr::null_mut());
+            errcode = kernel32::GetLastError();
+            kernel32::SetConsoleMode(h, mode);
+        }
+        match res {
+            FALSE => Err(format!("Failed to output: {}", errcode)),
+            _ => Ok(()),
+        }
+    }
+
     fn size(&self) -> Result<Point, String> {
         let mut cinfo: CONSOLE_SCREEN_BUFFER_INFO = CONSOLE_SCREEN_BUFFER_INFO{
             dwSize: COORD { X: 0, Y: 0},
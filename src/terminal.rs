@@ -1,20 +1,104 @@
+use std::collections::VecDeque;
 use std::sync::mpsc::{sync_channel, SyncSender, Receiver};
+use std::time::{Duration, Instant};
 
+use boxdraw::*;
+use canvas::*;
 use cellbuf::*;
 use common::*;
-use unicode_width::*;
+use image::*;
 use intf::*;
+use layer::*;
+use markup::*;
+use pixel::*;
+use query::*;
+use textblock::*;
+use theme::*;
 #[cfg(windows)] use term_windows::*;
 
+// Begin and end of synchronized update
+const SYNC_BEGIN: &str = "\x1b[?2026h";
+const SYNC_END: &str = "\x1b[?2026l";
+// How long to wait for the terminal to report whether it supports a mode
+const MODE_QUERY_TIMEOUT: u64 = 200;
+// Save and restore cursor position around image output
+const CURSOR_SAVE: &str = "\x1b7";
+const CURSOR_RESTORE: &str = "\x1b8";
+// Device attribute of terminals that support Sixel graphics
+const DA_SIXEL: u32 = 4;
+// How long an incomplete escape sequence is held before its events are
+// returned as key presses, e.g. a single ESC
+const ESC_TIMEOUT: u64 = 50;
+// How long replies to a query are expected after it has timed out. Some
+// terminals and multiplexers never answer device attributes request, so the
+// replies to later queries must not be dropped forever
+const LATE_REPLY_TIMEOUT: u64 = 1000;
+
+/// Moves rows on the real terminal side before the output of a buffer.
+/// `front` is what the terminal displays in double buffered mode: it is
+/// scrolled as well, and a scroll is detected if the buffer has none. A
+/// scroll that crosses `reserved` rectangles, e.g. images, is not sent,
+/// because the terminal would move what is displayed there. In this case,
+/// and if the terminal cannot scroll, the moved rows are redrawn
+pub fn flush_scrolls<T: TerminalManager>(terminal: &T, buf: &mut CellBuf, front: &mut Option<CellBuf>, reserved: &[CellRect]) {
+    let mut scrolls = buf.take_scrolls();
+    if let Some(ref front) = *front {
+        if front.width != buf.width || front.height != buf.height {
+            // everything is redrawn anyway
+            return;
+        }
+        if scrolls.is_empty() {
+            scrolls.extend(buf.detect_scroll(front));
+        }
+    }
+
+    for (idx, sc) in scrolls.iter().enumerate() {
+        let crosses = reserved.iter().any(|r| r.top <= sc.bottom && r.bottom >= sc.top);
+        if crosses || terminal.scroll(sc.top, sc.bottom, sc.count).is_err() {
+            // the following scrolls would move wrong rows, so all moved rows
+            // are redrawn
+            for sc in &scrolls[idx..] {
+                buf.invalidate_rows(sc.top, sc.bottom);
+            }
+            return;
+        }
+        if let Some(ref mut front) = *front {
+            front.scroll(sc.top, sc.bottom, sc.count);
+            front.clear_damage();
+        }
+    }
+}
+
 /// Virtual terminal that can print strings on real terminal, emit terminal
 /// events, return the current terminal data, and move cursor
 pub struct Terminal {
     buffer: CellBuf,
     fg: Attribute,
     bg: Attribute,
+    style: Style,
+    link: LinkId,
+    theme: Theme,
     #[cfg(windows)] terminal: WinTerminal,
     event_chan_rx: SyncSender<Event>,
     event_chan_tx: Receiver<Event>,
+    // events that are not terminal replies and are not returned yet
+    pending: VecDeque<Event>,
+    // picks terminal replies out of all input events
+    replies: ReplyParser,
+    // when the parser received the last event of an incomplete sequence
+    held_since: Instant,
+    // device attributes replies to the queries that are not waited for
+    // anymore, and when they are not expected anymore
+    late_replies: usize,
+    late_until: Instant,
+    // what the real terminal displays, used only in double buffered mode
+    front: Option<CellBuf>,
+    // wrap the output of every flush into synchronized update sequences
+    sync_output: bool,
+    layers: LayerStack,
+    image_protocol: ImageProtocol,
+    // size of a cell in pixels, used to scale Sixel images
+    cell_pixels: (i32, i32),
 }
 
 impl Terminal {
@@ -38,8 +122,21 @@ impl Terminal {
                             buffer: CellBuf::new(pt.x, pt.y),
                             fg: COLOR_DEFAULT,
                             bg: COLOR_DEFAULT,
+                            style: Style::default(),
+                            link: NO_LINK,
+                            theme: Theme::new(),
                             event_chan_tx: tx,
                             event_chan_rx: rx,
+                            pending: VecDeque::new(),
+                            replies: ReplyParser::new(),
+                            held_since: Instant::now(),
+                            late_replies: 0,
+                            late_until: Instant::now(),
+                            front: None,
+                            sync_output: false,
+                            layers: LayerStack::new(),
+                            image_protocol: IMAGE_BLOCKS,
+                            cell_pixels: (10, 20),
                             terminal: term,
                         },
         }
@@ -59,11 +156,7 @@ impl Terminal {
     pub fn peek_event(&mut self) -> Option<Event> {
         //self.terminal.peek_event()
         // TODO: squash the same events like Refresh/MouseMove into one
-        let res = self.event_chan_tx.try_recv();
-        match res {
-            Ok(ev) => Some(ev),
-            _ => None,
-        }
+        self.next_event(false)
     }
 
     /// Blocking call. If event queue contains any event then it works in the
@@ -72,10 +165,72 @@ impl Terminal {
     pub fn get_event(&mut self) -> Option<Event> {
         //self.terminal.get_event()
         // TODO: squash the same events like Refresh/MouseMove into one
-        let res = self.event_chan_tx.recv();
-        match res {
-            Ok(ev) => Some(ev),
-            _ => None,
+        self.next_event(true)
+    }
+
+    // Returns the next event that is not a part of terminal reply. Replies
+    // may come after their query has timed out, so all events go through the
+    // reply parser. An incomplete sequence is returned as key presses if it
+    // does not continue in ESC_TIMEOUT
+    fn next_event(&mut self, block: bool) -> Option<Event> {
+        loop {
+            if let Some(ev) = self.pending.pop_front() {
+                return Some(ev);
+            }
+            let res = if self.replies.is_holding() {
+                let deadline = self.held_since + Duration::from_millis(ESC_TIMEOUT);
+                let now = Instant::now();
+                if now >= deadline {
+                    None
+                } else if block {
+                    self.event_chan_tx.recv_timeout(deadline - now).ok()
+                } else {
+                    match self.event_chan_tx.try_recv() {
+                        Ok(ev) => Some(ev),
+                        _ => return None,
+                    }
+                }
+            } else if block {
+                self.event_chan_tx.recv().ok()
+            } else {
+                self.event_chan_tx.try_recv().ok()
+            };
+            match res {
+                Some(ev) => self.feed_event(ev),
+                None if self.replies.is_holding() => {
+                    let held = self.replies.flush();
+                    self.pending.extend(held);
+                },
+                None => return None,
+            }
+        }
+    }
+
+    // Passes an event to the reply parser and keeps the events that are not
+    // replies
+    fn feed_event(&mut self, ev: Event) {
+        let out = self.replies.feed(ev);
+        self.pending.extend(out);
+        self.held_since = Instant::now();
+        self.drop_late_replies();
+    }
+
+    // Drops replies to the queries that are not waited for anymore. The
+    // terminal replies in order, so everything that comes before the device
+    // attributes reply of a late query is late too
+    fn drop_late_replies(&mut self) {
+        if Instant::now() >= self.late_until {
+            self.late_replies = 0;
+        }
+        while self.late_replies > 0 {
+            self.replies.take_replies();
+            self.replies.take_mode_replies();
+            self.replies.take_graphics_replies();
+            self.replies.take_cell_size();
+            if !self.replies.take_device_attributes() {
+                break;
+            }
+            self.late_replies -= 1;
         }
     }
 
@@ -87,13 +242,423 @@ impl Terminal {
         self.event_chan_rx.send(ev).unwrap();
     }
 
-    /// Writes all detected changes from internal buffer to real terminal
-    pub fn flush(&mut self) {
-        let res = self.terminal.write(&self.buffer);
-        match res {
-            Err(e) => panic!(e),
-            _ => { self.buffer.dirty = false; self.buffer.dirty_rect = CellRect::new(); },
+    /// Asks the real terminal for its colors and waits for replies not longer
+    /// than `timeout`. Returns a color for every query in the same order, or
+    /// `None` if the terminal has not replied in time or does not support the
+    /// query. Replies are removed from event queue, and all other events
+    /// received while waiting are kept for `peek_event` and `get_event`. The
+    /// queries are followed by device attributes request, so the function
+    /// does not wait the whole `timeout` for terminals that ignore them
+    pub fn query_colors(&mut self, queries: &[ColorQuery], timeout: Duration) -> Vec<Option<ColorInfo>> {
+        let mut res = vec![None; queries.len()];
+        let mut s: String = queries.iter().map(|q| q.sequence()).collect();
+        s.push_str(DEVICE_ATTRIBUTES_QUERY);
+        if queries.is_empty() || self.terminal.write_raw(&s).is_err() {
+            return res;
+        }
+
+        let mut left = queries.len();
+        self.wait_replies(timeout, |parser, _| {
+            for (q, info) in parser.take_replies() {
+                for (idx, query) in queries.iter().enumerate() {
+                    if *query == q && res[idx].is_none() {
+                        res[idx] = Some(info);
+                        left -= 1;
+                    }
+                }
+            }
+            left == 0
+        });
+
+        res
+    }
+
+    /// Asks the real terminal for its default background color and returns
+    /// `Some(true)` if it is light, `Some(false)` if it is dark, and `None` if
+    /// the terminal has not replied in `timeout`
+    pub fn is_background_light(&mut self, timeout: Duration) -> Option<bool> {
+        self.query_colors(&[ColorQuery::Background], timeout)[0].map(|c| c.light)
+    }
+
+    /// Asks the real terminal for the state of a private mode(DECRQM) and
+    /// waits for the reply not longer than `timeout`. Returns `None` if the
+    /// terminal has not replied in time. The query is followed by device
+    /// attributes request, so the function does not wait the whole `timeout`
+    /// for terminals that ignore DECRQM
+    pub fn query_mode(&mut self, mode: u16, timeout: Duration) -> Option<ModeState> {
+        let s = format!("{}{}", mode_query_sequence(mode), DEVICE_ATTRIBUTES_QUERY);
+        if self.terminal.write_raw(&s).is_err() {
+            return None;
+        }
+
+        let mut res = None;
+        self.wait_replies(timeout, |parser, _| {
+            for (m, state) in parser.take_mode_replies() {
+                if m == mode {
+                    res = Some(state);
+                }
+            }
+            false
+        });
+        res
+    }
+
+    // Reads events until `done` returns `true`, the device attributes reply
+    // that ends every query arrives, or `timeout` expires. `done` also gets
+    // whether the device attributes reply has arrived. Events that are not
+    // replies are kept for `peek_event` and `get_event`
+    fn wait_replies<F: FnMut(&mut ReplyParser, bool) -> bool>(&mut self, timeout: Duration, mut done: F) {
+        let deadline = Instant::now() + timeout;
+        // replies that nobody has asked for
+        self.drop_late_replies();
+        self.replies.take_replies();
+        self.replies.take_mode_replies();
+        self.replies.take_graphics_replies();
+        self.replies.take_cell_size();
+        while self.replies.take_device_attributes() {}
+
+        let mut answered = false;
+        while !answered {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let ev = match self.event_chan_tx.recv_timeout(deadline - now) {
+                Ok(ev) => ev,
+                _ => break,
+            };
+            self.feed_event(ev);
+            answered = self.replies.take_device_attributes();
+            if done(&mut self.replies, answered) {
+                break;
+            }
+        }
+        if !answered {
+            // the rest of the replies must not be taken for replies to the
+            // next query or for key presses
+            self.late_replies += 1;
+            self.late_until = Instant::now() + Duration::from_millis(LATE_REPLY_TIMEOUT);
+        }
+        // the rest of an interrupted reply is not returned as key presses
+        // if it comes soon
+        self.held_since = Instant::now();
+    }
+
+    /// Sets whether `flush` wraps its output into synchronized update
+    /// sequences(mode 2026). A terminal that supports them displays only
+    /// complete frames, so large updates do not tear. `SYNC_OFF`(default)
+    /// and `SYNC_ON` force the setting, `SYNC_AUTO` asks the terminal
+    /// whether it supports the mode. Terminals that do not know the sequences
+    /// ignore them. Returns the setting that is active after the call: on or
+    /// off
+    pub fn set_sync_output(&mut self, mode: SyncOutput) -> SyncOutput {
+        self.sync_output = match mode {
+            SYNC_ON => true,
+            SYNC_AUTO => {
+                let timeout = Duration::from_millis(MODE_QUERY_TIMEOUT);
+                self.query_mode(MODE_SYNC_OUTPUT, timeout).map(|st| st.is_supported()) == Some(true)
+            },
+            _ => false,
+        };
+        self.get_sync_output()
+    }
+
+    /// Returns `SYNC_ON` if `flush` uses synchronized updates, and `SYNC_OFF`
+    /// otherwise
+    pub fn get_sync_output(&self) -> SyncOutput {
+        if self.sync_output {
+            SYNC_ON
+        } else {
+            SYNC_OFF
+        }
+    }
+
+    /// Sets how `put_image` displays images. `IMAGE_BLOCKS`(default) draws
+    /// them with half block characters, `IMAGE_SIXEL` and `IMAGE_KITTY`
+    /// force the protocol, and `IMAGE_AUTO` asks the terminal which protocol
+    /// it supports and prefers kitty graphics protocol. With Sixel graphics
+    /// the terminal is asked for its cell size as well, see
+    /// `set_cell_pixel_size`. Returns the protocol that is active after the
+    /// call
+    pub fn set_image_protocol(&mut self, mode: ImageProtocol) -> ImageProtocol {
+        let timeout = Duration::from_millis(MODE_QUERY_TIMEOUT);
+        self.image_protocol = match mode {
+            IMAGE_SIXEL | IMAGE_KITTY => mode,
+            IMAGE_AUTO => self.detect_image_protocol(timeout),
+            _ => IMAGE_BLOCKS,
+        };
+        if self.image_protocol == IMAGE_SIXEL {
+            if let Some((w, h)) = self.query_cell_size(timeout) {
+                self.set_cell_pixel_size(w, h);
+            }
+        }
+        self.image_protocol
+    }
+
+    // Asks the terminal for the size of a cell in pixels
+    fn query_cell_size(&mut self, timeout: Duration) -> Option<(i32, i32)> {
+        let s = format!("{}{}", CELL_SIZE_QUERY, DEVICE_ATTRIBUTES_QUERY);
+        if self.terminal.write_raw(&s).is_err() {
+            return None;
+        }
+
+        let mut res = None;
+        self.wait_replies(timeout, |parser, _| {
+            if let Some(size) = parser.take_cell_size() {
+                res = Some(size);
+            }
+            false
+        });
+        res
+    }
+
+    /// Returns the protocol that `put_image` uses
+    pub fn get_image_protocol(&self) -> ImageProtocol {
+        self.image_protocol
+    }
+
+    // Sends kitty graphics query and device attributes request. The terminal
+    // replies in order, so device attributes come last
+    fn detect_image_protocol(&mut self, timeout: Duration) -> ImageProtocol {
+        let s = format!("{}{}", KITTY_QUERY, DEVICE_ATTRIBUTES_QUERY);
+        if self.terminal.write_raw(&s).is_err() {
+            return IMAGE_BLOCKS;
+        }
+
+        let mut kitty = false;
+        let mut sixel = false;
+        self.wait_replies(timeout, |parser, answered| {
+            for (id, msg) in parser.take_graphics_replies() {
+                if id == KITTY_QUERY_ID && msg == "OK" {
+                    kitty = true;
+                }
+            }
+            if answered {
+                // the first parameter is the terminal class
+                sixel = parser.device_attribute_params().iter().skip(1).any(|&p| p == DA_SIXEL);
+            }
+            false
+        });
+        if kitty {
+            IMAGE_KITTY
+        } else if sixel {
+            IMAGE_SIXEL
+        } else {
+            IMAGE_BLOCKS
+        }
+    }
+
+    /// Sets the size of a cell in pixels. Sixel images are scaled to this
+    /// size, so they fill their rectangle. `set_image_protocol` asks the
+    /// terminal for it, and the default for terminals that do not reply is
+    /// 10x20. With a wrong size an image does not fit its rectangle and
+    /// covers other cells or leaves a part of the rectangle empty
+    pub fn set_cell_pixel_size(&mut self, width: i32, height: i32) {
+        if width > 0 && height > 0 {
+            self.cell_pixels = (width, height);
+        }
+    }
+
+    /// Displays an image scaled to a rectangle. With Sixel or kitty graphics
+    /// protocol the image is sent by the next flush, and the cells under it
+    /// are not written until the image is removed. Returns the image ID for
+    /// `remove_image`. Without them the image is drawn with half block
+    /// characters and the function returns `None`. A Sixel image that
+    /// reaches the last row would scroll the screen, so it is drawn with half
+    /// block characters too
+    pub fn put_image(&mut self, rect: &CellRect, image: &Image) -> Option<ImageId> {
+        if rect.is_empty() {
+            return None;
+        }
+        let sixel = self.image_protocol == IMAGE_SIXEL && rect.bottom < self.buffer.height - 1;
+        if sixel || self.image_protocol == IMAGE_KITTY {
+            return Some(self.buffer.place_image(rect, image.clone()));
+        }
+        let pc = image.to_pixel_canvas(rect.right - rect.left + 1, rect.bottom - rect.top + 1);
+        pc.render(&mut self.buffer, rect);
+        None
+    }
+
+    /// Removes an image displayed with Sixel or kitty graphics protocol. The
+    /// next flush redraws the cells under it.
+    /// Returns `false` if there is no image with this ID
+    pub fn remove_image(&mut self, id: ImageId) -> bool {
+        self.buffer.remove_image(id)
+    }
+
+    // Returns the sequence that displays an image at the top left cell of
+    // its rectangle
+    fn image_sequence(&self, img: &ImagePlacement) -> String {
+        let rect = img.rect();
+        let (cols, rows) = (rect.right - rect.left + 1, rect.bottom - rect.top + 1);
+        let seq = if self.image_protocol == IMAGE_KITTY {
+            kitty_sequence(img.image(), img.id(), cols, rows)
+        } else {
+            let (cw, ch) = self.cell_pixels;
+            sixel_sequence(&img.image().scale(cols * cw, rows * ch))
         };
+        format!("{}\x1b[{};{}H{}{}", CURSOR_SAVE, rect.top + 1, rect.left + 1, seq, CURSOR_RESTORE)
+    }
+
+    /// Writes all detected changes from internal buffer to real terminal.
+    /// If there are layers, the buffer and visible layers are composed first
+    /// and only the changes of the composed picture are written
+    pub fn flush(&mut self) {
+        // if the terminal has not received the beginning, the end is useless
+        let sync = self.sync_output && self.terminal.write_control(SYNC_BEGIN).is_ok();
+
+        let composing = self.layers.is_composing();
+        self.layers.compose(&mut self.buffer);
+        if composing != self.layers.is_composing() {
+            self.redraw_front();
+        }
+        let (placed, removed) = self.buffer.take_image_updates();
+        if !removed.is_empty() {
+            // the cells under removed images are unknown
+            self.redraw_front();
+        }
+        for img in &removed {
+            if self.image_protocol == IMAGE_KITTY {
+                // the cells under the image are redrawn anyway, and a terminal
+                // that has not received the image has nothing to delete
+                let _ = self.terminal.write_control(&kitty_delete_sequence(img.id()));
+            }
+            match self.layers.composite_mut() {
+                Some(c) => c.invalidate_rows(img.rect().top, img.rect().bottom),
+                None => self.buffer.invalidate_rows(img.rect().top, img.rect().bottom),
+            }
+        }
+        let reserved: Vec<CellRect> = self.buffer.images().iter().map(|img| img.rect().clone()).collect();
+        {
+            let out = match self.layers.composite_mut() {
+                Some(c) => c,
+                None => &mut self.buffer,
+            };
+            flush_scrolls(&self.terminal, out, &mut self.front, &reserved);
+            let spans = match self.front {
+                Some(ref front) => out.diff_spans(front),
+                None => out.dirty_spans(),
+            };
+            let spans = spans_outside(&spans, &reserved);
+            let res = self.terminal.write(out, &spans);
+            match res {
+                Err(e) => panic!("{}", e),
+                _ => out.clear_damage(),
+            };
+            if let Some(ref mut front) = self.front {
+                front.resize(out.width, out.height);
+                front.cells.clone_from(&out.cells);
+            }
+        }
+        self.buffer.clear_damage();
+        self.layers.clear_damage();
+        for img in &placed {
+            // an image that is not shown only leaves its cells empty
+            let _ = self.terminal.write_raw(&self.image_sequence(img));
+        }
+        if composing && !self.layers.is_composing() {
+            // the composed picture is dropped, the buffer is displayed again
+            self.redraw_front();
+        }
+
+        if sync {
+            // terminals end a synchronized update by timeout, so a lost end only
+            // delays the frame
+            let _ = self.terminal.write_control(SYNC_END);
+        }
+    }
+
+    // Makes the next flush in double buffered mode redraw the whole screen.
+    // Used when the buffer that is sent to terminal changes, because link and
+    // grapheme cluster IDs of different buffers do not match
+    fn redraw_front(&mut self) {
+        if self.front.is_some() {
+            self.front = Some(CellBuf::new(0, 0));
+        }
+    }
+
+    /// Returns layers drawn above the buffer
+    pub fn layers(&self) -> &LayerStack {
+        &self.layers
+    }
+
+    /// Returns layers drawn above the buffer to add, remove, change, or draw
+    /// on them. Put functions of `Terminal` draw only on the buffer, and
+    /// layers are drawn with functions of their `CellBuf`
+    pub fn layers_mut(&mut self) -> &mut LayerStack {
+        &mut self.layers
+    }
+
+    /// Moves rows from `top` to `bottom` inclusive up by `count` rows, or
+    /// down if `count` is negative. New rows are filled with spaces and
+    /// default attributes. On the next flush the real terminal moves the rows
+    /// itself if it can, so only new rows are redrawn. It makes scrolling of
+    /// logs and lists that take the whole terminal width fast
+    pub fn scroll(&mut self, top: i32, bottom: i32, count: i32) {
+        self.buffer.scroll(top, bottom, count);
+    }
+
+    /// Fills a rectangle with character `c` using the current attributes.
+    /// Retuns `false` if the rectangle is outside terminal window
+    pub fn fill_rect(&mut self, rect: &CellRect, c: char) -> bool {
+        let cell = Cell{ch: c, fg: self.fg, bg: self.bg, style: self.style, link: self.link, combining: Combining::None};
+        self.buffer.fill_rect(rect, cell)
+    }
+
+    /// Fills a rectangle with spaces and default attributes.
+    /// Retuns `false` if the rectangle is outside terminal window
+    pub fn clear_rect(&mut self, rect: &CellRect) -> bool {
+        self.buffer.clear_rect(rect)
+    }
+
+    /// Copies a rectangle so its top left corner is at `x` and `y`. The
+    /// source and destination may overlap.
+    /// Retuns `false` if nothing is copied
+    pub fn copy_rect(&mut self, src: &CellRect, x: i32, y: i32) -> bool {
+        self.buffer.copy_rect(src, x, y)
+    }
+
+    /// Moves the content of a rectangle up by `dy` rows and left by `dx`
+    /// columns, or down and right if the values are negative. The area that
+    /// appears at the other side is filled with spaces using the current
+    /// attributes.
+    /// Retuns `false` if the rectangle is outside terminal window
+    pub fn scroll_rect(&mut self, rect: &CellRect, dy: i32, dx: i32) -> bool {
+        let cell = Cell{ch: ' ', fg: self.fg, bg: self.bg, style: self.style, link: self.link, combining: Combining::None};
+        self.buffer.scroll_rect(rect, dy, dx, cell)
+    }
+
+    /// Copies a rectangle of an offscreen buffer so its top left corner is
+    /// at `x` and `y`. Parts outside terminal window are skipped.
+    /// Retuns `false` if nothing is copied
+    pub fn blit(&mut self, src: &CellBuf, src_rect: &CellRect, x: i32, y: i32) -> bool {
+        self.buffer.blit(src, src_rect, x, y)
+    }
+
+    /// The same as `blit` but the source cells equal to `key` do not
+    /// overwrite what is on the screen
+    pub fn blit_transparent(&mut self, src: &CellBuf, src_rect: &CellRect, x: i32, y: i32, key: &Cell) -> bool {
+        self.buffer.blit_transparent(src, src_rect, x, y, key)
+    }
+
+    /// Turns double buffered mode on or off. In this mode `flush` compares
+    /// the buffer with the picture that the real terminal displays and
+    /// outputs only the cells that differ. So an application can clear and
+    /// redraw the whole screen every frame, and only real changes are sent to
+    /// the terminal. The first flush after turning the mode on redraws the
+    /// whole screen
+    pub fn set_double_buffer(&mut self, on: bool) {
+        if !on {
+            self.front = None;
+        } else if self.front.is_none() {
+            // the size differs from the buffer size, so everything is redrawn
+            self.front = Some(CellBuf::new(0, 0));
+        }
+    }
+
+    /// Returns `true` if double buffered mode is on
+    pub fn is_double_buffer(&self) -> bool {
+        self.front.is_some()
     }
 
     /// Clears the buffer with space character and default attributes
@@ -105,7 +670,7 @@ impl Terminal {
     /// the internal buffer contains some text that is not shows on the real
     /// terminal yet(not displayed for a user yet)
     pub fn dirty(&self) -> bool {
-        self.buffer.dirty
+        self.buffer.dirty || self.layers.dirty()
     }
 
     /// Returns the value of internal buffer. It is public for debug purposes
@@ -123,6 +688,69 @@ impl Terminal {
         (self.buffer.width, self.buffer.height)
     }
 
+    /// Sets the color mode of the real terminal. All colors are converted to
+    /// the nearest ones available in this mode before output. By default the
+    /// mode is detected from `NO_COLOR`, `COLORTERM`, and `TERM` environment
+    /// variables. `OUTPUT_CURRENT` does not change the mode.
+    /// Returns the mode that is active after the call
+    pub fn set_output_mode(&mut self, mode: OutputMode) -> OutputMode {
+        let prev = self.terminal.set_output_mode(OUTPUT_CURRENT);
+        let curr = self.terminal.set_output_mode(mode);
+        if prev != curr {
+            // all colors may look different, so the whole screen is redrawn
+            self.buffer.invalidate();
+            self.redraw_front();
+        }
+        curr
+    }
+
+    /// Sets how many columns East Asian characters of ambiguous width take,
+    /// e.g. box drawing characters: `AMBIGUOUS_NARROW` - one column(default),
+    /// `AMBIGUOUS_WIDE` - two columns as in CJK locales, `AMBIGUOUS_AUTO` -
+    /// detect by printing such a character and checking the cursor position.
+    /// If the detection fails the width is narrow. The setting affects all
+    /// text printed after the call, so the screen should be redrawn.
+    /// Returns the width that is active after the call: narrow or wide
+    pub fn set_ambiguous_width(&mut self, mode: AmbiguousWidth) -> AmbiguousWidth {
+        let wide = match mode {
+            AMBIGUOUS_WIDE => true,
+            AMBIGUOUS_AUTO => {
+                // the probe character spoils the screen
+                self.buffer.invalidate();
+                self.redraw_front();
+                self.probe_ambiguous_width() == Some(2)
+            },
+            _ => false,
+        };
+        self.buffer.set_ambiguous_wide(wide);
+        self.get_ambiguous_width()
+    }
+
+    /// Returns `AMBIGUOUS_WIDE` if East Asian characters of ambiguous width
+    /// take two columns, and `AMBIGUOUS_NARROW` otherwise
+    pub fn get_ambiguous_width(&self) -> AmbiguousWidth {
+        if self.buffer.ambiguous_wide() {
+            AMBIGUOUS_WIDE
+        } else {
+            AMBIGUOUS_NARROW
+        }
+    }
+
+    // Prints a box drawing character in the top left corner of the real
+    // terminal and returns how many columns the cursor has moved
+    fn probe_ambiguous_width(&self) -> Option<i16> {
+        let saved = self.terminal.get_cursor_pos().ok()?;
+        let res = self.terminal.set_cursor_pos(0, 0)
+            .and_then(|_| self.terminal.write_raw("\u{2500}"))
+            .and_then(|_| self.terminal.get_cursor_pos());
+        let _ = self.terminal.set_cursor_pos(saved.x, saved.y);
+        match res {
+            Ok(ref pos) if pos.y == 0 => Some(pos.x),
+            _ => None,
+        }
+    }
+
+
     /// Sets foreground(text) color for all following put calls
     pub fn set_foreground(&mut self, c: Attribute) {
         self.fg = c;
@@ -143,6 +771,54 @@ impl Terminal {
         self.bg
     }
 
+    /// Sets text style(italic, underline etc) for all following put calls
+    pub fn set_style(&mut self, st: Style) {
+        self.style = st;
+    }
+
+    /// Retuns the current text style
+    pub fn get_style(&self) -> Style {
+        self.style
+    }
+
+    /// Sets the theme for all following put calls that use theme styles.
+    /// The buffer is not redrawn: the new theme affects only new output
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+    }
+
+    /// Returns the current theme
+    pub fn get_theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Sets colors and text style of a theme style selected by its name or
+    /// handle for all following put calls.
+    /// Returns `false` if the current theme does not contain the style. In
+    /// this case the current attributes are not changed
+    pub fn use_style<K: ThemeKey>(&mut self, key: K) -> bool {
+        match self.theme.get(key) {
+            Some(ts) => {
+                self.fg = ts.fg;
+                self.bg = ts.bg;
+                self.style = ts.style;
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Temporarily applies a theme style and calls a drawing function
+    fn draw_themed<K: ThemeKey, F: FnOnce(&mut Terminal) -> bool>(&mut self, key: K, f: F) -> bool {
+        let (f_save, b_save, st_save) = (self.fg, self.bg, self.style);
+        self.use_style(key);
+        let res = f(self);
+        self.style = st_save;
+        self.bg = b_save;
+        self.fg = f_save;
+        res
+    }
+
     /// Sets the value of a single terminal cell.
     /// Retuns `false` if coordinates are outside terminal window
     pub fn set_cell(&mut self, x: i32, y: i32, c: Cell) -> bool {
@@ -157,59 +833,84 @@ impl Terminal {
         self.buffer.get_cell(x, y)
     }
 
+    /// Returns the whole grapheme cluster of a terminal cell: the cell
+    /// character with all combining characters that follow it.
+    /// Returns `None` if coordinates are outside terminal window
+    pub fn get_grapheme(&self, x: i32, y: i32) -> Option<String> {
+        self.buffer.get_cell(x, y).map(|c| self.buffer.get_grapheme(&c))
+    }
+
+    /// Returns the number of columns a string takes in terminal window, see
+    /// `CellBuf::text_width`
+    pub fn text_width(&self, s: &str) -> i32 {
+        self.buffer.text_width(s)
+    }
+
+    /// Splits a string at a column, see `CellBuf::split_at_column`
+    pub fn split_at_column<'b>(&self, s: &'b str, column: i32) -> (&'b str, &'b str) {
+        self.buffer.split_at_column(s, column)
+    }
+
+    /// Cuts off the end of a string wider than `width`, see
+    /// `CellBuf::truncate`
+    pub fn truncate<'b>(&self, s: &'b str, width: i32) -> &'b str {
+        self.buffer.truncate(s, width)
+    }
+
+    /// Cuts off the end of a string wider than `width` and marks it with `…`
+    pub fn truncate_with_ellipsis(&self, s: &str, width: i32) -> String {
+        self.buffer.truncate_with_ellipsis(s, width)
+    }
+
+    /// Truncates or pads a string to `width` columns, see `CellBuf::pad`
+    pub fn pad(&self, s: &str, width: i32, align: Align) -> String {
+        self.buffer.pad(s, width, align)
+    }
+
+    /// Returns a drawing context that covers the whole terminal window and
+    /// uses the current attributes. Call `Canvas::region` to draw in a part
+    /// of the window with local coordinates
+    pub fn canvas(&mut self) -> Canvas<'_> {
+        let mut cv = Canvas::new(&mut self.buffer);
+        cv.set_foreground(self.fg);
+        cv.set_background(self.bg);
+        cv.set_style(self.style);
+        cv.set_link(self.link);
+        cv
+    }
+
     /// Sets an UTF8 character of a terminal cell with current attributes.
     /// Retuns `false` if coordinates are outside terminal window
     pub fn put_char(&mut self, x: i32, y: i32, c: char) -> bool {
         let f = self.fg;
         let b = self.bg;
-        self.set_cell(x, y, Cell{ch: c, fg: f, bg: b})
+        let st = self.style;
+        self.set_cell(x, y, Cell{ch: c, fg: f, bg: b, style: st, link: self.link, combining: Combining::None})
     }
 
     /// Sets temporarily attributes and purs a character to given coordinates
     /// Retuns `false` if coordinates are outside terminal window
     pub fn put_char_with_attrs(&mut self, x: i32, y: i32, c: char, fg: Attribute, bg: Attribute) -> bool {
-        self.set_cell(x, y, Cell{ch: c, fg: fg, bg: bg})
+        self.set_cell(x, y, Cell{ch: c, fg: fg, bg: bg, style: self.style, link: self.link, combining: Combining::None})
+    }
+
+    /// Puts a character to given coordinates using a theme style selected by
+    /// its name or handle. If the theme does not have the style, the current
+    /// attributes are used.
+    /// Retuns `false` if coordinates are outside terminal window
+    pub fn put_char_themed<K: ThemeKey>(&mut self, x: i32, y: i32, c: char, key: K) -> bool {
+        self.draw_themed(key, |t| t.put_char(x, y, c))
     }
 
-    // The only function that uses unicode width information
-    // TODO: how to get CJK context?
     /// Puts a string to given coordinates using the current attributes.
+    /// The string is split into grapheme clusters, so a letter with combining
+    /// marks or an emoji sequence takes one cell(two for wide ones). The width
+    /// of East Asian characters of ambiguous width is set with
+    /// `set_ambiguous_width`.
     /// Retuns `false` if the entire string is outside terminal window.
     /// Retunrs `true` if at least one character of the string was printed on the screen
     pub fn put_string<S: Into<String> >(&mut self, x: i32, y: i32, s: S) -> bool {
-        if y < 0 || y >= self.buffer.height || x >= self.buffer.width {
-            return false
-        }
-
-        let string = s.into();
-        if x + (string.chars().count() as i32) < 0 {
-            return false;
-        }
-
-        let mut pos = x;
-        for c in string.chars() {
-            if let Some(w) = c.width() {
-                if w == 0 {
-                    continue;
-                }
-                let wi = w as i32;
-
-                if pos >= 0 {
-                    if pos + wi <= self.buffer.width {
-                        self.buffer.set_cell(pos, y, Cell{ch: c, fg: self.fg, bg: self.bg});
-                    } else if w == 2 && pos == self.buffer.width - 1 {
-                        self.buffer.set_cell(pos, y, Cell{ch: ' ', fg: self.fg, bg: self.bg});
-                    }
-                }
-                pos += wi;
-
-                if pos >= self.buffer.width {
-                    break;
-                }
-            }
-        }
-
-        true
+        self.canvas().put_string(x, y, s)
     }
 
     /// Sets new attributes temporarily and puts a string to given coordinates
@@ -225,33 +926,35 @@ impl Terminal {
         res
     }
 
-    /// Puts a string from top to bottom starting from given coordinates using
-    /// the current attributes.
+    /// Puts a string to given coordinates using a theme style selected by
+    /// its name or handle. If the theme does not have the style, the current
+    /// attributes are used.
     /// Retuns `false` if the entire string is outside terminal window.
     /// Retunrs `true` if at least one character of the string was printed on the screen
-    pub fn put_string_vertical<S: Into<String> >(&mut self, x: i32, y: i32, s: S) -> bool {
-        if x < 0 || x >= self.buffer.width || y >= self.buffer.height {
-            return false
-        }
-
-        let string = s.into();
-        if y + (string.chars().count() as i32) < 0 {
-            return false;
-        }
-
-        let mut pos = y;
-        for c in string.chars() {
-            if pos >= 0 {
-                self.buffer.set_cell(x, pos, Cell{ch: c, fg: self.fg, bg: self.bg});
-            }
-            pos += 1;
+    pub fn put_string_themed<S: Into<String>, K: ThemeKey>(&mut self, x: i32, y: i32, s: S, key: K) -> bool {
+        self.draw_themed(key, |t| t.put_string(x, y, s))
+    }
 
-            if pos >= self.buffer.height {
-                break;
-            }
-        }
+    /// Puts a string to given coordinates using the current attributes and
+    /// makes the string a hyperlink to `url`. Terminals that do not support
+    /// hyperlinks display the string as a regular text.
+    /// Retuns `false` if the entire string is outside terminal window.
+    /// Retunrs `true` if at least one character of the string was printed on the screen
+    pub fn put_string_with_link<S: Into<String> >(&mut self, x: i32, y: i32, s: S, url: &str) -> bool {
+        let link_save = self.link;
+        self.link = self.buffer.add_link(url);
+        let res = self.put_string(x, y, s);
+        self.link = link_save;
+        res
+    }
 
-        true
+    /// Puts a string from top to bottom starting from given coordinates using
+    /// the current attributes. Wide characters take two columns, so the
+    /// column to the right of the string may be changed as well.
+    /// Retuns `false` if the entire string is outside terminal window.
+    /// Retunrs `true` if at least one character of the string was printed on the screen
+    pub fn put_string_vertical<S: Into<String> >(&mut self, x: i32, y: i32, s: S) -> bool {
+        self.canvas().put_string_vertical(x, y, s)
     }
 
     /// Temporarily changes the current attributes and puts a string from top to
@@ -268,34 +971,21 @@ impl Terminal {
         res
     }
 
+    /// Puts a string from top to bottom starting from given coordinates using
+    /// a theme style selected by its name or handle. If the theme does not
+    /// have the style, the current attributes are used.
+    /// Retuns `false` if the entire string is outside terminal window.
+    /// Retunrs `true` if at least one character of the string was printed on the screen
+    pub fn put_string_vertical_themed<S: Into<String>, K: ThemeKey>(&mut self, x: i32, y: i32, s: S, key: K) -> bool {
+        self.draw_themed(key, |t| t.put_string_vertical(x, y, s))
+    }
+
     /// Puts a horizontal line of character `c` starting from given coordinates.
     /// The current attributes are used.
     /// Retuns `false` if the entire string is outside terminal window.
     /// Retunrs `true` if at least one character of the string was printed on the screen
     pub fn put_horizontal_line(&mut self, x: i32, y: i32, length: i32, c: char) -> bool {
-        if y < 0 || y >= self.buffer.height || x >= self.buffer.width || x + length < 0 {
-            return false;
-        }
-
-        let mut xw = length;
-        let mut xs = x;
-        if x < 0 {
-            xw += x;
-            xs = 0;
-        }
-        if x + xw > self.buffer.width {
-            xw = self.buffer.width - x
-        }
-
-        if xw <= 0 {
-            return false
-        }
-
-        for xx in 0..xw {
-            self.buffer.set_cell(xs + xx, y, Cell{ch: c, fg: self.fg, bg: self.bg});
-        }
-
-        true
+        self.canvas().put_horizontal_line(x, y, length, c)
     }
 
     /// Puts a horizontal line of character `c` starting from given coordinates.
@@ -313,34 +1003,21 @@ impl Terminal {
         res
     }
 
+    /// Puts a horizontal line of character `c` starting from given coordinates
+    /// using a theme style selected by its name or handle. If the theme does
+    /// not have the style, the current attributes are used.
+    /// Retuns `false` if the entire string is outside terminal window.
+    /// Retunrs `true` if at least one character of the string was printed on the screen
+    pub fn put_horizontal_line_themed<K: ThemeKey>(&mut self, x: i32, y: i32, length: i32, c: char, key: K) -> bool {
+        self.draw_themed(key, |t| t.put_horizontal_line(x, y, length, c))
+    }
+
     /// Puts a vertical line of character `c` starting from given coordinates.
     /// The current attributes are used.
     /// Retuns `false` if the entire string is outside terminal window.
     /// Retunrs `true` if at least one character of the string was printed on the screen
     pub fn put_vertical_line(&mut self, x: i32, y: i32, length: i32, c: char) -> bool {
-        if x < 0 || x >= self.buffer.width || y >= self.buffer.height || y + length < 0 {
-            return false;
-        }
-
-        let mut yw = length;
-        let mut ys = y;
-        if y < 0 {
-            yw += y;
-            ys = 0;
-        }
-        if y + yw > self.buffer.height {
-            yw = self.buffer.height - y
-        }
-
-        if yw <= 0 {
-            return false
-        }
-
-        for yy in 0..yw {
-            self.buffer.set_cell(x, ys + yy, Cell{ch: c, fg: self.fg, bg: self.bg});
-        }
-
-        true
+        self.canvas().put_vertical_line(x, y, length, c)
     }
 
     /// Puts a vertical line of character `c` starting from given coordinates.
@@ -358,6 +1035,82 @@ impl Terminal {
         res
     }
 
+    /// Puts a vertical line of character `c` starting from given coordinates
+    /// using a theme style selected by its name or handle. If the theme does
+    /// not have the style, the current attributes are used.
+    /// Retuns `false` if the entire string is outside terminal window.
+    /// Retunrs `true` if at least one character of the string was printed on the screen
+    pub fn put_vertical_line_themed<K: ThemeKey>(&mut self, x: i32, y: i32, length: i32, c: char, key: K) -> bool {
+        self.draw_themed(key, |t| t.put_vertical_line(x, y, length, c))
+    }
+
+    /// Draws a horizontal line with box drawing characters using the
+    /// current attributes. The line joins lines and frames it crosses or
+    /// touches.
+    /// Retuns `false` if the entire line is outside terminal window
+    pub fn draw_horizontal_line(&mut self, x: i32, y: i32, length: i32, style: LineStyle) -> bool {
+        self.canvas().draw_horizontal_line(x, y, length, style)
+    }
+
+    /// Draws a vertical line with box drawing characters using the current
+    /// attributes. The line joins lines and frames it crosses or touches.
+    /// Retuns `false` if the entire line is outside terminal window
+    pub fn draw_vertical_line(&mut self, x: i32, y: i32, length: i32, style: LineStyle) -> bool {
+        self.canvas().draw_vertical_line(x, y, length, style)
+    }
+
+    /// Draws a frame along the edges of a rectangle using the current
+    /// attributes. The frame joins lines and frames it crosses or touches.
+    /// Retuns `false` if the entire frame is outside terminal window
+    pub fn draw_frame(&mut self, rect: &CellRect, style: LineStyle) -> bool {
+        self.canvas().draw_frame(rect, style)
+    }
+
+    /// Draws a frame with a title in its top border.
+    /// Retuns `false` if the entire frame is outside terminal window
+    pub fn draw_frame_with_title(&mut self, rect: &CellRect, style: LineStyle, title: &str) -> bool {
+        self.canvas().draw_frame_with_title(rect, style, title)
+    }
+
+    /// Puts styled text, see `Canvas::put_styled`. Returns the number of
+    /// columns the text takes including the part outside terminal window
+    pub fn put_styled(&mut self, x: i32, y: i32, text: &StyledText) -> i32 {
+        self.canvas().put_styled(x, y, text)
+    }
+
+    /// Puts text with markup, see module `markup`. Returns the number of
+    /// columns the text takes including the part outside terminal window
+    pub fn put_markup(&mut self, x: i32, y: i32, markup: &str) -> i32 {
+        self.canvas().put_markup(x, y, markup)
+    }
+
+    /// Puts a string with ANSI escape sequences using the current attributes
+    /// as defaults, see module `ansi`. Returns the number of columns the
+    /// text takes including the part outside terminal window
+    pub fn put_ansi_string(&mut self, x: i32, y: i32, s: &str) -> i32 {
+        self.canvas().put_ansi_string(x, y, s)
+    }
+
+    /// Puts text into a rectangle with the current attributes, see
+    /// `Canvas::put_text_block`. Returns the number of laid out lines
+    pub fn put_text_block(&mut self, rect: &CellRect, text: &str, opts: &TextOptions) -> i32 {
+        self.canvas().put_text_block(rect, text, opts)
+    }
+
+    /// Puts a string in visual order using the current attributes, see
+    /// module `bidi`.
+    /// Retuns `false` if the entire string is outside terminal window
+    pub fn put_string_bidi(&mut self, x: i32, y: i32, s: &str, dir: TextDirection) -> bool {
+        self.canvas().put_string_bidi(x, y, s, dir)
+    }
+
+    /// Draws pixels into a rectangle of terminal window, see
+    /// `PixelCanvas::render`.
+    /// Retuns `false` if nothing is drawn
+    pub fn put_pixels(&mut self, rect: &CellRect, pixels: &PixelCanvas) -> bool {
+        pixels.render(&mut self.buffer, rect)
+    }
+
     /// Moves terminal cursor.
     /// Returns `OK(())` if the cursor has moved, or `Err(string)` if anything
     /// failed, e.g. API call
@@ -0,0 +1,280 @@
+//! Paragraphs of text in a rectangle. Text is split into paragraphs by new
+//! line characters, and long paragraphs are wrapped at spaces. Words that
+//! are longer than the rectangle width are broken between grapheme
+//! clusters. Widths of wide characters are taken into account
+use std::cmp;
+use std::mem;
+
+use bidi::*;
+use canvas::*;
+use cellbuf::*;
+use common::*;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// How to put text into a rectangle
+#[derive(Debug,Clone,Copy,PartialEq)]
+pub struct TextOptions {
+    /// One of `ALIGN_*` values
+    pub align: Align,
+    /// One of `VALIGN_*` values
+    pub valign: VAlign,
+    /// Wrap long paragraphs. If it is `false` every paragraph takes one line
+    /// and its end is cut off
+    pub wrap: bool,
+    /// Mark text that does not fit the rectangle with `…`
+    pub ellipsis: bool,
+    /// Distance between tab stops
+    pub tab_width: i32,
+    /// Base direction of bidirectional text, one of `BIDI_*` values.
+    /// Paragraphs are wrapped in logical order, and every line is reordered,
+    /// see module `bidi`. Lines are not aligned to the right automatically
+    pub direction: TextDirection,
+}
+
+impl Default for TextOptions {
+    fn default() -> TextOptions {
+        TextOptions{
+            align: ALIGN_LEFT,
+            valign: VALIGN_TOP,
+            wrap: true,
+            ellipsis: false,
+            tab_width: 8,
+            direction: BIDI_OFF,
+        }
+    }
+}
+
+// A line of laid out text
+struct TextLine {
+    text: String,
+    width: i32,
+    // the last line of a paragraph is never justified
+    last: bool,
+    // base direction of the paragraph
+    dir: TextDirection,
+}
+
+impl TextLine {
+    fn new() -> TextLine {
+        TextLine{text: String::new(), width: 0, last: false, dir: BIDI_OFF}
+    }
+
+    // Returns the text of the line or a part of it in visual order
+    fn visual(&self, s: &str) -> String {
+        if self.dir == BIDI_OFF {
+            s.to_string()
+        } else {
+            BidiLine::new(s, self.dir).visual_text()
+        }
+    }
+}
+
+// Splits a string into runs of spaces and runs of other characters
+fn runs(s: &str) -> Vec<(bool, &str)> {
+    let mut res = Vec::new();
+    let mut start = 0;
+    let mut space = false;
+    for (i, c) in s.char_indices() {
+        if i > 0 && (c == ' ') != space {
+            res.push((space, &s[start..i]));
+            start = i;
+        }
+        space = c == ' ';
+    }
+    if start < s.len() {
+        res.push((space, &s[start..]));
+    }
+    res
+}
+
+impl<'a> Canvas<'a> {
+    // Replaces tabs with spaces up to the next tab stop
+    fn expand_tabs(&self, s: &str, tab_width: i32) -> String {
+        let tab_width = cmp::max(tab_width, 1);
+        let mut res = String::new();
+        let mut col = 0;
+        for g in s.graphemes(true) {
+            if g == "\t" {
+                let n = tab_width - col % tab_width;
+                res.extend((0..n).map(|_| ' '));
+                col += n;
+            } else {
+                res.push_str(g);
+                col += self.text_width(g);
+            }
+        }
+        res
+    }
+
+    // Cuts off the end of the last visible line when the text has more
+    // lines than the rectangle. The line is marked with `…` even if it fits
+    fn mark_overflow(&self, s: &str, width: i32) -> String {
+        let buf = self.buffer();
+        let ew = buf.text_width(ELLIPSIS);
+        if ew > width {
+            return buf.truncate(s, width).to_string();
+        }
+        let mut res = buf.truncate(s, width - ew).to_string();
+        res.push_str(ELLIPSIS);
+        res
+    }
+
+    // Puts a long word to lines breaking it between grapheme clusters
+    fn break_word(&self, word: &str, width: i32, line: &mut TextLine, lines: &mut Vec<TextLine>) {
+        for g in word.graphemes(true) {
+            let gw = self.text_width(g);
+            if line.width + gw > width && line.width > 0 {
+                lines.push(mem::replace(line, TextLine::new()));
+            }
+            line.text.push_str(g);
+            line.width += gw;
+        }
+    }
+
+    // Wraps one paragraph
+    fn wrap_paragraph(&self, para: &str, width: i32, lines: &mut Vec<TextLine>) {
+        let mut line = TextLine::new();
+        let mut space = "";
+        let mut space_width = 0;
+        for (is_space, run) in runs(para) {
+            if is_space {
+                space = run;
+                space_width = self.text_width(run);
+                continue;
+            }
+            let ww = self.text_width(run);
+            if line.width + space_width + ww <= width {
+                line.text.push_str(space);
+                line.text.push_str(run);
+                line.width += space_width + ww;
+            } else {
+                if !line.text.is_empty() {
+                    lines.push(mem::replace(&mut line, TextLine::new()));
+                }
+                if ww <= width {
+                    line.text.push_str(run);
+                    line.width = ww;
+                } else {
+                    self.break_word(run, width, &mut line, lines);
+                }
+            }
+            space = "";
+            space_width = 0;
+        }
+        line.last = true;
+        lines.push(line);
+    }
+
+    // Splits text into lines of at most `width` columns if wrapping is on
+    fn layout_lines(&self, text: &str, width: i32, opts: &TextOptions) -> Vec<TextLine> {
+        let mut lines = Vec::new();
+        for para in text.split('\n') {
+            let para = self.expand_tabs(para.trim_end_matches('\r'), opts.tab_width);
+            // all lines of a paragraph have the same direction
+            let dir = match opts.direction {
+                BIDI_AUTO if BidiLine::new(&para, BIDI_AUTO).is_rtl() => BIDI_RTL,
+                BIDI_AUTO => BIDI_LTR,
+                dir => dir,
+            };
+            let start = lines.len();
+            if opts.wrap && width > 0 {
+                self.wrap_paragraph(&para, width, &mut lines);
+            } else {
+                let w = self.text_width(&para);
+                lines.push(TextLine{text: para, width: w, last: true, dir: BIDI_OFF});
+            }
+            for line in &mut lines[start..] {
+                line.dir = dir;
+            }
+        }
+        lines
+    }
+
+    /// Returns lines of text as `put_text_block` lays them out in a
+    /// rectangle of `width` columns before alignment. Its length is the
+    /// height the text needs
+    pub fn layout_text_block(&self, width: i32, text: &str, opts: &TextOptions) -> Vec<String> {
+        self.layout_lines(text, width, opts).into_iter().map(|l| l.text).collect()
+    }
+
+    // Puts a line stretched to `width` columns by widening gaps between words
+    fn put_justified(&mut self, y: i32, line: &TextLine, width: i32) {
+        let text = line.visual(&line.text);
+        let parts = runs(&text);
+        let gaps = parts.iter().enumerate().filter(|&(i, p)| p.0 && i > 0).count() as i32;
+        if gaps == 0 {
+            self.put_string(0, y, text);
+            return;
+        }
+        let extra = width - line.width;
+        let mut x = 0;
+        let mut gap = 0;
+        for (i, &(is_space, run)) in parts.iter().enumerate() {
+            let w = self.text_width(run);
+            if is_space && i > 0 {
+                let gw = w + extra / gaps + if gap < extra % gaps { 1 } else { 0 };
+                self.put_horizontal_line(x, y, gw, ' ');
+                x += gw;
+                gap += 1;
+            } else {
+                self.put_string(x, y, run);
+                x += w;
+            }
+        }
+    }
+
+    /// Puts text into a rectangle in local coordinates: wraps, aligns, and
+    /// cuts it off as `opts` says, see module `textblock`. If the text has
+    /// more lines than the rectangle, only the first lines are displayed.
+    /// Returns the number of laid out lines including the ones that do not
+    /// fit, so a scrollable view knows the height of its content
+    pub fn put_text_block(&mut self, rect: &CellRect, text: &str, opts: &TextOptions) -> i32 {
+        let (width, height) = (rect.right - rect.left + 1, rect.bottom - rect.top + 1);
+        let lines = self.layout_lines(text, width, opts);
+        let count = lines.len() as i32;
+        if width <= 0 || height <= 0 {
+            return count;
+        }
+
+        let mut reg = self.region(rect);
+        let top = match opts.valign {
+            VALIGN_MIDDLE => cmp::max((height - count) / 2, 0),
+            VALIGN_BOTTOM => cmp::max(height - count, 0),
+            _ => 0,
+        };
+        let shown = cmp::min(count, height);
+        for (i, line) in lines.iter().take(shown as usize).enumerate() {
+            let y = top + i as i32;
+            let overflow = opts.ellipsis && i as i32 == shown - 1 && count > height;
+            let cut = if overflow {
+                Some(reg.mark_overflow(&line.text, width))
+            } else if line.width > width && opts.ellipsis {
+                Some(reg.buffer().truncate_with_ellipsis(&line.text, width))
+            } else if line.width > width {
+                Some(reg.buffer().truncate(&line.text, width).to_string())
+            } else {
+                None
+            };
+
+            match cut {
+                Some(s) => {
+                    let w = reg.text_width(&s);
+                    let s = line.visual(&s);
+                    let x = match opts.align {
+                        ALIGN_CENTER => (width - w) / 2,
+                        ALIGN_RIGHT => width - w,
+                        _ => 0,
+                    };
+                    reg.put_string(x, y, s);
+                },
+                None => match opts.align {
+                    ALIGN_CENTER => { reg.put_string((width - line.width) / 2, y, line.visual(&line.text)); },
+                    ALIGN_RIGHT => { reg.put_string(width - line.width, y, line.visual(&line.text)); },
+                    ALIGN_JUSTIFY if !line.last => reg.put_justified(y, line, width),
+                    _ => { reg.put_string(0, y, line.visual(&line.text)); },
+                },
+            }
+        }
+        count
+    }
+}
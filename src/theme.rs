@@ -0,0 +1,254 @@
+//! Themes: a set of named styles for UI elements, e.g. "selection", "border",
+//! or "statusbar". An application draws with style names instead of hard-coded
+//! colors, and a user can change the look by loading a theme file.
+//!
+//! Theme file is a text file: one style per line in format
+//! `name = attributes`. Empty lines and lines starting with `#` are ignored.
+//! Attributes are separated with spaces:
+//! * `fg:color` - text color
+//! * `bg:color` - background color
+//! * `ul:color` - underline color
+//! * `inherit:name` - start from all attributes of style `name` defined
+//!   before. The parent is looked up every time the style is used, so a
+//!   theme that redefines the parent changes its children too. A style that
+//!   inherits itself extends its previous definition
+//! * a style name, e.g. `bold` or `curly-underline`, adds the style
+//! * a style name with `-` in front of it, e.g. `-bold`, removes the style
+//!
+//! Colors use the format of `parse_color`. Example:
+//! ```text
+//! # blue panels
+//! border    = fg:white bg:blue
+//! statusbar = inherit:border fg:yellow bold
+//! error     = fg:#ff4040 curly-underline ul:red
+//! ```
+//! Loading a file into a theme that already has styles overrides only the
+//! styles defined in the file, so a user theme can be applied on top of the
+//! default one.
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+use color::*;
+use common::*;
+use markup::*;
+
+/// Colors and text style of a UI element
+#[derive(Debug,Clone,Copy,PartialEq,Default)]
+pub struct ThemeStyle {
+    pub fg: Attribute,
+    pub bg: Attribute,
+    pub style: Style,
+}
+
+/// Handle of a theme style. It is quicker to find a style by its handle
+/// than by its name. The handle of a style is not changed when the style
+/// is redefined
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub struct StyleId(usize);
+
+/// Anything that can select a theme style: its name or `StyleId`
+pub trait ThemeKey {
+    fn find(&self, theme: &Theme) -> Option<StyleId>;
+}
+
+impl ThemeKey for &str {
+    fn find(&self, theme: &Theme) -> Option<StyleId> {
+        theme.id(self)
+    }
+}
+
+impl ThemeKey for StyleId {
+    fn find(&self, theme: &Theme) -> Option<StyleId> {
+        if self.0 < theme.styles.len() {
+            Some(*self)
+        } else {
+            None
+        }
+    }
+}
+
+// What a style definition starts from
+#[derive(Debug,Clone)]
+enum Parent {
+    Fixed(ThemeStyle),
+    // another style, resolved when the style is used
+    Style(StyleId),
+    // the previous definition of the same style
+    Previous(Box<StyleDef>),
+}
+
+// A style as it is defined: a parent with changes on top of it
+#[derive(Debug,Clone)]
+struct StyleDef {
+    parent: Parent,
+    changes: TextAttrs,
+}
+
+/// A set of named styles
+#[derive(Debug,Clone,Default)]
+pub struct Theme {
+    ids: HashMap<String, StyleId>,
+    styles: Vec<StyleDef>,
+}
+
+/// Returns text style attribute by its name as used in theme files:
+/// `bold`, `dim`, `italic`, `blink`, `reverse`, `strikethrough`,
+/// `overline`, `underline`, `double-underline`, `curly-underline`,
+/// `dotted-underline`, or `dashed-underline`
+pub fn parse_style_attr(s: &str) -> Option<StyleAttr> {
+    match s.trim().to_lowercase().as_str() {
+        "bold" => Some(STYLE_BOLD),
+        "dim" => Some(STYLE_DIM),
+        "italic" => Some(STYLE_ITALIC),
+        "blink" => Some(STYLE_BLINK),
+        "reverse" => Some(STYLE_REVERSE),
+        "strikethrough" => Some(STYLE_STRIKETHROUGH),
+        "overline" => Some(STYLE_OVERLINE),
+        "underline" => Some(STYLE_UNDERLINE),
+        "double-underline" => Some(STYLE_DOUBLE_UNDERLINE),
+        "curly-underline" => Some(STYLE_CURLY_UNDERLINE),
+        "dotted-underline" => Some(STYLE_DOTTED_UNDERLINE),
+        "dashed-underline" => Some(STYLE_DASHED_UNDERLINE),
+        _ => None,
+    }
+}
+
+impl Theme {
+    /// Creates an empty theme
+    pub fn new() -> Theme {
+        Theme::default()
+    }
+
+    /// Loads a theme from a file
+    pub fn from_file(path: &str) -> Result<Theme, String> {
+        let mut theme = Theme::new();
+        theme.load_file(path)?;
+        Ok(theme)
+    }
+
+    /// Returns a handle of a style or `None` if the theme does not have it
+    pub fn id(&self, name: &str) -> Option<StyleId> {
+        self.ids.get(name).cloned()
+    }
+
+    /// Returns a style by its name or handle
+    pub fn get<K: ThemeKey>(&self, key: K) -> Option<ThemeStyle> {
+        let id = key.find(self)?;
+        self.resolve(&self.styles[id.0], &mut vec![id])
+    }
+
+    // Applies the changes of a definition to its parent. `visited` are the
+    // styles of the inheritance chain, it returns `None` for a loop
+    fn resolve(&self, def: &StyleDef, visited: &mut Vec<StyleId>) -> Option<ThemeStyle> {
+        let parent = match def.parent {
+            Parent::Fixed(st) => st,
+            Parent::Style(id) => {
+                if visited.contains(&id) {
+                    return None;
+                }
+                visited.push(id);
+                self.resolve(&self.styles[id.0], visited)?
+            },
+            Parent::Previous(ref prev) => self.resolve(prev, visited)?,
+        };
+        let (fg, bg, style) = def.changes.apply(parent.fg, parent.bg, parent.style);
+        Some(ThemeStyle{fg, bg, style})
+    }
+
+    /// Adds a new style or replaces the existing one. Returns the style handle
+    pub fn set(&mut self, name: &str, st: ThemeStyle) -> StyleId {
+        self.define(name, StyleDef{parent: Parent::Fixed(st), changes: TextAttrs::default()})
+    }
+
+    fn define(&mut self, name: &str, def: StyleDef) -> StyleId {
+        if let Some(id) = self.id(name) {
+            self.styles[id.0] = def;
+            return id;
+        }
+        let id = StyleId(self.styles.len());
+        self.styles.push(def);
+        self.ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Loads styles from a file. See module description for file format.
+    /// Styles from the file replace the existing ones with the same names
+    pub fn load_file(&mut self, path: &str) -> Result<(), String> {
+        let mut text = String::new();
+        match File::open(path).and_then(|mut f| f.read_to_string(&mut text)) {
+            Err(e) => Err(format!("Failed to read theme {}: {}", path, e)),
+            _ => self.load_str(&text),
+        }
+    }
+
+    /// Loads styles from a string in the same format as theme file has.
+    /// If any line is invalid no style is changed
+    pub fn load_str(&mut self, text: &str) -> Result<(), String> {
+        let mut theme = self.clone();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Err(e) = theme.parse_line(line) {
+                return Err(format!("Line {}: {}", n + 1, e));
+            }
+        }
+        *self = theme;
+        Ok(())
+    }
+
+    fn parse_line(&mut self, line: &str) -> Result<(), String> {
+        let eq = match line.find('=') {
+            Some(pos) => pos,
+            None => return Err(String::from("'=' expected")),
+        };
+        let name = line[..eq].trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(format!("invalid style name '{}'", name));
+        }
+
+        let mut parent = Parent::Fixed(ThemeStyle::default());
+        let mut changes = TextAttrs::default();
+        for word in line[eq + 1..].split_whitespace() {
+            if let Some(colon) = word.find(':') {
+                let (key, val) = (&word[..colon], &word[colon + 1..]);
+                if key == "inherit" {
+                    parent = match self.id(val) {
+                        Some(id) if val == name => Parent::Previous(Box::new(self.styles[id.0].clone())),
+                        Some(id) => Parent::Style(id),
+                        None => return Err(format!("unknown style '{}'", val)),
+                    };
+                    continue;
+                }
+                let clr = match parse_color(val) {
+                    Some(c) => c,
+                    None => return Err(format!("invalid color '{}'", val)),
+                };
+                match key {
+                    "fg" => changes.fg = Some(clr),
+                    "bg" => changes.bg = Some(clr),
+                    "ul" => changes.underline_color = Some(clr),
+                    _ => return Err(format!("unknown attribute '{}'", key)),
+                }
+            } else if let Some(attr) = word.strip_prefix('-') {
+                match parse_style_attr(attr) {
+                    Some(a) => changes = changes.without_style(a),
+                    None => return Err(format!("unknown style '{}'", word)),
+                }
+            } else {
+                match parse_style_attr(word) {
+                    Some(a) => changes = changes.with_style(a),
+                    None => return Err(format!("unknown style '{}'", word)),
+                }
+            }
+        }
+
+        let id = self.define(name, StyleDef{parent, changes});
+        if self.get(id).is_none() {
+            return Err(format!("style '{}' inherits itself through other styles", name));
+        }
+        Ok(())
+    }
+}
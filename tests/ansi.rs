@@ -0,0 +1,43 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{CellBuf, StyledText, TextAttrs};
+use common::rows;
+
+/* SGR sequences set colors and styles, other sequences are removed */
+#[test]
+fn parse() {
+    let t = StyledText::from_ansi("\x1b[1;31merror\x1b[0m: \x1b[38;5;208mx\x1b[48;2;1;2;3;4mz\x1b[39;22m!");
+    let spans = t.spans();
+    assert_eq!(spans.len(), 5);
+    let red = TextAttrs::default().with_fg(rterm::COLOR_RED).with_style(rterm::STYLE_BOLD);
+    assert_eq!(spans[0], ("error".to_string(), red));
+    assert_eq!(spans[1], (": ".to_string(), TextAttrs::default()));
+    let orange = TextAttrs::default().with_fg(rterm::palette(208));
+    assert_eq!(spans[2], ("x".to_string(), orange));
+    let bg = orange.with_bg(rterm::rgb(1, 2, 3)).with_style(rterm::STYLE_UNDERLINE);
+    assert_eq!(spans[3], ("z".to_string(), bg));
+    assert_eq!(spans[4], ("!".to_string(), TextAttrs{fg: None, ..bg}.without_style(rterm::STYLE_BOLD | rterm::STYLE_DIM)));
+
+    // bright colors and colon separated parameters
+    let t = StyledText::from_ansi("\x1b[92;104;4:3;58:2::10:20:30ma");
+    let a = TextAttrs::default().with_fg(rterm::palette(10)).with_bg(rterm::palette(12))
+        .with_style(rterm::STYLE_CURLY_UNDERLINE);
+    assert_eq!(t.spans()[0].1, TextAttrs{underline_color: Some(rterm::rgb(10, 20, 30)), ..a});
+
+    let t = StyledText::from_ansi("a\x1b[2Kb\x1b]8;;http://x\x1b\\c\x1b]0;title\x07d\x1b(Be\tf\r\x1b[?25lg\x1b[38;5m");
+    assert_eq!(t.plain_text(), "abcde fg");
+    assert_eq!(t.spans().len(), 1);
+    assert_eq!(StyledText::from_ansi("\x1b[").plain_text(), "");
+}
+
+/* ANSI text is written into a buffer */
+#[test]
+fn put() {
+    let mut cb = CellBuf::new(6, 1);
+    assert_eq!(cb.put_ansi_string(1, 0, "a\x1b[7m漢\x1b[mbcdef"), 8);
+    assert_eq!(rows(&cb), vec![" a漢\0bc"]);
+    assert_eq!(cb.get_cell(2, 0).unwrap().style.attrs, rterm::STYLE_REVERSE);
+    assert_eq!(cb.get_cell(4, 0).unwrap().style.attrs, 0);
+}
@@ -0,0 +1,72 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{BidiLine, Canvas, CellBuf, CellRect, TextOptions};
+use common::rows;
+
+/* Right-to-left runs are reordered, numbers and Latin words keep their order */
+#[test]
+fn reorder() {
+    let text = "abc שלום 123";
+    assert_eq!(BidiLine::new(text, rterm::BIDI_OFF).visual_text(), text);
+    assert_eq!(BidiLine::new(text, rterm::BIDI_LTR).visual_text(), "abc 123 םולש");
+    assert_eq!(BidiLine::new(text, rterm::BIDI_RTL).visual_text(), "123 םולש abc");
+    assert!(!BidiLine::new(text, rterm::BIDI_AUTO).is_rtl());
+    assert!(BidiLine::new("שלום abc", rterm::BIDI_AUTO).is_rtl());
+    assert_eq!(BidiLine::new("(שלום)", rterm::BIDI_RTL).visual_text(), "(םולש)");
+    assert!(BidiLine::new("", rterm::BIDI_AUTO).is_empty());
+
+    // every paragraph is reordered on its own
+    let line = BidiLine::new("שלום 1\nab שלום", rterm::BIDI_AUTO);
+    assert_eq!(line.len(), 14);
+    assert_eq!(line.visual_text(), "\n1 םולשab םולש");
+    assert_eq!(BidiLine::new("אב\nגד", rterm::BIDI_AUTO).visual_text(), "\nבאדג");
+}
+
+/* Logical and visual positions and cursor columns */
+#[test]
+fn cursor() {
+    let cb = CellBuf::new(1, 1);
+    let l = BidiLine::new("ab שלום", rterm::BIDI_LTR);
+    assert_eq!(l.len(), 7);
+    assert_eq!(l.logical_to_visual(3), 6);
+    assert_eq!(l.visual_to_logical(3), 6);
+    assert_eq!(l.logical_to_visual(1), 1);
+    assert!(l.is_rtl_at(4));
+    assert!(!l.is_rtl_at(0));
+    assert_eq!(l.cursor_column(&cb, 3), 6);
+    assert_eq!(l.cursor_column(&cb, 7), 7);
+    assert_eq!(l.logical_at_column(&cb, 3), 6);
+    assert_eq!(l.logical_at_column(&cb, 10), 7);
+
+    let l = BidiLine::new("שלום ab", rterm::BIDI_AUTO);
+    assert_eq!(l.visual_text(), "ab םולש");
+    assert_eq!(l.cursor_column(&cb, 0), 6);
+    assert_eq!(l.cursor_column(&cb, 5), 0);
+    assert_eq!(l.cursor_column(&cb, 7), -1);
+    assert_eq!(l.logical_at_column(&cb, -1), 7);
+    assert_eq!(l.logical_at_column(&cb, 10), 0);
+    for i in 0..l.len() {
+        assert_eq!(l.logical_at_column(&cb, l.cursor_column(&cb, i)), i);
+    }
+
+    // wide characters take two columns
+    let l = BidiLine::new("漢a", rterm::BIDI_RTL);
+    assert_eq!(l.cursor_column(&cb, 1), 2);
+    assert_eq!(l.logical_at_column(&cb, 1), 0);
+}
+
+/* Strings and text blocks are printed in visual order */
+#[test]
+fn put() {
+    let mut cb = CellBuf::new(8, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.fill('.');
+        assert!(cv.put_string_bidi(0, 0, "a אב", rterm::BIDI_LTR));
+        let opts = TextOptions{direction: rterm::BIDI_AUTO, align: rterm::ALIGN_RIGHT, ..TextOptions::default()};
+        assert_eq!(cv.put_text_block(&CellRect::with_size(0, 1, 6, 2), "אב גד הו", &opts), 2);
+    }
+    assert_eq!(rows(&cb), vec!["a בא....", ".דג בא..", "....וה.."]);
+}
@@ -0,0 +1,80 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{Canvas, CellBuf, CellRect, LineStyle};
+use common::rows;
+
+/* Frames in different styles */
+#[test]
+fn frames() {
+    let mut cb = CellBuf::new(5, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        assert!(cv.draw_frame(&CellRect::with_size(0, 0, 5, 3), LineStyle::Single));
+        assert!(!cv.draw_frame(&CellRect::with_size(5, 0, 2, 2), LineStyle::Single));
+    }
+    assert_eq!(rows(&cb), vec!["┌───┐", "│   │", "└───┘"]);
+
+    let styles = [
+        (LineStyle::Double, vec!["╔═══╗", "║   ║", "╚═══╝"]),
+        (LineStyle::Heavy, vec!["┏━━━┓", "┃   ┃", "┗━━━┛"]),
+        (LineStyle::Rounded, vec!["╭───╮", "│   │", "╰───╯"]),
+        (LineStyle::Ascii, vec!["+---+", "|   |", "+---+"]),
+    ];
+    for &(style, ref expected) in &styles {
+        let mut cb = CellBuf::new(5, 3);
+        Canvas::new(&mut cb).draw_frame(&CellRect::with_size(0, 0, 5, 3), style);
+        assert_eq!(&rows(&cb), expected);
+    }
+}
+
+/* Titles are clipped by the top border */
+#[test]
+fn titles() {
+    let mut cb = CellBuf::new(10, 2);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.draw_frame_with_title(&CellRect::with_size(0, 0, 10, 2), LineStyle::Single, "Log");
+    }
+    assert_eq!(rows(&cb), vec!["┌─ Log ──┐", "└────────┘"]);
+
+    let mut cb = CellBuf::new(6, 2);
+    Canvas::new(&mut cb).draw_frame_with_title(&CellRect::with_size(0, 0, 6, 2), LineStyle::Double, "Settings");
+    assert_eq!(rows(&cb), vec!["╔═ Se╗", "╚════╝"]);
+}
+
+/* Crossing and touching lines join */
+#[test]
+fn junctions() {
+    let mut cb = CellBuf::new(7, 5);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.draw_frame(&CellRect::with_size(0, 0, 7, 5), LineStyle::Single);
+        // split panes
+        cv.draw_vertical_line(3, 0, 5, LineStyle::Single);
+        cv.draw_horizontal_line(3, 2, 4, LineStyle::Single);
+        // a line that touches nothing is full at both ends
+        cv.draw_horizontal_line(1, 1, 2, LineStyle::Heavy);
+    }
+    assert_eq!(rows(&cb), vec!["┌──┬──┐", "│━━│  │", "│  ├──┤", "│  │  │", "└──┴──┘"]);
+
+    // double lines join light ones, heavy strokes become light next to them
+    let mut cb = CellBuf::new(5, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.draw_frame(&CellRect::with_size(0, 0, 5, 3), LineStyle::Double);
+        cv.draw_vertical_line(2, 0, 3, LineStyle::Single);
+        cv.draw_horizontal_line(0, 1, 5, LineStyle::Heavy);
+    }
+    assert_eq!(rows(&cb), vec!["╔═╤═╗", "╟━┿━╢", "╚═╧═╝"]);
+
+    // adjacent frames share the border, ASCII merges only with ASCII
+    let mut cb = CellBuf::new(5, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.draw_frame(&CellRect::with_size(0, 0, 3, 3), LineStyle::Ascii);
+        cv.draw_frame(&CellRect::with_size(2, 0, 3, 3), LineStyle::Ascii);
+    }
+    assert_eq!(rows(&cb), vec!["+-+-+", "| | |", "+-+-+"]);
+}
@@ -0,0 +1,96 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{Canvas, CellBuf, CellRect};
+use common::rows;
+
+/* Drawing in local coordinates is clipped by the region */
+#[test]
+fn region() {
+    let mut cb = CellBuf::new(8, 4);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.fill('.');
+        let mut r = cv.region(&CellRect::with_size(2, 1, 4, 2));
+        assert_eq!(r.size(), (4, 2));
+        assert!(r.put_string(-1, 0, "abcdef"));
+        assert!(r.put_horizontal_line(1, 1, 10, '-'));
+        assert!(!r.put_string(0, 2, "x"));
+        assert!(!r.put_string(4, 0, "x"));
+        assert!(!r.put_string(-3, 0, "xy"));
+        assert!(!r.put_vertical_line(0, 3, 2, '|'));
+        assert!(r.put_vertical_line(0, -5, 10, '|'));
+        assert!(!r.put_char(-1, 0, '#'));
+        assert!(r.put_char(3, 1, '#'));
+        assert_eq!(r.get_cell(0, 1).unwrap().ch, '|');
+        assert!(r.get_cell(4, 1).is_none());
+    }
+    assert_eq!(rows(&cb), vec!["........", "..|cde..", "..|--#..", "........"]);
+}
+
+/* Nested regions intersect their clip rectangles */
+#[test]
+fn nested() {
+    let mut cb = CellBuf::new(6, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        let mut outer = cv.region(&CellRect::with_size(1, 0, 4, 3));
+        outer.set_foreground(rterm::COLOR_RED);
+        let mut inner = outer.region(&CellRect::with_size(2, 1, 5, 5));
+        assert_eq!(inner.visible_rect().right, 1);
+        assert_eq!(inner.get_foreground(), rterm::COLOR_RED);
+        inner.fill('#');
+        // a wide character does not cross the clip edge
+        assert!(inner.put_string(0, 0, "\u{4E2D}\u{6587}"));
+        assert!(inner.put_string_vertical(1, 1, "\u{4E2D}"));
+
+        let mut empty = outer.region(&CellRect::with_size(5, 0, 2, 2));
+        assert!(empty.visible_rect().is_empty());
+        assert!(!empty.put_string(0, 0, "x"));
+        empty.fill('x');
+    }
+    assert_eq!(rows(&cb), vec!["      ", "   \u{4E2D}\0 ", "   #  "]);
+    assert_eq!(cb.get_cell(3, 2).unwrap().fg, rterm::COLOR_RED);
+}
+
+/* The edge columns of a region are drawn over wide characters crossing
+ * them, and the halves outside become spaces */
+#[test]
+fn wide_edges() {
+    let mut cb = CellBuf::new(6, 1);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        assert!(cv.put_string(0, 0, "a\u{4E2D}b\u{6587}"));
+        let mut r = cv.region(&CellRect::with_size(2, 0, 3, 1));
+        assert!(r.put_char(0, 0, 'x'));
+        assert!(r.put_char(2, 0, 'y'));
+    }
+    assert_eq!(rows(&cb), vec!["a xby "]);
+
+    let mut cb = CellBuf::new(6, 1);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.put_string(0, 0, "ab\u{6F22}de");
+        let mut r = cv.region(&CellRect::with_size(3, 0, 3, 1));
+        assert!(r.put_string(0, 0, "XYZ"));
+    }
+    assert_eq!(rows(&cb), vec!["ab XYZ"]);
+}
+
+/* Strings that start before the region are clipped by their width */
+#[test]
+fn clipped_start() {
+    let mut cb = CellBuf::new(6, 3);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        assert!(cv.put_string(-5, 0, "\u{4E16}\u{754C}\u{4E16}a"));
+        assert!(!cv.put_string(-4, 0, "\u{4E16}\u{754C}"));
+        assert!(cv.put_string(0, 1, "abcdef"));
+        let mut r = cv.region(&CellRect::with_size(2, 1, 3, 2));
+        assert!(r.put_string(-1, 0, "\u{6F22}xy"));
+        assert!(r.put_string_vertical(1, -1, "de\u{301}f"));
+        assert!(!r.put_string_vertical(2, -2, "e\u{301}f"));
+    }
+    assert_eq!(rows(&cb), vec![" a    ", "ab eyf", "   f  "]);
+}
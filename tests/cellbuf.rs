@@ -1,5 +1,9 @@
 extern crate rterm;
 
+mod common;
+
+use common::{cell, rows};
+
 /*
  * Creates empty 2x2 buffer
  */
@@ -15,6 +19,9 @@ fn create() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
 
     assert_eq!(cb.width, w);
@@ -37,6 +44,9 @@ fn clear() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
     let mut v = vec![cl; 4];
     v[1].ch = 'a';
@@ -49,6 +59,9 @@ fn clear() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
     let v_empty = vec![cl; 4];
     assert_eq!(v_empty, cb.cells);
@@ -76,6 +89,9 @@ fn resize() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
     let mut v = vec![cl.clone(); 4];
     v[3].ch = 'a';
@@ -112,10 +128,13 @@ fn put_and_get() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
     let mut v = vec![cl.clone(); 9];
 
-    let new_c = rterm::Cell{ ch: 'z', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_MAGENTA};
+    let new_c = rterm::Cell{ch: 'z', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_MAGENTA, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None};
     let idx = (1 + 2 * w) as usize;
     v[idx] = new_c.clone();
     cb.set_cell(1, 2, new_c.clone());
@@ -138,18 +157,456 @@ fn put_and_get() {
     assert!(cb.dirty);
 
     cb.dirty = false;
-    cb.set_cell(1, 2, rterm::Cell{ch: '-', fg: rterm::COLOR_RED, bg: rterm::COLOR_GREEN});
+    cb.set_cell(1, 2, rterm::Cell{ch: '-', fg: rterm::COLOR_RED, bg: rterm::COLOR_GREEN, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
     assert!(cb.dirty);
     cb.dirty = false;
-    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_RED, bg: rterm::COLOR_GREEN});
+    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_RED, bg: rterm::COLOR_GREEN, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
     assert!(cb.dirty);
     cb.dirty = false;
-    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_GREEN});
+    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_GREEN, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
     assert!(cb.dirty);
     cb.dirty = false;
-    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_WHITE});
+    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_WHITE, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
     assert!(cb.dirty);
     cb.dirty = false;
-    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_WHITE});
+    cb.set_cell(1, 2, rterm::Cell{ch: '=', fg: rterm::COLOR_BLUE, bg: rterm::COLOR_WHITE, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
+    assert!(!cb.dirty);
+}
+
+/* Registers hyperlinks */
+#[test]
+fn links() {
+    let mut cb = rterm::CellBuf::new(2, 2);
+
+    assert!(cb.get_link(rterm::NO_LINK).is_none());
+    let id1 = cb.add_link("http://example.com");
+    let id2 = cb.add_link("file:///tmp/a.rs");
+    assert!(id1 != rterm::NO_LINK);
+    assert!(id1 != id2);
+    assert_eq!(cb.add_link("http://example.com"), id1);
+    assert_eq!(cb.get_link(id2), Some("file:///tmp/a.rs"));
+    assert!(cb.get_link(id2 + 1).is_none());
+
+    // links that no cell uses are dropped, IDs are not reused
+    let mut c = cb.get_cell(0, 0).unwrap();
+    c.link = id2;
+    cb.set_cell(0, 0, c);
+    for i in 0..10 {
+        cb.add_link(&format!("http://{}", i));
+    }
+    assert!(cb.get_link(id1).is_none());
+    assert_eq!(cb.get_link(id2), Some("file:///tmp/a.rs"));
+    cb.clear();
+    assert!(cb.get_link(id2).is_none());
+    let id3 = cb.add_link("file:///tmp/a.rs");
+    assert!(id3 > id2 + 10);
+}
+
+/* Wide characters take two cells, overwritten halves are erased */
+#[test]
+fn wide_chars() {
+    let mut cb = rterm::CellBuf::new(5, 1);
+    let c = |ch: char| rterm::Cell{ch, fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None};
+    let line = |cb: &rterm::CellBuf| -> String { (0..5).map(|x| cb.get_cell(x, 0).unwrap().ch).collect() };
+
+    cb.set_cell(1, 0, c('中'));
+    assert_eq!(line(&cb), " 中\0  ");
+    assert!(cb.get_cell(2, 0).unwrap().is_continuation());
+    assert_eq!(cb.get_cell(1, 0).unwrap().width(), 2);
+
+    // overwriting the continuation erases the first half
+    cb.set_cell(2, 0, c('a'));
+    assert_eq!(line(&cb), "  a  ");
+
+    // overwriting the first half erases the continuation
+    cb.set_cell(1, 0, c('中'));
+    cb.set_cell(1, 0, c('b'));
+    assert_eq!(line(&cb), " b   ");
+
+    // a wide character over the first half of another one
+    cb.set_cell(2, 0, c('中'));
+    cb.set_cell(1, 0, c('文'));
+    assert_eq!(line(&cb), " 文\0  ");
+
+    // a wide character does not fit the last column
+    cb.set_cell(4, 0, c('中'));
+    assert_eq!(line(&cb), " 文\0  ");
+    // continuation cells cannot be set directly
+    cb.set_cell(0, 0, c(rterm::CONTINUATION));
+    assert_eq!(line(&cb), " 文\0  ");
+
+    // the same wide character does not make the buffer dirty
+    cb.dirty = false;
+    cb.set_cell(1, 0, c('文'));
+    assert!(!cb.dirty);
+
+    // cropping removes a wide character without its continuation
+    cb.resize(2, 1);
+    assert_eq!(cb.get_cell(1, 0).unwrap().ch, ' ');
+}
+
+/* Grapheme clusters: short ones are kept in a cell, long ones are interned */
+#[test]
+fn graphemes() {
+    let mut cb = rterm::CellBuf::new(4, 1);
+
+    assert_eq!(cb.add_grapheme("a"), ('a', rterm::Combining::None));
+    let (ch, comb) = cb.add_grapheme("e\u{301}");
+    assert_eq!(ch, 'e');
+    assert_eq!(comb, rterm::Combining::Inline(['\u{301}', '\0']));
+
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+    let (ch, comb) = cb.add_grapheme(family);
+    assert_eq!(cb.add_grapheme(family), (ch, comb));
+    match comb {
+        rterm::Combining::Interned(_, wide) => assert!(wide),
+        _ => panic!("Interned cluster expected"),
+    }
+    cb.set_cell(0, 0, rterm::Cell{ch, fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: comb});
+    let c = cb.get_cell(0, 0).unwrap();
+    assert_eq!(cb.get_grapheme(&c), family);
+    assert!(cb.get_cell(1, 0).unwrap().is_continuation());
+
+    // flags and emoji presentation selector make a cluster wide
+    assert_eq!(rterm::grapheme_width("\u{1F1FA}\u{1F1F8}"), 2);
+    assert_eq!(rterm::grapheme_width("\u{2764}\u{FE0F}"), 2);
+    assert_eq!(rterm::grapheme_width("e\u{301}"), 1);
+    assert_eq!(rterm::grapheme_width("\u{301}"), 0);
+    assert_eq!(rterm::grapheme_width("\n"), 0);
+    let (ch, comb) = cb.add_grapheme("\u{1F1FA}\u{1F1F8}");
+    let flag = rterm::Cell{ch, fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: comb};
+    assert_eq!(flag.width(), 2);
+
+    // clusters that no cell uses are dropped, IDs are not reused
+    cb.clear();
+    assert_eq!(cb.get_grapheme(&c), "\u{1F468}");
+    assert!(cb.add_grapheme(family).1 != comb);
+}
+
+/* East Asian characters of ambiguous width */
+#[test]
+fn ambiguous_width() {
+    let mut cb = rterm::CellBuf::new(4, 1);
+    let c = rterm::Cell{ch: '\u{2500}', fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None};
+
+    assert_eq!(c.width(), 1);
+    assert_eq!(c.width_cjk(), 2);
+    assert_eq!(rterm::grapheme_width("\u{25CB}"), 1);
+    assert_eq!(rterm::grapheme_width_cjk("\u{25CB}"), 2);
+    assert_eq!(rterm::grapheme_width_cjk("a"), 1);
+
+    assert!(!cb.ambiguous_wide());
+    cb.set_cell(0, 0, c.clone());
+    assert!(!cb.get_cell(1, 0).unwrap().is_continuation());
+
+    cb.set_ambiguous_wide(true);
+    assert_eq!(cb.cell_width(&c), 2);
+    assert_eq!(cb.grapheme_width("\u{25CB}"), 2);
+    cb.set_cell(2, 0, c.clone());
+    assert!(cb.get_cell(3, 0).unwrap().is_continuation());
+
+    // the continuation is erased even if the setting has changed
+    cb.set_ambiguous_wide(false);
+    cb.set_cell(2, 0, c);
+    assert_eq!(cb.get_cell(3, 0).unwrap().ch, ' ');
+}
+
+/* Changes are tracked per row */
+#[test]
+fn damage() {
+    let mut cb = rterm::CellBuf::new(10, 5);
+    let c = rterm::Cell{ch: 'x', fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None};
+
+    assert!(cb.dirty_spans().is_empty());
+    cb.set_cell(0, 0, c.clone());
+    cb.set_cell(9, 4, c.clone());
+    cb.set_cell(5, 4, c.clone());
+    // the same content does not damage the row
+    cb.set_cell(5, 2, rterm::Cell{ch: ' ', ..c.clone()});
+    assert_eq!(cb.dirty_spans(), vec![
+        rterm::Span{y: 0, left: 0, right: 0},
+        rterm::Span{y: 4, left: 5, right: 9},
+    ]);
+
+    cb.clear_damage();
     assert!(!cb.dirty);
+    assert!(cb.dirty_spans().is_empty());
+
+    // a wide character damages both columns
+    cb.set_cell(3, 1, rterm::Cell{ch: '中', ..c});
+    assert_eq!(cb.dirty_spans(), vec![rterm::Span{y: 1, left: 3, right: 4}]);
+
+    assert_eq!(cb.dirty_rect, rterm::CellRect{left: 3, top: 1, right: 4, bottom: 1});
+
+    // distant changes of a row are separate runs
+    cb.clear_damage();
+    assert_eq!(cb.dirty_rect, rterm::CellRect::new());
+    cb.set_cell(0, 3, c.clone());
+    cb.set_cell(9, 3, c.clone());
+    cb.set_cell(2, 3, c.clone());
+    assert_eq!(cb.dirty_spans(), vec![
+        rterm::Span{y: 3, left: 0, right: 2},
+        rterm::Span{y: 3, left: 9, right: 9},
+    ]);
+    assert_eq!(cb.dirty_rect, rterm::CellRect{left: 0, top: 3, right: 9, bottom: 3});
+
+    cb.clear();
+    let spans = cb.dirty_spans();
+    assert_eq!(spans.len(), 5);
+    assert_eq!(spans[2], rterm::Span{y: 2, left: 0, right: 9});
+    assert_eq!(cb.dirty_rect, rterm::CellRect::with_size(0, 0, 10, 5));
+}
+
+/* Runs of different cells of two buffers */
+#[test]
+fn diff() {
+    let mut back = rterm::CellBuf::new(10, 3);
+    let mut front = rterm::CellBuf::new(10, 3);
+    let c = rterm::Cell{ch: 'x', fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None};
+
+    assert!(back.diff_spans(&front).is_empty());
+    front.set_cell(1, 1, c.clone());
+    back.set_cell(1, 1, c.clone());
+    back.set_cell(2, 1, c.clone());
+    back.set_cell(3, 1, c.clone());
+    back.set_cell(7, 1, c.clone());
+    back.set_cell(9, 2, c.clone());
+    assert_eq!(back.diff_spans(&front), vec![
+        rterm::Span{y: 1, left: 2, right: 3},
+        rterm::Span{y: 1, left: 7, right: 7},
+        rterm::Span{y: 2, left: 9, right: 9},
+    ]);
+
+    // clear and redraw does not change anything
+    front.cells.clone_from(&back.cells);
+    back.clear();
+    back.set_cell(1, 1, c.clone());
+    back.set_cell(2, 1, c.clone());
+    back.set_cell(3, 1, c.clone());
+    back.set_cell(7, 1, c.clone());
+    back.set_cell(9, 2, c);
+    assert!(back.diff_spans(&front).is_empty());
+
+    let other = rterm::CellBuf::new(5, 3);
+    assert_eq!(back.diff_spans(&other).len(), 3);
+}
+
+fn fill_rows(cb: &mut rterm::CellBuf, rows: &str) {
+    for (y, ch) in rows.chars().enumerate() {
+        for x in 0..cb.width {
+            cb.set_cell(x, y as i32, rterm::Cell{ch, fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
+        }
+    }
+}
+
+fn first_column(cb: &rterm::CellBuf) -> String {
+    (0..cb.height).map(|y| cb.get_cell(0, y).unwrap().ch).collect()
+}
+
+/* Scrolling moves rows with their changes */
+#[test]
+fn scroll() {
+    let mut cb = rterm::CellBuf::new(3, 6);
+    fill_rows(&mut cb, "abcdef");
+    cb.clear_damage();
+
+    cb.set_cell(1, 3, rterm::Cell{ch: 'x', fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
+    cb.scroll(1, 4, 2);
+    assert_eq!(first_column(&cb), "ade  f");
+    assert_eq!(cb.dirty_spans(), vec![
+        rterm::Span{y: 1, left: 1, right: 1},
+        rterm::Span{y: 3, left: 0, right: 2},
+        rterm::Span{y: 4, left: 0, right: 2},
+    ]);
+    assert_eq!(cb.take_scrolls(), vec![rterm::Scroll{top: 1, bottom: 4, count: 2}]);
+    assert!(cb.take_scrolls().is_empty());
+
+    cb.clear_damage();
+    cb.scroll(0, 5, -1);
+    assert_eq!(first_column(&cb), " ade  ");
+    assert_eq!(cb.dirty_spans(), vec![rterm::Span{y: 0, left: 0, right: 2}]);
+
+    // scrolling by more than the area height clears it
+    cb.clear_damage();
+    cb.scroll(0, 2, 5);
+    assert_eq!(first_column(&cb), "   e  ");
+    assert!(cb.take_scrolls().is_empty());
+    assert_eq!(cb.dirty_spans().len(), 3);
+}
+
+/* Scroll is detected by comparing with the front buffer */
+#[test]
+fn detect_scroll() {
+    let mut front = rterm::CellBuf::new(3, 8);
+    let mut back = rterm::CellBuf::new(3, 8);
+    fill_rows(&mut front, "abcdefgh");
+
+    fill_rows(&mut back, "abdefgxy");
+    assert_eq!(back.detect_scroll(&front), Some(rterm::Scroll{top: 2, bottom: 6, count: 1}));
+
+    fill_rows(&mut back, "zabcdehh");
+    assert_eq!(back.detect_scroll(&front), Some(rterm::Scroll{top: 0, bottom: 5, count: -1}));
+
+    // too few rows moved
+    fill_rows(&mut back, "bcxxxxxx");
+    assert_eq!(back.detect_scroll(&front), None);
+
+    // after the terminal scrolls, only new rows differ
+    fill_rows(&mut back, "abdefgxy");
+    front.scroll(2, 6, 1);
+    assert_eq!(back.diff_spans(&front), vec![
+        rterm::Span{y: 6, left: 0, right: 2},
+        rterm::Span{y: 7, left: 0, right: 2},
+    ]);
+}
+
+fn put_rows(cb: &mut rterm::CellBuf, lines: &[&str]) {
+    for (y, line) in lines.iter().enumerate() {
+        for (x, ch) in line.chars().enumerate() {
+            cb.set_cell(x as i32, y as i32, cell(ch));
+        }
+    }
+}
+
+/* Fill and clear rectangles */
+#[test]
+fn fill_rect() {
+    let mut cb = rterm::CellBuf::new(6, 3);
+    put_rows(&mut cb, &["abcdef", "ghijkl", "mnopqr"]);
+    cb.clear_damage();
+
+    assert!(cb.fill_rect(&rterm::CellRect::with_size(1, 1, 10, 1), cell('*')));
+    assert_eq!(rows(&cb), vec!["abcdef", "g*****", "mnopqr"]);
+    assert_eq!(cb.dirty_spans(), vec![rterm::Span{y: 1, left: 1, right: 5}]);
+    assert!(!cb.fill_rect(&rterm::CellRect::with_size(6, 0, 2, 2), cell('*')));
+    assert!(!cb.fill_rect(&rterm::CellRect::with_size(1, 1, 0, 1), cell('*')));
+
+    // wide characters go in pairs, a cut wide character becomes a space
+    cb.set_cell(4, 2, cell('\u{4E2D}'));
+    cb.fill_rect(&rterm::CellRect::with_size(0, 2, 5, 1), cell('\u{6587}'));
+    assert_eq!(rows(&cb)[2], "\u{6587}\0\u{6587}\0  ");
+
+    cb.clear_damage();
+    assert!(cb.clear_rect(&rterm::CellRect::with_size(-1, -1, 3, 3)));
+    assert_eq!(rows(&cb), vec!["  cdef", "  ****", "\u{6587}\0\u{6587}\0  "]);
+    assert_eq!(cb.dirty_spans(), vec![
+        rterm::Span{y: 0, left: 0, right: 1},
+        rterm::Span{y: 1, left: 0, right: 1},
+    ]);
+}
+
+/* Copy overlapping rectangles */
+#[test]
+fn copy_rect() {
+    let mut cb = rterm::CellBuf::new(5, 4);
+    put_rows(&mut cb, &["abcde", "fghij", "klmno", "pqrst"]);
+
+    assert!(cb.copy_rect(&rterm::CellRect::with_size(0, 0, 3, 3), 1, 1));
+    assert_eq!(rows(&cb), vec!["abcde", "fabcj", "kfgho", "pklmt"]);
+    assert!(cb.copy_rect(&rterm::CellRect::with_size(1, 1, 3, 3), 0, 0));
+    assert_eq!(rows(&cb), vec!["abcde", "fghcj", "klmho", "pklmt"]);
+
+    // the part outside the buffer is skipped
+    assert!(cb.copy_rect(&rterm::CellRect::with_size(0, 0, 5, 1), 3, 3));
+    assert_eq!(rows(&cb)[3], "pklab");
+    assert!(!cb.copy_rect(&rterm::CellRect::with_size(0, 0, 2, 2), 5, 0));
+
+    // halves of wide characters are not copied
+    cb.set_cell(0, 0, cell('\u{4E2D}'));
+    cb.set_cell(2, 0, cell('\u{6587}'));
+    cb.copy_rect(&rterm::CellRect::with_size(1, 0, 2, 1), 1, 2);
+    assert_eq!(rows(&cb)[2], "k  ho");
+}
+
+/* Scroll a part of the buffer */
+#[test]
+fn scroll_rect() {
+    let mut cb = rterm::CellBuf::new(5, 4);
+    put_rows(&mut cb, &["abcde", "fghij", "klmno", "pqrst"]);
+    cb.clear_damage();
+
+    assert!(cb.scroll_rect(&rterm::CellRect::with_size(1, 1, 3, 3), 1, -1, cell('.')));
+    assert_eq!(rows(&cb), vec!["abcde", "f.lmj", "k.qro", "p...t"]);
+    assert!(cb.take_scrolls().is_empty());
+
+    // whole rows are scrolled
+    cb.clear_damage();
+    assert!(cb.scroll_rect(&rterm::CellRect::with_size(0, 0, 5, 4), 1, 0, cell('.')));
+    assert_eq!(rows(&cb), vec!["f.lmj", "k.qro", "p...t", "....."]);
+    assert_eq!(cb.take_scrolls(), vec![rterm::Scroll{top: 0, bottom: 3, count: 1}]);
+
+    assert!(cb.scroll_rect(&rterm::CellRect::with_size(0, 0, 2, 2), 0, 5, cell('x')));
+    assert_eq!(rows(&cb), vec!["xxlmj", "xxqro", "p...t", "....."]);
+}
+
+/* Copy rectangles between buffers */
+#[test]
+fn blit() {
+    let mut src = rterm::CellBuf::new(4, 2);
+    put_rows(&mut src, &["ab.d", "e.gh"]);
+    let mut dst = rterm::CellBuf::new(5, 3);
+    put_rows(&mut dst, &["12345", "67890", "ABCDE"]);
+    dst.clear_damage();
+
+    assert!(dst.blit(&src, &rterm::CellRect::with_size(0, 0, 4, 2), 2, 2));
+    assert_eq!(rows(&dst), vec!["12345", "67890", "ABab."]);
+    assert_eq!(dst.dirty_spans(), vec![rterm::Span{y: 2, left: 2, right: 4}]);
+    assert!(dst.blit(&src, &rterm::CellRect::with_size(1, 0, 3, 2), -1, 0));
+    assert_eq!(rows(&dst), vec![".d345", "gh890", "ABab."]);
+    assert!(!dst.blit(&src, &rterm::CellRect::with_size(0, 0, 4, 2), 5, 0));
+    assert!(!dst.blit(&src, &rterm::CellRect::with_size(4, 0, 2, 2), 0, 0));
+
+    // dots are transparent
+    src.set_cell(2, 1, cell('\u{4E2D}'));
+    assert!(dst.blit_transparent(&src, &rterm::CellRect::with_size(0, 0, 4, 2), 1, 0, &cell('.')));
+    assert_eq!(rows(&dst), vec![".ab4d", "ge8\u{4E2D}\0", "ABab."]);
+    // a continuation of a transparent character is transparent as well
+    assert!(dst.blit_transparent(&src, &rterm::CellRect::with_size(0, 1, 4, 1), 0, 2, &cell('\u{4E2D}')));
+    assert_eq!(rows(&dst)[2], "e.ab.");
+
+    // links and long clusters are registered in the target buffer
+    let mut src = rterm::CellBuf::new(2, 1);
+    let mut c = cell('x');
+    c.link = src.add_link("http://b");
+    let (ch, combining) = src.add_grapheme("e\u{301}\u{302}\u{303}");
+    src.set_cell(0, 0, c);
+    src.set_cell(1, 0, rterm::Cell{ch, combining, ..cell(' ')});
+    dst.add_link("http://a");
+    dst.blit(&src, &rterm::CellRect::with_size(0, 0, 2, 1), 0, 0);
+    let c = dst.get_cell(0, 0).unwrap();
+    assert_eq!(dst.get_link(c.link), Some("http://b"));
+    assert_eq!(dst.get_grapheme(&dst.get_cell(1, 0).unwrap()), "e\u{301}\u{302}\u{303}");
+}
+
+/* Measuring and truncating text agrees with put_string */
+#[test]
+fn measure() {
+    let mut cb = rterm::CellBuf::new(6, 1);
+    assert_eq!(cb.text_width("a漢e\u{301}"), 4);
+    assert_eq!(cb.text_width("…"), 1);
+    assert_eq!(cb.split_at_column("ab漢c", 3), ("ab", "漢c"));
+    assert_eq!(cb.split_at_column("ab漢c", 4), ("ab漢", "c"));
+    assert_eq!(cb.split_at_column("ab", 5), ("ab", ""));
+    assert_eq!(cb.truncate("e\u{301}x", 1), "e\u{301}");
+    assert_eq!(cb.truncate_with_ellipsis("abcdef", 6), "abcdef");
+    assert_eq!(cb.truncate_with_ellipsis("abc漢def", 5), "abc…");
+    assert_eq!(cb.truncate_with_ellipsis("abc", 0), "");
+    assert_eq!(cb.pad("ab漢", 3, rterm::ALIGN_LEFT), "ab ");
+    assert_eq!(cb.pad("ab", 5, rterm::ALIGN_CENTER), " ab  ");
+    assert_eq!(cb.pad("ab", 4, rterm::ALIGN_RIGHT), "  ab");
+
+    // the part of a string put_string displays in a region is the truncated
+    // string padded with spaces
+    {
+        let mut cv = rterm::Canvas::new(&mut cb);
+        cv.put_string(0, 0, "ab漢cdefg");
+        let mut r = cv.region(&rterm::CellRect::with_size(3, 0, 3, 1));
+        r.put_string(0, 0, "xy漢");
+    }
+    let shown: String = (0..6).map(|x| cb.get_cell(x, 0).unwrap().ch).collect();
+    assert_eq!(shown, format!("{}{}", cb.pad("ab漢cdefg", 3, rterm::ALIGN_LEFT), cb.pad("xy漢", 3, rterm::ALIGN_LEFT)));
+
+    cb.set_ambiguous_wide(true);
+    assert_eq!(cb.text_width("…"), 2);
+    assert_eq!(cb.truncate_with_ellipsis("abcdef", 4), "ab…");
 }
@@ -0,0 +1,62 @@
+extern crate rterm;
+
+/* Exact palette colors must be found as is */
+#[test]
+fn rgb_to_palette() {
+    assert_eq!(rterm::rgb_to_256(0, 0, 0), 16);
+    assert_eq!(rterm::rgb_to_256(255, 255, 255), 231);
+    assert_eq!(rterm::rgb_to_256(95, 135, 175), 16 + 36 + 2 * 6 + 3);
+    assert_eq!(rterm::rgb_to_256(128, 128, 128), 244);
+    assert_eq!(rterm::rgb_to_256(250, 5, 3), 196);
+
+    assert_eq!(rterm::rgb_to_16(250, 5, 3), 9);
+    assert_eq!(rterm::rgb_to_16(200, 10, 190), 5);
+    assert_eq!(rterm::rgb_to_16(10, 10, 10), 0);
+
+    assert_eq!(rterm::rgb_to_gray(0, 0, 0), 16);
+    assert_eq!(rterm::rgb_to_gray(255, 255, 255), 231);
+    assert_eq!(rterm::rgb_to_gray(128, 128, 128), 244);
+}
+
+/* Conversion between modes keeps text attributes */
+#[test]
+fn quantize() {
+    let c = rterm::rgb(250, 5, 3) | rterm::ATTR_BOLD;
+
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_TRUECOLOR), c);
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_256), rterm::palette(196) | rterm::ATTR_BOLD);
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_NORMAL), rterm::palette(9) | rterm::ATTR_BOLD);
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_NO_COLOR), rterm::COLOR_DEFAULT | rterm::ATTR_BOLD);
+
+    let c = rterm::palette(196);
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_256), c);
+    assert_eq!(rterm::quantize(c, rterm::OUTPUT_NORMAL), rterm::palette(9));
+    assert_eq!(rterm::quantize(rterm::COLOR_RED, rterm::OUTPUT_NORMAL), rterm::COLOR_RED);
+    assert_eq!(rterm::quantize(rterm::COLOR_WHITE, rterm::OUTPUT_GRAYSCALE), rterm::palette(254));
+    assert_eq!(rterm::quantize(rterm::COLOR_DEFAULT, rterm::OUTPUT_GRAYSCALE), rterm::COLOR_DEFAULT);
+}
+
+/* Detects color support by environment variables */
+#[test]
+fn detect_mode() {
+    assert_eq!(rterm::output_mode_from_env("truecolor", "xterm-256color", false), rterm::OUTPUT_TRUECOLOR);
+    assert_eq!(rterm::output_mode_from_env("", "xterm-256color", false), rterm::OUTPUT_256);
+    assert_eq!(rterm::output_mode_from_env("", "xterm", false), rterm::OUTPUT_NORMAL);
+    assert_eq!(rterm::output_mode_from_env("", "", false), rterm::OUTPUT_NORMAL);
+    assert_eq!(rterm::output_mode_from_env("", "dumb", false), rterm::OUTPUT_NO_COLOR);
+    assert_eq!(rterm::output_mode_from_env("truecolor", "xterm-256color", true), rterm::OUTPUT_NO_COLOR);
+}
+
+/* Parses color names */
+#[test]
+fn parse() {
+    assert_eq!(rterm::parse_color("default"), Some(rterm::COLOR_DEFAULT));
+    assert_eq!(rterm::parse_color("Red"), Some(rterm::COLOR_RED));
+    assert_eq!(rterm::parse_color("bright-white"), Some(rterm::palette(15)));
+    assert_eq!(rterm::parse_color("208"), Some(rterm::palette(208)));
+    assert_eq!(rterm::parse_color("#0a0b0C"), Some(rterm::rgb(10, 11, 12)));
+    assert_eq!(rterm::parse_color("#0a0b0"), None);
+    assert_eq!(rterm::parse_color("#0a0b0g"), None);
+    assert_eq!(rterm::parse_color("256"), None);
+    assert_eq!(rterm::parse_color("bright-pink"), None);
+}
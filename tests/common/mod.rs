@@ -0,0 +1,14 @@
+// Helpers shared by integration tests
+#![allow(dead_code)]
+
+use rterm::{Cell, CellBuf};
+
+// Returns a cell with default attributes
+pub fn cell(ch: char) -> Cell {
+    Cell{ch, fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None}
+}
+
+// Returns characters of the buffer row by row
+pub fn rows(cb: &CellBuf) -> Vec<String> {
+    (0..cb.height).map(|y| (0..cb.width).map(|x| cb.get_cell(x, y).unwrap().ch).collect()).collect()
+}
@@ -14,6 +14,9 @@ fn create() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
 
     let (ww, hh) = cb.get_size();
@@ -36,6 +39,9 @@ fn put_char() {
         ch: ' ',
         bg: rterm::COLOR_DEFAULT,
         fg: rterm::COLOR_DEFAULT,
+        style: rterm::Style::default(),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
     };
 
     let mut v = vec![cl; sz];
@@ -115,6 +121,40 @@ fn put_string_vertical() {
     assert!(cb.dirty());
 }
 
+// Grapheme clusters take one cell
+#[test]
+fn put_graphemes() {
+    let mut cb = rterm::Terminal::new();
+
+    cb.put_string(0, 0, "e\u{301}x\u{1F1FA}\u{1F1F8}y");
+    assert_eq!(cb.get_grapheme(0, 0).unwrap(), "e\u{301}");
+    assert_eq!(cb.get_grapheme(1, 0).unwrap(), "x");
+    assert_eq!(cb.get_grapheme(2, 0).unwrap(), "\u{1F1FA}\u{1F1F8}");
+    assert!(cb.get_cell(3, 0).unwrap().is_continuation());
+    assert_eq!(cb.get_grapheme(4, 0).unwrap(), "y");
+
+    cb.put_string_vertical(0, 1, "a\u{308}b");
+    assert_eq!(cb.get_grapheme(0, 1).unwrap(), "a\u{308}");
+    assert_eq!(cb.get_grapheme(0, 2).unwrap(), "b");
+}
+
+// Ambiguous width characters take two columns in wide mode
+#[test]
+fn ambiguous_width() {
+    let mut cb = rterm::Terminal::new();
+
+    assert_eq!(cb.get_ambiguous_width(), rterm::AMBIGUOUS_NARROW);
+    cb.put_string(0, 0, "\u{2500}\u{2500}a");
+    assert_eq!(cb.get_cell(1, 0).unwrap().ch, '\u{2500}');
+    assert_eq!(cb.get_cell(2, 0).unwrap().ch, 'a');
+
+    assert_eq!(cb.set_ambiguous_width(rterm::AMBIGUOUS_WIDE), rterm::AMBIGUOUS_WIDE);
+    cb.put_string(0, 1, "\u{2500}\u{2500}a");
+    assert!(cb.get_cell(1, 1).unwrap().is_continuation());
+    assert_eq!(cb.get_cell(2, 1).unwrap().ch, '\u{2500}');
+    assert_eq!(cb.get_cell(4, 1).unwrap().ch, 'a');
+}
+
 // Put vertical line
 #[test]
 fn put_line_vertical() {
@@ -185,3 +225,104 @@ fn flush() {
     cb.flush();
     assert!(!cb.dirty());
 }
+
+// Double buffered mode
+#[test]
+fn double_buffer() {
+    let mut cb = rterm::Terminal::new();
+
+    assert!(!cb.is_double_buffer());
+    cb.set_double_buffer(true);
+    assert!(cb.is_double_buffer());
+    cb.put_string(0, 0, "frame");
+    cb.flush();
+    assert!(!cb.dirty());
+
+    // the same frame drawn again
+    cb.clear();
+    cb.put_string(0, 0, "frame");
+    assert!(cb.dirty());
+    cb.flush();
+    assert!(!cb.dirty());
+
+    cb.set_double_buffer(false);
+    assert!(!cb.is_double_buffer());
+}
+
+// Scroll moves rows of the buffer
+#[test]
+fn scroll() {
+    let mut cb = rterm::Terminal::new();
+
+    cb.put_string(0, 1, "first");
+    cb.put_string(0, 2, "second");
+    cb.flush();
+    cb.scroll(0, 2, 1);
+    assert_eq!(cb.get_cell(0, 0).unwrap().ch, 'f');
+    assert_eq!(cb.get_cell(0, 1).unwrap().ch, 's');
+    assert_eq!(cb.get_cell(0, 2).unwrap().ch, ' ');
+    cb.flush();
+    assert!(!cb.dirty());
+}
+
+// Synchronized output can be forced on and off
+#[test]
+fn sync_output() {
+    let mut cb = rterm::Terminal::new();
+
+    assert_eq!(cb.get_sync_output(), rterm::SYNC_OFF);
+    assert_eq!(cb.set_sync_output(rterm::SYNC_ON), rterm::SYNC_ON);
+    cb.put_string(0, 0, "frame");
+    cb.flush();
+    assert!(!cb.dirty());
+    assert_eq!(cb.set_sync_output(rterm::SYNC_OFF), rterm::SYNC_OFF);
+}
+
+// Layers are drawn above the buffer
+#[test]
+fn layers() {
+    let mut cb = rterm::Terminal::new();
+
+    cb.put_string(0, 0, "main");
+    cb.flush();
+    let mut popup = rterm::Layer::new("popup", 3, 1, 1);
+    popup.buffer.set_cell(0, 0, rterm::Cell{ch: '#', fg: rterm::COLOR_DEFAULT, bg: rterm::COLOR_DEFAULT, style: rterm::Style::default(), link: rterm::NO_LINK, combining: rterm::Combining::None});
+    cb.layers_mut().add(popup);
+    assert!(cb.dirty());
+    cb.flush();
+    assert!(!cb.dirty());
+    assert_eq!(cb.get_cell(0, 0).unwrap().ch, 'm');
+    assert_eq!(cb.layers().composite().unwrap().get_cell(0, 0).unwrap().ch, '#');
+
+    cb.layers_mut().remove("popup");
+    cb.flush();
+    assert!(cb.layers().composite().is_none());
+}
+
+// Put text with theme styles
+#[test]
+fn put_themed() {
+    let mut cb = rterm::Terminal::new();
+    let mut th = rterm::Theme::new();
+    th.load_str("error = fg:red bold").unwrap();
+    cb.set_theme(th);
+    cb.set_foreground(rterm::COLOR_BLUE);
+
+    let r = cb.put_string_themed(0, 0, "ab", "error");
+    assert!(r);
+    let c = cb.get_cell(1, 0).unwrap();
+    assert_eq!(c.fg, rterm::COLOR_RED);
+    assert_eq!(c.style.attrs, rterm::STYLE_BOLD);
+    assert_eq!(cb.get_foreground(), rterm::COLOR_BLUE);
+
+    let id = cb.get_theme().id("error").unwrap();
+    cb.put_char_themed(0, 1, 'x', id);
+    assert_eq!(cb.get_cell(0, 1).unwrap().fg, rterm::COLOR_RED);
+
+    cb.put_char_themed(0, 2, 'x', "unknown");
+    assert_eq!(cb.get_cell(0, 2).unwrap().fg, rterm::COLOR_BLUE);
+
+    assert!(cb.use_style("error"));
+    assert_eq!(cb.get_foreground(), rterm::COLOR_RED);
+    assert!(!cb.use_style("unknown"));
+}
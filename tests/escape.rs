@@ -0,0 +1,245 @@
+extern crate rterm;
+
+use rterm::TerminalManager;
+
+fn cell(fg: rterm::Attribute, bg: rterm::Attribute, attrs: rterm::StyleAttr) -> rterm::Cell {
+    rterm::Cell{
+        ch: 'a',
+        fg,
+        bg,
+        style: rterm::Style::new(attrs),
+        link: rterm::NO_LINK,
+        combining: rterm::Combining::None,
+    }
+}
+
+/* Colors and styles are converted to SGR parameters */
+#[test]
+fn sgr() {
+    let c = cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0);
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_TRUECOLOR), "\x1b[0m");
+
+    let c = cell(rterm::COLOR_RED | rterm::ATTR_BOLD, rterm::palette(12), rterm::STYLE_ITALIC);
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_TRUECOLOR), "\x1b[0;1;3;31;104m");
+    // bold background is not bold text but a bright background
+    let c = cell(rterm::COLOR_RED, rterm::palette(4) | rterm::ATTR_BOLD, 0);
+    assert_eq!(c.full_style().attrs & rterm::STYLE_BOLD, 0);
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_NORMAL), "\x1b[0;31;104m");
+    let c = cell(rterm::COLOR_DEFAULT, rterm::palette(100) | rterm::ATTR_BOLD, 0);
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_256), "\x1b[0;48;5;100m");
+
+    let c = cell(rterm::rgb(1, 2, 3), rterm::palette(100), rterm::STYLE_STRIKETHROUGH | rterm::STYLE_DIM);
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_TRUECOLOR), "\x1b[0;2;9;38;2;1;2;3;48;5;100m");
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_NO_COLOR), "\x1b[0;2;9m");
+
+    let mut c = cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, rterm::STYLE_CURLY_UNDERLINE);
+    c.style.underline_color = rterm::COLOR_RED;
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_256), "\x1b[0;4:3;58;5;1m");
+    c.fg = rterm::ATTR_UNDERLINE;
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_256), "\x1b[0;4:3;58;5;1m");
+    c.style = rterm::Style::default();
+    assert_eq!(rterm::sgr_sequence(&c, rterm::OUTPUT_256), "\x1b[0;4m");
+}
+
+/* Only dirty area is sent to terminal */
+#[test]
+fn write() {
+    let mut cb = rterm::CellBuf::new(4, 3);
+    let mut term = rterm::EscTerminal::new(Vec::new(), 4, 3);
+    term.set_output_mode(rterm::OUTPUT_NORMAL);
+
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+    cb.set_cell(1, 1, cell(rterm::COLOR_GREEN, rterm::COLOR_DEFAULT, 0));
+    cb.set_cell(2, 1, cell(rterm::COLOR_GREEN, rterm::COLOR_DEFAULT, 0));
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[2;2H\x1b[0;32maa\x1b[0m\x1b[H");
+}
+
+/* Continuation cells of wide characters are never printed */
+#[test]
+fn write_wide() {
+    let mut cb = rterm::CellBuf::new(4, 1);
+    let mut term = rterm::EscTerminal::new(Vec::new(), 4, 1);
+    term.set_output_mode(rterm::OUTPUT_NORMAL);
+
+    let mut c = cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0);
+    c.ch = '中';
+    cb.set_cell(0, 0, c);
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+    cb.clear_damage();
+
+    // only the continuation is dirty: the character is redrawn from its first column
+    let mut c = cb.get_cell(1, 0).unwrap();
+    c.fg = rterm::COLOR_RED;
+    cb.set_cell(0, 0, rterm::Cell{ch: '中', ..c});
+    term.write(&cb, &[rterm::Span{y: 0, left: 1, right: 1}]).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[H\x1b[0m中\r\x1b[31m中\x1b[0m\r");
+}
+
+/* The whole grapheme cluster is printed */
+#[test]
+fn write_grapheme() {
+    let mut cb = rterm::CellBuf::new(2, 1);
+    let mut term = rterm::EscTerminal::new(Vec::new(), 2, 1);
+    term.set_output_mode(rterm::OUTPUT_NORMAL);
+
+    let mut c = cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0);
+    let (ch, comb) = cb.add_grapheme("e\u{301}");
+    c.ch = ch;
+    c.combining = comb;
+    cb.set_cell(0, 0, c);
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[H\x1b[0me\u{301}\r");
+}
+
+/* Hyperlinks are closed at the end of every redrawn span */
+#[test]
+fn hyperlink() {
+    let mut cb = rterm::CellBuf::new(4, 2);
+    let term = rterm::EscTerminal::new(Vec::new(), 4, 2);
+
+    let id = cb.add_link("http://a.b/\x1bc");
+    assert_eq!(cb.get_link(id), Some("http://a.b/c"));
+    let mut c = cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0);
+    c.link = id;
+    cb.set_cell(2, 0, c.clone());
+    cb.set_cell(3, 0, c.clone());
+    cb.set_cell(0, 1, c.clone());
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    let start = "\x1b]8;id=1;http://a.b/c\x1b\\";
+    let end = "\x1b]8;;\x1b\\";
+    assert_eq!(out, format!("\x1b[1;3H{}\x1b[0maa{}\x1b[2H{}a{}\x1b[H", start, end, start, end));
+}
+
+/* Encoder uses the shortest cursor moves */
+#[test]
+fn encoder_moves() {
+    let mut enc = rterm::Encoder::new();
+
+    enc.move_to(5, 3);
+    assert_eq!(enc.take(), "\x1b[4;6H");
+    enc.move_to(5, 3);
+    assert_eq!(enc.take(), "");
+    enc.move_to(7, 3);
+    assert_eq!(enc.take(), "\x1b[2C");
+    enc.move_to(6, 3);
+    assert_eq!(enc.take(), "\x1b[D");
+    enc.move_to(0, 3);
+    assert_eq!(enc.take(), "\r");
+    enc.move_to(0, 5);
+    assert_eq!(enc.take(), "\r\n\n");
+    enc.move_to(0, 4);
+    assert_eq!(enc.take(), "\x1b[A");
+    enc.move_to(0, 2);
+    assert_eq!(enc.take(), "\x1b[3H");
+    enc.move_to(40, 20);
+    assert_eq!(enc.take(), "\x1b[21;41H");
+
+    // the cursor is unknown after printing in the last column
+    enc.put("ab", 2, 42);
+    enc.move_to(0, 21);
+    assert_eq!(enc.take(), "ab\x1b[22H");
+
+    enc.reset();
+    enc.move_to(0, 0);
+    assert_eq!(enc.take(), "\x1b[H");
+}
+
+/* Encoder emits only changed SGR parameters */
+#[test]
+fn encoder_sgr() {
+    let mut enc = rterm::Encoder::new();
+    let mode = rterm::OUTPUT_256;
+
+    enc.set_style(&cell(rterm::COLOR_RED, rterm::COLOR_DEFAULT, rterm::STYLE_BOLD | rterm::STYLE_ITALIC), mode);
+    assert_eq!(enc.take(), "\x1b[0;1;3;31m");
+    enc.set_style(&cell(rterm::COLOR_RED, rterm::COLOR_DEFAULT, rterm::STYLE_BOLD | rterm::STYLE_ITALIC), mode);
+    assert_eq!(enc.take(), "");
+    enc.set_style(&cell(rterm::COLOR_RED, rterm::COLOR_BLUE, rterm::STYLE_BOLD), mode);
+    assert_eq!(enc.take(), "\x1b[23;44m");
+    enc.set_style(&cell(rterm::COLOR_RED, rterm::COLOR_BLUE, rterm::STYLE_DIM), mode);
+    assert_eq!(enc.take(), "\x1b[22;2m");
+    enc.set_style(&cell(rterm::COLOR_GREEN, rterm::COLOR_BLUE, rterm::STYLE_DIM | rterm::STYLE_UNDERLINE), mode);
+    assert_eq!(enc.take(), "\x1b[4;32m");
+    enc.set_style(&cell(rterm::COLOR_GREEN, rterm::COLOR_BLUE | rterm::ATTR_BOLD, rterm::STYLE_DIM | rterm::STYLE_UNDERLINE), mode);
+    assert_eq!(enc.take(), "\x1b[104m");
+    // full reset is shorter
+    enc.set_style(&cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0), mode);
+    assert_eq!(enc.take(), "\x1b[0m");
+
+    // the reset is sent only if some attributes are set
+    enc.reset_style();
+    assert_eq!(enc.take(), "");
+    enc.set_style(&cell(rterm::COLOR_RED, rterm::COLOR_DEFAULT, 0), mode);
+    enc.reset_style();
+    assert_eq!(enc.take(), "\x1b[31m\x1b[0m");
+}
+
+/* Trailing blanks of a line are erased */
+#[test]
+fn write_erase() {
+    let mut cb = rterm::CellBuf::new(10, 2);
+    let term = rterm::EscTerminal::new(Vec::new(), 10, 2);
+
+    cb.invalidate();
+    cb.set_cell(1, 0, cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0));
+    cb.set_cell(8, 1, cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0));
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[H\x1b[0m a\x1b[K\r\n        a \x1b[H");
+}
+
+/* Scroll uses a scroll region */
+#[test]
+fn scroll() {
+    let term = rterm::EscTerminal::new(Vec::new(), 10, 10);
+
+    term.scroll(2, 8, 1).unwrap();
+    term.scroll(0, 9, -3).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[0m\x1b[3;9r\x1b[S\x1b[r\x1b[1;10r\x1b[3T\x1b[r");
+}
+
+/* Control sequences keep the encoder state, raw strings reset it */
+#[test]
+fn write_control() {
+    let mut cb = rterm::CellBuf::new(4, 1);
+    let mut term = rterm::EscTerminal::new(Vec::new(), 4, 1);
+    term.set_output_mode(rterm::OUTPUT_NORMAL);
+
+    cb.set_cell(1, 0, cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0));
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+    cb.clear_damage();
+    term.write_control("\x1b[?2026h").unwrap();
+    cb.set_cell(2, 0, cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0));
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+    cb.clear_damage();
+    term.write_raw("x").unwrap();
+    cb.set_cell(3, 0, cell(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, 0));
+    term.write(&cb, &cb.dirty_spans()).unwrap();
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[1;2H\x1b[0ma\r\x1b[?2026h\x1b[2Ca\rx\x1b[1;4H\x1b[0ma\x1b[H");
+}
+
+/* The cursor can be moved but its position cannot be read back */
+#[test]
+fn cursor() {
+    let term = rterm::EscTerminal::new(Vec::new(), 10, 10);
+
+    term.set_cursor_pos(2, 1).unwrap();
+    assert!(term.get_cursor_pos().is_err());
+
+    let out = String::from_utf8(term.into_inner()).unwrap();
+    assert_eq!(out, "\x1b[2;3H");
+}
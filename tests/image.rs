@@ -0,0 +1,174 @@
+extern crate rterm;
+
+mod common;
+
+use std::cell::RefCell;
+
+use rterm::{CellBuf, CellRect, Image, Span, TerminalManager};
+use common::{cell, rows};
+
+// Terminal that only records scrolls
+#[derive(Default)]
+struct ScrollRecorder {
+    scrolls: RefCell<Vec<(i32, i32, i32)>>,
+}
+
+impl TerminalManager for ScrollRecorder {
+    fn write(&self, _buf: &CellBuf, _spans: &[Span]) -> Result<(), String> {
+        Ok(())
+    }
+    fn set_output_mode(&mut self, mode: rterm::OutputMode) -> rterm::OutputMode {
+        mode
+    }
+    fn size(&self) -> Result<rterm::Point, String> {
+        Ok(rterm::Point{x: 10, y: 6})
+    }
+    fn set_cursor_pos(&self, _x: i16, _y: i16) -> Result<(), String> {
+        Ok(())
+    }
+    fn get_cursor_pos(&self) -> Result<rterm::CursorInfo, String> {
+        Err(String::from("unknown"))
+    }
+    fn write_raw(&self, _s: &str) -> Result<(), String> {
+        Ok(())
+    }
+    fn scroll(&self, top: i32, bottom: i32, count: i32) -> Result<(), String> {
+        self.scrolls.borrow_mut().push((top, bottom, count));
+        Ok(())
+    }
+}
+
+/* Images are created from RGB and RGBA data and scaled */
+#[test]
+fn image_data() {
+    assert!(Image::from_rgba(2, 1, vec![0; 7]).is_err());
+    assert!(Image::from_rgb(2, 1, &[0; 5]).is_err());
+
+    let img = Image::from_rgb(2, 2, &[255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]).unwrap();
+    assert_eq!(img.size(), (2, 2));
+    assert_eq!(img.pixel(1, 0), Some((0, 255, 0, 255)));
+    assert_eq!(img.pixel(2, 0), None);
+
+    let small = img.scale(1, 1);
+    assert_eq!(small.size(), (1, 1));
+    assert_eq!(small.pixel(0, 0), Some((127, 127, 127, 255)));
+    let big = img.scale(4, 2);
+    assert_eq!(big.pixel(0, 0), Some((255, 0, 0, 255)));
+    assert_eq!(big.pixel(1, 0), Some((255, 0, 0, 255)));
+    assert_eq!(big.pixel(3, 1), Some((255, 255, 255, 255)));
+
+    // half blocks: top pixel is the text color, bottom one is background
+    let pc = img.to_pixel_canvas(2, 1);
+    let mut cb = CellBuf::new(2, 1);
+    pc.render(&mut cb, &CellRect::with_size(0, 0, 2, 1));
+    let c = cb.get_cell(0, 0).unwrap();
+    assert_eq!(c.ch, '▀');
+    assert_eq!((c.fg, c.bg), (rterm::rgb(255, 0, 0), rterm::rgb(0, 0, 255)));
+}
+
+/* Sixel and kitty graphics sequences */
+#[test]
+fn sequences() {
+    let mut data = [255, 0, 0, 255].repeat(5);
+    data.extend_from_slice(&[0, 0, 0, 0]);
+    let img = Image::from_rgba(1, 6, data).unwrap();
+    assert_eq!(rterm::sixel_sequence(&img), "\x1bP0;1;0q\"1;1;1;6#180;2;100;0;0#180^\x1b\\");
+
+    let img = Image::from_rgb(5, 7, &[0; 105]).unwrap();
+    assert_eq!(rterm::sixel_sequence(&img), "\x1bP0;1;0q\"1;1;5;7#0;2;0;0;0#0!5~-#0!5@\x1b\\");
+
+    let img = Image::from_rgba(1, 1, vec![1, 2, 3, 4]).unwrap();
+    assert_eq!(rterm::kitty_sequence(&img, 7, 2, 1),
+        "\x1b_Ga=T,f=32,s=1,v=1,i=7,c=2,r=1,C=1,q=2,m=0;AQIDBA==\x1b\\");
+    assert_eq!(rterm::kitty_delete_sequence(7), "\x1b_Ga=d,d=I,i=7,q=2\x1b\\");
+
+    // large images are sent in chunks
+    let img = Image::from_rgba(1024, 3, vec![0; 1024 * 3 * 4]).unwrap();
+    let s = rterm::kitty_sequence(&img, 1, 10, 1);
+    assert_eq!(s.matches("\x1b_G").count(), 4);
+    assert!(s.contains(",m=1;"));
+    assert!(s.contains("\x1b_Gm=0;"));
+}
+
+/* Output spans skip cells under images */
+#[test]
+fn spans_outside() {
+    let spans = vec![Span{y: 0, left: 0, right: 9}, Span{y: 1, left: 2, right: 3}, Span{y: 5, left: 0, right: 9}];
+    let rects = vec![CellRect::with_size(3, 0, 2, 2), CellRect::with_size(8, 0, 5, 1)];
+    assert_eq!(rterm::spans_outside(&spans, &rects), vec![
+        Span{y: 0, left: 0, right: 2},
+        Span{y: 0, left: 5, right: 7},
+        Span{y: 1, left: 2, right: 2},
+        Span{y: 5, left: 0, right: 9},
+    ]);
+}
+
+/* Placed images reserve cells until they are removed */
+#[test]
+fn placement() {
+    let mut cb = CellBuf::new(6, 3);
+    let img = Image::from_rgb(1, 1, &[0, 0, 0]).unwrap();
+    let id = cb.place_image(&CellRect::with_size(1, 1, 2, 2), img.clone());
+    let id2 = cb.place_image(&CellRect::with_size(4, 0, 1, 1), img);
+    assert!(id != id2);
+    assert!(cb.is_reserved(2, 2));
+    assert!(!cb.is_reserved(0, 1));
+    assert_eq!(cb.images().len(), 2);
+
+    let (placed, removed) = cb.take_image_updates();
+    assert_eq!(placed.iter().map(|p| p.id()).collect::<Vec<_>>(), vec![id, id2]);
+    assert!(placed.iter().all(|p| p.is_shown()));
+    assert!(removed.is_empty());
+    let (placed, _) = cb.take_image_updates();
+    assert!(placed.is_empty());
+
+    cb.clear_damage();
+    assert!(cb.remove_image(id));
+    assert!(!cb.remove_image(id));
+    assert!(!cb.is_reserved(2, 2));
+    assert_eq!(cb.dirty_spans(), vec![Span{y: 1, left: 1, right: 2}, Span{y: 2, left: 1, right: 2}]);
+    let (placed, removed) = cb.take_image_updates();
+    assert!(placed.is_empty());
+    assert_eq!(removed.len(), 1);
+    assert_eq!(removed[0].id(), id);
+
+    // clearing the buffer removes all images
+    cb.clear();
+    assert!(cb.images().is_empty());
+    assert_eq!(cb.take_image_updates().1.len(), 1);
+    assert_eq!(rows(&cb), vec!["      "; 3]);
+}
+
+/* The terminal does not scroll rows with images, they are redrawn instead */
+#[test]
+fn scroll_images() {
+    let term = ScrollRecorder::default();
+    let mut cb = CellBuf::new(10, 6);
+    let img = Image::from_rgb(1, 1, &[0, 0, 0]).unwrap();
+    cb.place_image(&CellRect::with_size(2, 1, 3, 2), img);
+    let reserved: Vec<CellRect> = cb.images().iter().map(|img| img.rect().clone()).collect();
+    cb.clear_damage();
+
+    cb.scroll(3, 5, 1);
+    cb.scroll(0, 5, -1);
+    cb.scroll(4, 5, 1);
+    rterm::flush_scrolls(&term, &mut cb, &mut None, &reserved);
+    assert_eq!(*term.scrolls.borrow(), vec![(3, 5, 1)]);
+    assert_eq!(cb.dirty_spans(), (0..6).map(|y| Span{y, left: 0, right: 9}).collect::<Vec<_>>());
+
+    // in double buffered mode detected scrolls are skipped as well
+    let mut front = CellBuf::new(10, 6);
+    for (y, ch) in "abcdef".chars().enumerate() {
+        for x in 0..10 {
+            front.set_cell(x, y as i32, cell(ch));
+            cb.set_cell(x, y as i32, cell(ch));
+        }
+    }
+    cb.scroll(0, 5, 1);
+    cb.take_scrolls();
+    cb.clear_damage();
+    assert!(cb.detect_scroll(&front).is_some());
+    rterm::flush_scrolls(&term, &mut cb, &mut Some(front), &reserved);
+    assert_eq!(term.scrolls.borrow().len(), 1);
+    assert_eq!(cb.dirty_spans().len(), 6);
+}
@@ -0,0 +1,96 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{CellBuf, CellRect, Layer, LayerStack};
+use common::{cell, rows};
+
+/* Layers are sorted by z-order, names are unique */
+#[test]
+fn stack() {
+    let mut st = LayerStack::new();
+    assert!(st.is_empty());
+    st.add(Layer::new("dialog", 2, 2, 10));
+    st.add(Layer::new("menu", 2, 2, 5));
+    st.add(Layer::new("tooltip", 2, 2, 10));
+    assert_eq!(st.names(), vec!["menu", "dialog", "tooltip"]);
+
+    assert!(st.set_z("dialog", 10));
+    assert_eq!(st.names(), vec!["menu", "tooltip", "dialog"]);
+    assert!(!st.set_z("popup", 1));
+
+    st.add(Layer::new("menu", 3, 3, 20));
+    assert_eq!(st.names(), vec!["tooltip", "dialog", "menu"]);
+    assert_eq!(st.get("menu").unwrap().buffer.width, 3);
+    assert_eq!(st.remove("tooltip").unwrap().z(), 10);
+    assert!(st.remove("tooltip").is_none());
+    assert_eq!(st.names(), vec!["dialog", "menu"]);
+}
+
+/* Composed picture changes only where layers and the buffer change */
+#[test]
+fn compose() {
+    let mut base = CellBuf::new(4, 3);
+    base.fill_rect(&CellRect::with_size(0, 0, 4, 3), cell('.'));
+    let mut st = LayerStack::new();
+    st.compose(&mut base);
+    assert!(!st.is_composing());
+
+    let mut popup = Layer::new("popup", 2, 2, 0);
+    popup.buffer.fill_rect(&CellRect::with_size(0, 0, 2, 2), cell('#'));
+    popup.buffer.set_cell(1, 1, cell(' '));
+    popup.transparent = Some(cell(' '));
+    popup.x = 1;
+    popup.y = 2;
+    st.add(popup);
+
+    assert!(st.dirty());
+    st.compose(&mut base);
+    assert_eq!(rows(st.composite().unwrap()), vec!["....", "....", ".##."]);
+    assert_eq!(st.composite().unwrap().dirty_spans().len(), 3);
+    st.clear_damage();
+    base.clear_damage();
+    assert!(!st.dirty());
+
+    // nothing is composed without changes
+    st.compose(&mut base);
+    assert!(st.composite().unwrap().dirty_spans().is_empty());
+
+    // moving the layer repaints only the old and new places
+    {
+        let p = st.get_mut("popup").unwrap();
+        p.x = 0;
+        p.y = 0;
+    }
+    assert!(st.dirty());
+    st.compose(&mut base);
+    assert_eq!(rows(st.composite().unwrap()), vec!["##..", "#...", "...."]);
+    assert_eq!(st.composite().unwrap().dirty_spans(), vec![
+        rterm::Span{y: 0, left: 0, right: 1},
+        rterm::Span{y: 1, left: 0, right: 0},
+        rterm::Span{y: 2, left: 1, right: 2},
+    ]);
+    st.clear_damage();
+
+    // changes under a layer are hidden
+    base.set_cell(0, 0, cell('x'));
+    base.set_cell(3, 0, cell('y'));
+    st.compose(&mut base);
+    assert_eq!(st.composite().unwrap().dirty_spans(), vec![rterm::Span{y: 0, left: 3, right: 3}]);
+    st.clear_damage();
+
+    base.clear_damage();
+    st.get_mut("popup").unwrap().visible = false;
+    assert!(st.dirty());
+    st.compose(&mut base);
+    assert_eq!(rows(st.composite().unwrap()), vec!["x..y", "....", "...."]);
+    st.clear_damage();
+
+    // without layers the picture is kept until the next clear
+    st.remove("popup");
+    assert!(st.dirty());
+    st.compose(&mut base);
+    assert!(st.is_composing());
+    st.clear_damage();
+    assert!(!st.is_composing());
+}
@@ -0,0 +1,57 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{Canvas, CellBuf, CellRect, StyledText, TextAttrs};
+use common::rows;
+
+/* Markup is split into spans with attributes of all open tags */
+#[test]
+fn parse() {
+    let t = StyledText::from_markup("[fg=red,bold]Error:[/] file [u,bg=#102030]x[i]y[/][/]");
+    let spans = t.spans();
+    assert_eq!(spans.len(), 4);
+    assert_eq!(spans[0].0, "Error:");
+    assert_eq!(spans[0].1, TextAttrs::default().with_fg(rterm::COLOR_RED).with_style(rterm::STYLE_BOLD));
+    assert_eq!(spans[1], (" file ".to_string(), TextAttrs::default()));
+    let ul = TextAttrs::default().with_bg(rterm::rgb(16, 32, 48)).with_style(rterm::STYLE_UNDERLINE);
+    assert_eq!(spans[2], ("x".to_string(), ul));
+    assert_eq!(spans[3], ("y".to_string(), ul.with_style(rterm::STYLE_ITALIC)));
+
+    // escaping and invalid tags are kept as text, unbalanced closing tags
+    // are dropped
+    let t = StyledText::from_markup("[[b] [x] [/] [fg=nope]a[b");
+    assert_eq!(t.spans().len(), 1);
+    assert_eq!(t.plain_text(), "[b] [x]  [fg=nope]a[b");
+    assert_eq!(rterm::escape_markup("[b]"), "[[b]");
+    assert_eq!(StyledText::from_markup(&rterm::escape_markup("[b]x")).plain_text(), "[b]x");
+
+    // a nested tag removes a style of the outer one
+    let t = StyledText::from_markup("[b,u]a[-b,curly-underline]b");
+    let inner = t.spans()[1].1;
+    let (_, _, st) = inner.apply(rterm::COLOR_DEFAULT, rterm::COLOR_DEFAULT, rterm::Style::default());
+    assert_eq!(st.attrs, rterm::STYLE_CURLY_UNDERLINE);
+}
+
+/* Styled text is printed with the current attributes changed by spans */
+#[test]
+fn put_styled() {
+    let mut cb = CellBuf::new(8, 2);
+    {
+        let mut cv = Canvas::new(&mut cb);
+        cv.fill('.');
+        cv.set_background(rterm::COLOR_BLUE);
+        let t = StyledText::new().text("a").span("漢b", TextAttrs::default().with_fg(rterm::COLOR_RED)).text("c");
+        assert_eq!(cv.put_styled(1, 0, &t), 5);
+        assert_eq!(cv.get_foreground(), rterm::COLOR_DEFAULT);
+
+        let mut r = cv.region(&CellRect::with_size(2, 1, 4, 1));
+        assert_eq!(r.put_markup(-1, 0, "x[b]yz[[[/]w漢"), 7);
+    }
+    assert_eq!(rows(&cb), vec![".a漢\0bc..", "..yz[w.."]);
+    let c = cb.get_cell(2, 0).unwrap();
+    assert_eq!((c.fg, c.bg), (rterm::COLOR_RED, rterm::COLOR_BLUE));
+    assert_eq!(cb.get_cell(5, 0).unwrap().fg, rterm::COLOR_DEFAULT);
+    assert_eq!(cb.get_cell(3, 1).unwrap().style.attrs, rterm::STYLE_BOLD);
+    assert_eq!(cb.get_cell(5, 1).unwrap().style.attrs, 0);
+}
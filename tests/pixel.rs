@@ -0,0 +1,79 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{CellBuf, CellRect, PixelCanvas};
+use common::rows;
+
+// Returns pixels as strings of '#' and '.'
+fn pixels(pc: &PixelCanvas) -> Vec<String> {
+    let (w, h) = pc.size();
+    (0..h).map(|y| (0..w).map(|x| if pc.get_pixel(x, y).is_some() { '#' } else { '.' }).collect()).collect()
+}
+
+/* Pixels are mapped to Braille dots and block elements */
+#[test]
+fn render() {
+    let mut pc = PixelCanvas::new(rterm::PIXEL_BRAILLE, 2, 1);
+    assert_eq!(pc.size(), (4, 4));
+    assert_eq!(pc.cell_size(), (2, 1));
+    pc.set_pixel(0, 0);
+    pc.set_pixel(1, 3);
+    pc.set_pixel(7, 7);
+    let mut cb = CellBuf::new(4, 2);
+    assert!(pc.render(&mut cb, &CellRect::with_size(1, 0, 5, 5)));
+    assert_eq!(rows(&cb), vec![" \u{2881}  ", "    "]);
+
+    let mut pc = PixelCanvas::new(rterm::PIXEL_QUADRANT, 2, 1);
+    pc.set_color(rterm::COLOR_RED);
+    pc.fill_rect(&CellRect::with_size(0, 0, 3, 1));
+    pc.set_pixel(3, 1);
+    pc.set_color(rterm::COLOR_BLUE);
+    pc.set_pixel(2, 1);
+    pc.set_pixel(3, 0);
+    pc.clear_pixel(1, 0);
+    assert!(pc.render(&mut cb, &CellRect::with_size(0, 1, 4, 1)));
+    assert_eq!(rows(&cb)[1], "▘▚  ");
+    let c = cb.get_cell(1, 1).unwrap();
+    assert_eq!((c.fg, c.bg), (rterm::COLOR_RED, rterm::COLOR_BLUE));
+
+    let mut pc = PixelCanvas::new(rterm::PIXEL_HALF_BLOCK, 3, 1);
+    pc.set_background(rterm::COLOR_GREEN);
+    pc.set_pixel(0, 1);
+    pc.draw_line(1, 0, 1, 1);
+    pc.set_color(rterm::COLOR_YELLOW);
+    pc.set_pixel(2, 0);
+    assert!(pc.render(&mut cb, &CellRect::with_size(0, 0, 3, 1)));
+    assert_eq!(rows(&cb)[0], "▄█▀ ");
+    let c = cb.get_cell(2, 0).unwrap();
+    assert_eq!((c.fg, c.bg), (rterm::COLOR_YELLOW, rterm::COLOR_GREEN));
+    assert!(!pc.render(&mut cb, &CellRect::with_size(4, 0, 3, 1)));
+}
+
+/* Lines, rectangles, and circles */
+#[test]
+fn shapes() {
+    let mut pc = PixelCanvas::new(rterm::PIXEL_HALF_BLOCK, 7, 3);
+    pc.draw_line(0, 0, 6, 2);
+    pc.draw_rect(&CellRect::with_size(4, 3, 3, 3));
+    assert_eq!(pixels(&pc), vec!["##.....", "..###..", ".....##", "....###", "....#.#", "....###"]);
+
+    pc.clear();
+    pc.draw_circle(3, 3, 2);
+    assert_eq!(pixels(&pc), vec![".......", "..###..", ".#...#.", ".#...#.", ".#...#.", "..###.."]);
+    pc.clear();
+    pc.fill_circle(3, 3, 2);
+    assert_eq!(pixels(&pc), vec![".......", "..###..", ".#####.", ".#####.", ".#####.", "..###.."]);
+}
+
+/* Polygons are filled by the even-odd rule */
+#[test]
+fn polygons() {
+    let mut pc = PixelCanvas::new(rterm::PIXEL_BRAILLE, 4, 2);
+    pc.fill_polygon(&[(0, 0), (6, 0), (0, 6)]);
+    assert_eq!(&pixels(&pc)[..7], &["#######.", "######..", "#####...", "####....", "###.....", "##......", "........"]);
+
+    pc.clear();
+    pc.draw_polygon(&[(1, 1), (5, 1), (5, 4)]);
+    assert_eq!(&pixels(&pc)[..5], &["........", ".#####..", "..##.#..", "....##..", ".....#.."]);
+}
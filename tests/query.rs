@@ -0,0 +1,136 @@
+extern crate rterm;
+
+use rterm::{ColorQuery, Event, ReplyParser};
+
+fn keys(s: &str) -> Vec<Event> {
+    s.chars().map(|c| {
+        if c == '\x1b' {
+            Event::Key(rterm::KEY_ESC, c, 0)
+        } else {
+            Event::Key(0, c, 0)
+        }
+    }).collect()
+}
+
+fn feed_all(p: &mut ReplyParser, evs: Vec<Event>) -> String {
+    let mut s = String::new();
+    for ev in evs {
+        for out in p.feed(ev) {
+            if let Event::Key(_, c, _) = out {
+                s.push(c);
+            }
+        }
+    }
+    s
+}
+
+/* Query sequences and color specifications */
+#[test]
+fn sequences() {
+    assert_eq!(ColorQuery::Foreground.sequence(), "\x1b]10;?\x1b\\");
+    assert_eq!(ColorQuery::Background.sequence(), "\x1b]11;?\x1b\\");
+    assert_eq!(ColorQuery::Palette(12).sequence(), "\x1b]4;12;?\x1b\\");
+
+    assert_eq!(rterm::parse_color_spec("rgb:ffff/8080/0000"), Some((255, 128, 0)));
+    assert_eq!(rterm::parse_color_spec("rgb:f/8/0"), Some((255, 136, 0)));
+    assert_eq!(rterm::parse_color_spec("#102030"), Some((16, 32, 48)));
+    assert_eq!(rterm::parse_color_spec("rgb:ff/ff"), None);
+    assert_eq!(rterm::parse_color_spec("rgb:fffff/0/0"), None);
+    assert_eq!(rterm::parse_color_spec("white"), None);
+
+    assert!(rterm::ColorInfo::new(255, 255, 255).light);
+    assert!(!rterm::ColorInfo::new(0, 0, 40).light);
+    assert_eq!(rterm::ColorInfo::new(1, 2, 3).color, rterm::rgb(1, 2, 3));
+}
+
+/* Replies are removed from input, other key presses are kept */
+#[test]
+fn parse_replies() {
+    let mut p = ReplyParser::new();
+    let s = feed_all(&mut p, keys("a\x1b]11;rgb:0000/0000/0000\x1b\\b\x1b]4;1;rgb:cdcd/0000/0000\x07c"));
+    assert_eq!(s, "abc");
+    let replies = p.take_replies();
+    assert_eq!(replies.len(), 2);
+    assert_eq!(replies[0].0, ColorQuery::Background);
+    assert!(!replies[0].1.light);
+    assert_eq!(replies[1].0, ColorQuery::Palette(1));
+    assert_eq!(replies[1].1.color, rterm::rgb(205, 0, 0));
+    assert!(p.take_replies().is_empty());
+
+    // unknown OSC replies are swallowed as well
+    assert_eq!(feed_all(&mut p, keys("\x1b]52;c;?\x07x")), "x");
+    assert!(p.take_replies().is_empty());
+
+    // ESC key press followed by other keys is not a reply
+    assert_eq!(feed_all(&mut p, keys("\x1b\x1bq")), "\x1b\x1bq");
+    // a single ESC is held until flush
+    assert_eq!(feed_all(&mut p, keys("\x1b")), "");
+    assert!(p.is_holding());
+    assert_eq!(p.flush().len(), 1);
+    assert!(!p.is_holding());
+
+    // non-key events end an incomplete sequence
+    let mut evs = keys("\x1b]1");
+    evs.push(Event::Resize(10, 20));
+    let mut p = ReplyParser::new();
+    let mut out = Vec::new();
+    for ev in evs {
+        out.extend(p.feed(ev));
+    }
+    assert_eq!(out.len(), 4);
+    match out[3] {
+        Event::Resize(10, 20) => {},
+        _ => panic!("Resize expected"),
+    }
+}
+
+/* Mode state and device attributes replies */
+#[test]
+fn mode_replies() {
+    assert_eq!(rterm::mode_query_sequence(rterm::MODE_SYNC_OUTPUT), "\x1b[?2026$p");
+    assert!(rterm::ModeState::Reset.is_supported());
+    assert!(!rterm::ModeState::NotRecognized.is_supported());
+    assert!(!rterm::ModeState::PermanentlyReset.is_supported());
+
+    let mut p = ReplyParser::new();
+    let s = feed_all(&mut p, keys("a\x1b[?2026;2$yb\x1b[?62;22cc"));
+    assert_eq!(s, "abc");
+    assert_eq!(p.take_mode_replies(), vec![(2026, rterm::ModeState::Reset)]);
+    assert!(p.take_device_attributes());
+    assert!(!p.take_device_attributes());
+
+    // every reply is taken separately
+    assert_eq!(feed_all(&mut p, keys("\x1b[?62c\x1b[?62c")), "");
+    assert!(p.take_device_attributes());
+    assert!(p.take_device_attributes());
+    assert!(!p.take_device_attributes());
+
+    // unknown state code
+    assert_eq!(feed_all(&mut p, keys("\x1b[?2026;7$y")), "");
+    assert!(p.take_mode_replies().is_empty());
+
+    // not a reply
+    assert_eq!(feed_all(&mut p, keys("\x1b[A\x1b[?1x")), "\x1b[A\x1b[?1x");
+    assert_eq!(feed_all(&mut p, keys("\x1b[?$y")), "\x1b[?$y");
+}
+
+/* Kitty graphics replies, device attribute parameters, and cell size */
+#[test]
+fn graphics_replies() {
+    let mut p = ReplyParser::new();
+    let s = feed_all(&mut p, keys("a\x1b_Gi=31;OK\x1b\\b\x1b_Gi=5,p=2;ENOENT:no image\x1b\\\x1b[?62;4;22c"));
+    assert_eq!(s, "ab");
+    assert_eq!(p.take_graphics_replies(), vec![(31, "OK".to_string()), (5, "ENOENT:no image".to_string())]);
+    assert!(p.take_graphics_replies().is_empty());
+    assert!(p.take_device_attributes());
+    assert_eq!(p.device_attribute_params(), &[62, 4, 22]);
+
+    // APC that is not a graphics reply
+    assert_eq!(feed_all(&mut p, keys("\x1b_Xi=1;OK\x1b\\")), "");
+    assert!(p.take_graphics_replies().is_empty());
+
+    // other window reports are swallowed, key sequences are kept
+    assert_eq!(feed_all(&mut p, keys("\x1b[6;20;10t\x1b[4;600;800ta\x1b[1;5A")), "a\x1b[1;5A");
+    assert_eq!(p.take_cell_size(), Some((10, 20)));
+    assert_eq!(p.take_cell_size(), None);
+}
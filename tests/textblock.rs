@@ -0,0 +1,62 @@
+extern crate rterm;
+
+mod common;
+
+use rterm::{Canvas, CellBuf, CellRect, TextOptions};
+use common::rows;
+
+fn block(w: i32, h: i32, text: &str, opts: &TextOptions) -> (i32, Vec<String>) {
+    let mut cb = CellBuf::new(w + 2, h + 2);
+    let n = {
+        let mut cv = Canvas::new(&mut cb);
+        cv.fill('.');
+        cv.put_text_block(&CellRect::with_size(1, 1, w, h), text, opts)
+    };
+    (n, rows(&cb).into_iter().skip(1).take(h as usize).map(|r| r[1..r.len() - 1].to_string()).collect())
+}
+
+/* Paragraphs are wrapped at spaces, long words are broken */
+#[test]
+fn wrap() {
+    let opts = TextOptions::default();
+    let (n, r) = block(8, 6, "the quick brown\nfox  jumps\n\nabcdefghijk", &opts);
+    assert_eq!(n, 8);
+    assert_eq!(r, vec!["the.....", "quick...", "brown...", "fox.....", "jumps...", "........"]);
+    let (n, r) = block(6, 3, "abcdefghijk 漢字漢", &opts);
+    assert_eq!(n, 3);
+    assert_eq!(r, vec!["abcdef", "ghijk.", "漢\0字\0漢\0"]);
+
+    let mut cb = CellBuf::new(10, 1);
+    let cv = Canvas::new(&mut cb);
+    assert_eq!(cv.layout_text_block(6, "a\tb  c d\r\n  x", &opts), vec!["a", "b  c d", "  x"]);
+    assert_eq!(cv.layout_text_block(6, "ab\tc", &TextOptions{tab_width: 4, ..opts}), vec!["ab  c"]);
+}
+
+/* Lines are aligned horizontally and vertically */
+#[test]
+fn align() {
+    let text = "aa bb cc d\nee f";
+    let opts = TextOptions{align: rterm::ALIGN_RIGHT, valign: rterm::VALIGN_BOTTOM, ..TextOptions::default()};
+    assert_eq!(block(7, 4, text, &opts).1, vec![".......", "..aa bb", "...cc d", "...ee f"]);
+    let opts = TextOptions{align: rterm::ALIGN_CENTER, valign: rterm::VALIGN_MIDDLE, ..opts};
+    assert_eq!(block(6, 4, "ab\nc", &opts).1, vec!["......", "..ab..", "..c...", "......"]);
+    let opts = TextOptions{align: rterm::ALIGN_JUSTIFY, ..TextOptions::default()};
+    assert_eq!(block(10, 2, "aa b cc dddd e", &opts).1, vec!["aa   b  cc", "dddd e...."]);
+}
+
+/* Text that does not fit is cut off and marked with an ellipsis */
+#[test]
+fn overflow() {
+    let opts = TextOptions{ellipsis: true, ..TextOptions::default()};
+    let (n, r) = block(5, 2, "one two three", &opts);
+    assert_eq!(n, 3);
+    assert_eq!(r, vec!["one..", "two…."]);
+    assert_eq!(block(5, 2, "one\nthreefold", &opts).1, vec!["one..", "thre…"]);
+
+    let opts = TextOptions{wrap: false, ..opts};
+    let (n, r) = block(5, 2, "abcdefg\nab", &opts);
+    assert_eq!(n, 2);
+    assert_eq!(r, vec!["abcd…", "ab..."]);
+    let opts = TextOptions{ellipsis: false, ..opts};
+    assert_eq!(block(5, 1, "abcd漢", &opts).1, vec!["abcd."]);
+}
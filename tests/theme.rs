@@ -0,0 +1,90 @@
+extern crate rterm;
+
+use std::io::Write;
+
+const THEME: &str = "
+# comment
+border    = fg:white bg:blue
+statusbar = inherit:border fg:yellow bold curly-underline ul:#102030
+
+selection = bg:cyan reverse
+";
+
+/* Loads styles with inheritance */
+#[test]
+fn load() {
+    let mut th = rterm::Theme::new();
+    th.load_str(THEME).unwrap();
+
+    let border = th.get("border").unwrap();
+    assert_eq!(border.fg, rterm::COLOR_WHITE);
+    assert_eq!(border.bg, rterm::COLOR_BLUE);
+    assert_eq!(border.style, rterm::Style::default());
+
+    let sb = th.get("statusbar").unwrap();
+    assert_eq!(sb.fg, rterm::COLOR_YELLOW);
+    assert_eq!(sb.bg, rterm::COLOR_BLUE);
+    assert_eq!(sb.style.attrs, rterm::STYLE_BOLD | rterm::STYLE_CURLY_UNDERLINE);
+    assert_eq!(sb.style.underline_color, rterm::rgb(0x10, 0x20, 0x30));
+
+    let id = th.id("selection").unwrap();
+    assert_eq!(th.get(id), th.get("selection"));
+    assert!(th.get("error").is_none());
+}
+
+/* A theme loaded over another one keeps handles and unchanged styles */
+#[test]
+fn overrides() {
+    let mut th = rterm::Theme::new();
+    th.load_str(THEME).unwrap();
+    let id = th.id("statusbar").unwrap();
+
+    th.load_str("statusbar = inherit:statusbar -bold -underline fg:red\nerror = fg:red").unwrap();
+    let sb = th.get(id).unwrap();
+    assert_eq!(sb.fg, rterm::COLOR_RED);
+    assert_eq!(sb.bg, rterm::COLOR_BLUE);
+    assert_eq!(sb.style.attrs, 0);
+    assert_eq!(th.get("border").unwrap().bg, rterm::COLOR_BLUE);
+    assert!(th.get("error").is_some());
+
+    // children follow a redefined parent
+    th.load_str("border = fg:black bg:green").unwrap();
+    let sb = th.get(id).unwrap();
+    assert_eq!((sb.fg, sb.bg), (rterm::COLOR_RED, rterm::COLOR_GREEN));
+    th.set("border", rterm::ThemeStyle{fg: rterm::COLOR_WHITE, bg: rterm::COLOR_MAGENTA, style: rterm::Style::default()});
+    assert_eq!(th.get(id).unwrap().bg, rterm::COLOR_MAGENTA);
+}
+
+/* Invalid lines are reported and nothing changes */
+#[test]
+fn errors() {
+    let mut th = rterm::Theme::new();
+    th.load_str(THEME).unwrap();
+
+    assert!(th.load_str("a = fg:red\nborder fg:red").is_err());
+    assert!(th.get("a").is_none());
+    assert!(th.load_str("a = fg:nocolor").is_err());
+    assert!(th.load_str("a = inherit:unknown").is_err());
+    assert!(th.load_str("a = sparkling").is_err());
+    assert!(th.load_str("a = size:10").is_err());
+    assert!(th.load_str("a b = bold").is_err());
+    // inheritance loop
+    assert!(th.load_str("border = inherit:statusbar").is_err());
+    assert_eq!(th.get("border").unwrap().bg, rterm::COLOR_BLUE);
+}
+
+#[test]
+fn from_file() {
+    // parallel test runs must not share the file
+    let name = format!("rterm-test-{}-{:?}.theme", std::process::id(), std::thread::current().id());
+    let path = std::env::temp_dir().join(name);
+    {
+        let mut f = std::fs::File::create(&path).unwrap();
+        f.write_all(THEME.as_bytes()).unwrap();
+    }
+    let th = rterm::Theme::from_file(path.to_str().unwrap()).unwrap();
+    assert_eq!(th.get("border").unwrap().bg, rterm::COLOR_BLUE);
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(rterm::Theme::from_file(path.to_str().unwrap()).is_err());
+}